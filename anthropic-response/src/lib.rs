@@ -4,6 +4,7 @@ pub mod content_block;
 pub mod content_block_delta;
 pub mod event;
 pub mod message;
+pub mod model;
 mod stop_reason;
 mod stream;
 
@@ -11,6 +12,7 @@ pub use content_block::*;
 pub use content_block_delta::*;
 pub use event::*;
 pub use message::*;
+pub use model::*;
 pub use stream::*;
 
 #[derive(Debug, Deserialize, Serialize)]
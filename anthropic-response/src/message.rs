@@ -1,7 +1,12 @@
 use aws_sdk_bedrockruntime::types::{ContentBlock as BedrockContentBlock, StopReason, TokenUsage};
 use serde::{Deserialize, Serialize};
 
-use crate::{bedrock_content_blocks_to_json, event::ContentBlock, stop_reason::get_stop_sequence};
+use crate::{
+    bedrock_content_blocks_to_json,
+    content_block_delta::ContentBlockDelta,
+    event::{ContentBlock, Event, MessageDeltaContent, UsageDelta},
+    stop_reason::get_stop_sequence,
+};
 
 pub fn converse_output_to_message(
     id: String,
@@ -32,6 +37,182 @@ pub fn converse_output_to_message(
         .build())
 }
 
+/// The SSE events a stream producing `message` would have sent, for
+/// serving a complete message to a streaming client. As in a live stream,
+/// `message_start` carries no content and `message_delta` the usage.
+pub fn message_to_events(
+    message: Message,
+) -> Result<Vec<(&'static str, Event)>, serde_json::Error> {
+    let mut events = vec![(
+        "message_start",
+        Event::message_start_builder()
+            .message(
+                Message::builder()
+                    .id(message.id)
+                    .model(message.model)
+                    .role(message.role)
+                    .message_type(message.message_type)
+                    .build(),
+            )
+            .build(),
+    )];
+    for (index, block) in message.content.into_iter().enumerate() {
+        let index = index as i32;
+        let (start, deltas) = match serde_json::from_value(block)? {
+            ContentBlock::Text { text } => (
+                ContentBlock::text_builder().build(),
+                vec![ContentBlockDelta::TextDelta { text }],
+            ),
+            ContentBlock::Thinking {
+                signature,
+                thinking,
+            } => (
+                ContentBlock::thinking_builder().build(),
+                vec![
+                    ContentBlockDelta::ThinkingDelta { thinking },
+                    ContentBlockDelta::SignatureDelta { signature },
+                ],
+            ),
+            ContentBlock::ToolUse { id, input, name } => (
+                ContentBlock::tool_use_builder().id(id).name(name).build(),
+                vec![ContentBlockDelta::InputJsonDelta {
+                    partial_json: input.to_string(),
+                }],
+            ),
+            ContentBlock::ServerToolUse { id, input, name } => (
+                ContentBlock::ServerToolUse {
+                    id,
+                    input: serde_json::json!({}),
+                    name,
+                },
+                vec![ContentBlockDelta::InputJsonDelta {
+                    partial_json: input.to_string(),
+                }],
+            ),
+            block @ ContentBlock::RedactedThinking { .. } => (block, vec![]),
+        };
+        events.push((
+            "content_block_start",
+            Event::content_block_start_builder()
+                .content_block(start)
+                .index(index)
+                .build(),
+        ));
+        for delta in deltas {
+            events.push((
+                "content_block_delta",
+                Event::content_block_delta_builder()
+                    .delta(delta)
+                    .index(index)
+                    .build(),
+            ));
+        }
+        events.push((
+            "content_block_stop",
+            Event::content_block_stop_builder().index(index).build(),
+        ));
+    }
+    events.push((
+        "message_delta",
+        Event::message_delta_builder()
+            .delta(MessageDeltaContent {
+                stop_reason: message.stop_reason,
+                stop_sequence: message.stop_sequence,
+            })
+            .usage(
+                UsageDelta::builder()
+                    .input_tokens(message.usage.input_tokens)
+                    .output_tokens(message.usage.output_tokens)
+                    .cache_creation_input_tokens(message.usage.cache_creation_input_tokens)
+                    .cache_read_input_tokens(message.usage.cache_read_input_tokens)
+                    .build(),
+            )
+            .build(),
+    ));
+    events.push(("message_stop", Event::message_stop()));
+    Ok(events)
+}
+
+/// Rebuilds the [`Message`] a stream of events describes, the inverse of
+/// [`message_to_events`], so that a streamed response can be kept like a
+/// non-streaming one.
+#[derive(Debug, Default)]
+pub struct MessageCollector {
+    message: Message,
+    /// The `input_json_delta`s of each content block so far.
+    partial_json: Vec<String>,
+    stopped: bool,
+}
+
+impl MessageCollector {
+    pub fn push(&mut self, event: &Event) -> Result<(), serde_json::Error> {
+        match event {
+            Event::MessageStart { message } => {
+                self.message = Message::builder()
+                    .id(message.id.clone())
+                    .model(message.model.clone())
+                    .role(message.role.clone())
+                    .message_type(message.message_type.clone())
+                    .build();
+            }
+            Event::ContentBlockStart { content_block, .. } => {
+                self.message
+                    .content
+                    .push(serde_json::to_value(content_block)?);
+                self.partial_json.push(String::new());
+            }
+            Event::ContentBlockDelta { delta, index } => {
+                let index = *index as usize;
+                let (Some(block), Some(partial_json)) = (
+                    self.message.content.get_mut(index),
+                    self.partial_json.get_mut(index),
+                ) else {
+                    return Ok(());
+                };
+                let (field, text) = match delta {
+                    ContentBlockDelta::TextDelta { text } => ("text", text),
+                    ContentBlockDelta::ThinkingDelta { thinking } => ("thinking", thinking),
+                    ContentBlockDelta::SignatureDelta { signature } => ("signature", signature),
+                    ContentBlockDelta::InputJsonDelta { partial_json: json } => {
+                        partial_json.push_str(json);
+                        return Ok(());
+                    }
+                };
+                if let Some(serde_json::Value::String(value)) = block.get_mut(field) {
+                    value.push_str(text);
+                }
+            }
+            Event::ContentBlockStop { index } => {
+                let index = *index as usize;
+                if let (Some(block), Some(partial_json)) = (
+                    self.message.content.get_mut(index),
+                    self.partial_json.get(index),
+                ) && !partial_json.is_empty()
+                {
+                    block["input"] = serde_json::from_str(partial_json)?;
+                }
+            }
+            Event::MessageDelta { delta, usage } => {
+                self.message.stop_reason = delta.stop_reason.clone();
+                self.message.stop_sequence = delta.stop_sequence.clone();
+                self.message.usage = Usage::builder()
+                    .input_tokens(usage.input_tokens)
+                    .output_tokens(usage.output_tokens)
+                    .cache_creation_input_tokens(usage.cache_creation_input_tokens)
+                    .cache_read_input_tokens(usage.cache_read_input_tokens)
+                    .build();
+            }
+            Event::MessageStop => self.stopped = true,
+        }
+        Ok(())
+    }
+
+    /// The message, once `message_stop` has been seen.
+    pub fn finish(self) -> Option<Message> {
+        self.stopped.then_some(self.message)
+    }
+}
+
 fn usage_from_token_usage(usage: Option<&TokenUsage>) -> Usage {
     Usage::builder()
         .input_tokens(usage.map_or(0, |u| u.input_tokens))
@@ -328,4 +509,86 @@ mod tests {
         assert_eq!(message.stop_reason.as_deref(), Some("stop_sequence"));
         assert!(message.stop_sequence.is_none());
     }
+
+    #[test]
+    fn message_replays_as_a_stream() {
+        let message = Message::builder()
+            .id("msg_1".to_string())
+            .model("claude".to_string())
+            .role("assistant".to_string())
+            .message_type("message".to_string())
+            .content(vec![
+                serde_json::json!({"type": "thinking", "thinking": "pondering", "signature": "sig"}),
+                serde_json::json!({"type": "tool_use", "id": "tu_1", "name": "get_weather", "input": {"location": "Paris"}}),
+            ])
+            .stop_reason(Some("tool_use".to_string()))
+            .usage(Usage::builder().input_tokens(10).output_tokens(20).build())
+            .build();
+
+        let events: Vec<_> = message_to_events(message)
+            .unwrap()
+            .into_iter()
+            .map(|(name, event)| (name, serde_json::to_value(event).unwrap()))
+            .collect();
+        let names: Vec<_> = events.iter().map(|(name, _)| *name).collect();
+        assert_eq!(
+            names,
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        assert_eq!(events[0].1["message"]["id"], "msg_1");
+        assert_eq!(events[0].1["message"]["content"], serde_json::json!([]));
+        assert_eq!(events[1].1["content_block"]["thinking"], "");
+        assert_eq!(events[2].1["delta"]["thinking"], "pondering");
+        assert_eq!(events[3].1["delta"]["signature"], "sig");
+        assert_eq!(events[5].1["index"], 1);
+        assert_eq!(events[5].1["content_block"]["input"], serde_json::json!({}));
+        assert_eq!(
+            events[6].1["delta"]["partial_json"],
+            r#"{"location":"Paris"}"#
+        );
+        assert_eq!(events[8].1["delta"]["stop_reason"], "tool_use");
+        assert_eq!(events[8].1["usage"]["output_tokens"], 20);
+    }
+
+    #[test]
+    fn collected_stream_rebuilds_the_message() {
+        let message = Message::builder()
+            .id("msg_1".to_string())
+            .model("claude".to_string())
+            .role("assistant".to_string())
+            .message_type("message".to_string())
+            .content(vec![
+                serde_json::json!({"type": "thinking", "thinking": "pondering", "signature": "sig"}),
+                serde_json::json!({"type": "text", "text": "Checking."}),
+                serde_json::json!({"type": "tool_use", "id": "tu_1", "name": "get_weather", "input": {"location": "Paris"}}),
+            ])
+            .stop_reason(Some("tool_use".to_string()))
+            .usage(Usage::builder().input_tokens(10).output_tokens(20).build())
+            .build();
+        let expected = serde_json::to_value(&message).unwrap();
+
+        let events = message_to_events(message).unwrap();
+        let collect = |events: &[(&str, Event)]| {
+            let mut collector = MessageCollector::default();
+            for (_, event) in events {
+                collector.push(event).unwrap();
+            }
+            collector
+        };
+        assert!(collect(&events[..events.len() - 1]).finish().is_none());
+        let collector = collect(&events);
+        let collected = collector.finish().unwrap();
+        assert_eq!(serde_json::to_value(collected).unwrap(), expected);
+    }
 }
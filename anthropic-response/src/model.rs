@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+/// A model entry as returned by `GET /v1/models`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ModelInfo {
+    /// RFC 3339 timestamp of the model's release.
+    pub created_at: String,
+    pub display_name: String,
+    pub id: String,
+    #[serde(rename = "type")]
+    pub model_type: String,
+}
+
+/// One page of `GET /v1/models`. `first_id`/`last_id` are the cursors for the
+/// `before_id`/`after_id` query parameters.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ModelList {
+    pub data: Vec<ModelInfo>,
+    pub first_id: Option<String>,
+    pub has_more: bool,
+    pub last_id: Option<String>,
+}
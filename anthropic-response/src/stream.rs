@@ -1,7 +1,6 @@
 use aws_sdk_bedrockruntime::types::{
-    ContentBlockStart as BedrockContentBlockStart, ConverseStreamOutput, TokenUsage,
+    ContentBlockStart as BedrockContentBlockStart, ConverseStreamOutput,
 };
-use std::sync::Arc;
 
 use crate::{
     content_block_delta::ContentBlockDelta,
@@ -31,7 +30,6 @@ pub struct EventConverter {
     pending_content_block_stop: Option<i32>,
     started: bool,
     terminated: bool,
-    usage_callback: Arc<dyn Fn(&TokenUsage) + Send + Sync>,
 }
 
 impl EventConverter {
@@ -39,7 +37,6 @@ impl EventConverter {
         message_id: String,
         model: String,
         request_stop_sequences: Option<Vec<String>>,
-        usage_callback: Arc<dyn Fn(&TokenUsage) + Send + Sync>,
     ) -> Self {
         Self {
             message_id,
@@ -51,7 +48,6 @@ impl EventConverter {
             pending_content_block_stop: None,
             started: false,
             terminated: false,
-            usage_callback,
         }
     }
 
@@ -227,9 +223,6 @@ impl EventConverter {
                 }
             }
             ConverseStreamOutput::Metadata(event) => {
-                if let Some(ref usage) = event.usage {
-                    (self.usage_callback)(usage);
-                }
                 self.terminated = true;
 
                 let mut events = self.flush_pending_content_block_stop();
@@ -270,9 +263,9 @@ impl EventConverter {
     /// no-op once the converter has already emitted the terminator via the
     /// `Metadata` arm, or if `MessageStart` was never seen.
     ///
-    /// Usage is reported as zero — the upstream `usage_callback` never fires
-    /// when `Metadata` is missing, but the client at least gets a well-formed
-    /// stream end with the `stop_reason` recorded from `MessageStop`.
+    /// Usage is reported as zero since Bedrock never sent it, but the client
+    /// at least gets a well-formed stream end with the `stop_reason` recorded
+    /// from `MessageStop`.
     pub fn finalize(&mut self) -> Option<Vec<(&'static str, Event)>> {
         if !self.started || self.terminated {
             return None;
@@ -304,12 +297,7 @@ mod tests {
     };
 
     fn converter() -> EventConverter {
-        EventConverter::new(
-            "msg_test".to_string(),
-            "model_test".to_string(),
-            None,
-            Arc::new(|_| {}),
-        )
+        EventConverter::new("msg_test".to_string(), "model_test".to_string(), None)
     }
 
     fn converter_with_stop_sequences(stop_sequences: Vec<String>) -> EventConverter {
@@ -317,7 +305,6 @@ mod tests {
             "msg_test".to_string(),
             "model_test".to_string(),
             Some(stop_sequences),
-            Arc::new(|_| {}),
         )
     }
 
@@ -5,6 +5,7 @@ use aws_sdk_bedrockruntime::types::{
 pub mod anthropic;
 pub mod openai;
 
+#[derive(Clone)]
 pub struct BedrockChatCompletion {
     pub model_id: String,
     pub messages: Option<Vec<Message>>,
@@ -0,0 +1,234 @@
+use aws_sdk_bedrockruntime::{
+    error::{ProvideErrorMetadata, SdkError},
+    operation::{
+        converse::ConverseError, converse_stream::ConverseStreamError,
+        count_tokens::CountTokensError,
+    },
+    types::error::ConverseStreamOutputError,
+};
+use axum::http::StatusCode;
+
+/// Error categories shared by the Anthropic and OpenAI front-ends. Each kind
+/// renders as the protocol-native error `type` so client SDKs apply their usual
+/// retry and error-handling logic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    InvalidRequest,
+    Authentication,
+    Permission,
+    NotFound,
+    RateLimit,
+    Api,
+    Overloaded,
+    Timeout,
+}
+
+impl ErrorKind {
+    pub fn anthropic_type(self) -> &'static str {
+        match self {
+            ErrorKind::InvalidRequest => "invalid_request_error",
+            ErrorKind::Authentication => "authentication_error",
+            ErrorKind::Permission => "permission_error",
+            ErrorKind::NotFound => "not_found_error",
+            ErrorKind::RateLimit => "rate_limit_error",
+            ErrorKind::Api => "api_error",
+            ErrorKind::Overloaded => "overloaded_error",
+            ErrorKind::Timeout => "timeout_error",
+        }
+    }
+
+    pub fn openai_type(self) -> &'static str {
+        match self {
+            ErrorKind::InvalidRequest | ErrorKind::NotFound => "invalid_request_error",
+            ErrorKind::Authentication => "authentication_error",
+            ErrorKind::Permission => "permission_error",
+            ErrorKind::RateLimit => "rate_limit_error",
+            ErrorKind::Api | ErrorKind::Overloaded | ErrorKind::Timeout => "server_error",
+        }
+    }
+
+    pub fn openai_code(self) -> Option<&'static str> {
+        match self {
+            ErrorKind::InvalidRequest | ErrorKind::Api => None,
+            ErrorKind::Authentication => Some("invalid_api_key"),
+            ErrorKind::Permission => Some("permission_denied"),
+            ErrorKind::NotFound => Some("model_not_found"),
+            ErrorKind::RateLimit => Some("rate_limit_exceeded"),
+            ErrorKind::Overloaded => Some("overloaded"),
+            ErrorKind::Timeout => Some("timeout"),
+        }
+    }
+
+    /// Best-effort kind for an error that carries only an HTTP status, e.g. a
+    /// Bedrock error code the SDK does not model.
+    pub fn from_status_code(status: StatusCode) -> Self {
+        match status {
+            StatusCode::BAD_REQUEST | StatusCode::PAYLOAD_TOO_LARGE => ErrorKind::InvalidRequest,
+            StatusCode::UNAUTHORIZED => ErrorKind::Authentication,
+            StatusCode::FORBIDDEN => ErrorKind::Permission,
+            StatusCode::NOT_FOUND => ErrorKind::NotFound,
+            StatusCode::TOO_MANY_REQUESTS => ErrorKind::RateLimit,
+            StatusCode::SERVICE_UNAVAILABLE => ErrorKind::Overloaded,
+            StatusCode::GATEWAY_TIMEOUT | StatusCode::REQUEST_TIMEOUT => ErrorKind::Timeout,
+            _ => ErrorKind::Api,
+        }
+    }
+
+    /// The HTTP status used when there is no upstream status to preserve.
+    pub fn status_code(self) -> StatusCode {
+        match self {
+            ErrorKind::InvalidRequest => StatusCode::BAD_REQUEST,
+            ErrorKind::Authentication => StatusCode::UNAUTHORIZED,
+            ErrorKind::Permission => StatusCode::FORBIDDEN,
+            ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            ErrorKind::RateLimit => StatusCode::TOO_MANY_REQUESTS,
+            ErrorKind::Api => StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorKind::Overloaded => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorKind::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+}
+
+/// A Bedrock operation error that can be mapped onto an [`ErrorKind`].
+/// The default arms fall back to `Api` so newly-introduced Bedrock variants
+/// never panic.
+pub trait BedrockError: ProvideErrorMetadata {
+    fn kind(&self) -> ErrorKind;
+}
+
+impl BedrockError for ConverseError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            ConverseError::ValidationException(_) => ErrorKind::InvalidRequest,
+            ConverseError::ThrottlingException(_) => ErrorKind::RateLimit,
+            ConverseError::AccessDeniedException(_) => ErrorKind::Permission,
+            ConverseError::ResourceNotFoundException(_) => ErrorKind::NotFound,
+            ConverseError::ServiceUnavailableException(_)
+            | ConverseError::ModelNotReadyException(_) => ErrorKind::Overloaded,
+            ConverseError::ModelTimeoutException(_) => ErrorKind::Timeout,
+            _ => ErrorKind::Api,
+        }
+    }
+}
+
+impl BedrockError for ConverseStreamError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            ConverseStreamError::ValidationException(_) => ErrorKind::InvalidRequest,
+            ConverseStreamError::ThrottlingException(_) => ErrorKind::RateLimit,
+            ConverseStreamError::AccessDeniedException(_) => ErrorKind::Permission,
+            ConverseStreamError::ResourceNotFoundException(_) => ErrorKind::NotFound,
+            ConverseStreamError::ServiceUnavailableException(_)
+            | ConverseStreamError::ModelNotReadyException(_) => ErrorKind::Overloaded,
+            ConverseStreamError::ModelTimeoutException(_) => ErrorKind::Timeout,
+            _ => ErrorKind::Api,
+        }
+    }
+}
+
+impl BedrockError for CountTokensError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            CountTokensError::ValidationException(_) => ErrorKind::InvalidRequest,
+            CountTokensError::ThrottlingException(_) => ErrorKind::RateLimit,
+            CountTokensError::AccessDeniedException(_) => ErrorKind::Permission,
+            CountTokensError::ResourceNotFoundException(_) => ErrorKind::NotFound,
+            CountTokensError::ServiceUnavailableException(_) => ErrorKind::Overloaded,
+            _ => ErrorKind::Api,
+        }
+    }
+}
+
+impl BedrockError for ConverseStreamOutputError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            ConverseStreamOutputError::ValidationException(_) => ErrorKind::InvalidRequest,
+            ConverseStreamOutputError::ThrottlingException(_) => ErrorKind::RateLimit,
+            ConverseStreamOutputError::ServiceUnavailableException(_) => ErrorKind::Overloaded,
+            _ => ErrorKind::Api,
+        }
+    }
+}
+
+/// Classifies an SDK error and extracts the human-readable upstream message.
+/// Errors that never reached Bedrock (dispatch, construction) are `Api`
+/// errors, except client-side timeouts.
+pub fn classify_sdk_error<E, R>(err: &SdkError<E, R>) -> (ErrorKind, String)
+where
+    E: BedrockError + std::error::Error + 'static,
+    R: std::fmt::Debug,
+{
+    let message = err
+        .as_service_error()
+        .and_then(|e| e.meta().message())
+        .map(String::from)
+        .unwrap_or_else(|| err.to_string());
+
+    let kind = match (err.as_service_error(), err) {
+        (Some(service_error), _) => service_error.kind(),
+        (None, SdkError::TimeoutError(_)) => ErrorKind::Timeout,
+        (None, _) => ErrorKind::Api,
+    };
+    (kind, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_bedrockruntime::types::error::{
+        AccessDeniedException, ThrottlingException, ValidationException,
+    };
+    use aws_smithy_runtime_api::http::{Response, StatusCode as SmithyStatusCode};
+    use aws_smithy_types::{body::SdkBody, error::ErrorMetadata};
+
+    fn raw(status: u16) -> Response {
+        Response::new(
+            SmithyStatusCode::try_from(status).unwrap(),
+            SdkBody::from("error"),
+        )
+    }
+
+    #[test]
+    fn converse_stream_throttling_is_rate_limit() {
+        let err: SdkError<ConverseStreamError> = SdkError::service_error(
+            ConverseStreamError::ThrottlingException(
+                ThrottlingException::builder()
+                    .meta(ErrorMetadata::builder().message("slow down").build())
+                    .build(),
+            ),
+            raw(429),
+        );
+        let (kind, message) = classify_sdk_error(&err);
+        assert_eq!(kind, ErrorKind::RateLimit);
+        assert_eq!(kind.anthropic_type(), "rate_limit_error");
+        assert_eq!(kind.openai_code(), Some("rate_limit_exceeded"));
+        assert_eq!(message, "slow down");
+    }
+
+    #[test]
+    fn converse_validation_is_invalid_request() {
+        let err: SdkError<ConverseError> = SdkError::service_error(
+            ConverseError::ValidationException(ValidationException::builder().build()),
+            raw(400),
+        );
+        assert_eq!(classify_sdk_error(&err).0, ErrorKind::InvalidRequest);
+    }
+
+    #[test]
+    fn count_tokens_access_denied_is_permission() {
+        let err: SdkError<CountTokensError> = SdkError::service_error(
+            CountTokensError::AccessDeniedException(AccessDeniedException::builder().build()),
+            raw(403),
+        );
+        let (kind, _) = classify_sdk_error(&err);
+        assert_eq!(kind, ErrorKind::Permission);
+        assert_eq!(kind.openai_type(), "permission_error");
+    }
+
+    #[test]
+    fn unhandled_service_error_falls_back_to_api_error() {
+        let err: SdkError<ConverseError> =
+            SdkError::service_error(ConverseError::unhandled("boom"), raw(500));
+        assert_eq!(classify_sdk_error(&err).0, ErrorKind::Api);
+    }
+}
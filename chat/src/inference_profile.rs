@@ -0,0 +1,528 @@
+use aws_sdk_bedrockruntime::{
+    error::SdkError,
+    operation::{converse::ConverseOutput, converse_stream::ConverseStreamOutput},
+};
+use std::{collections::HashMap, future::Future, sync::Mutex};
+use tracing::{info, warn};
+
+use crate::error::{BedrockError, ErrorKind, classify_sdk_error};
+use crate::metrics::{record_bedrock_error, record_model_fallback};
+
+/// What [`InferenceProfileResolver::send_with_failover`] got from Bedrock for
+/// a candidate it did not fail over from.
+pub trait Connection {
+    /// Whether Bedrock has accepted the candidate yet. A connect still in
+    /// flight is only remembered once it succeeds.
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    /// Keeps the candidate of a connect still in flight, to be passed to
+    /// [`InferenceProfileResolver::record_connected`] once it succeeds.
+    fn set_candidate(&mut self, _candidate: Candidate) {}
+}
+
+impl Connection for ConverseOutput {}
+
+impl Connection for ConverseStreamOutput {}
+
+/// The model ID Bedrock was sent for a requested model.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    model: String,
+    model_id: String,
+}
+
+/// Resolves bare Bedrock model IDs (`anthropic.claude-…`) to the
+/// cross-region inference profile that serves them (`us.anthropic.claude-…`).
+///
+/// A model ID is bare when it is not an ARN and does not already start with
+/// one of the configured prefixes. Bare IDs are tried with each prefix in
+/// order and finally as-is, so on-demand models keep working; the first
+/// candidate that Bedrock accepts is remembered and tried first next time.
+#[derive(Debug, Default)]
+pub struct InferenceProfileResolver {
+    prefixes: Vec<String>,
+    preferred: Mutex<HashMap<String, String>>,
+}
+
+impl InferenceProfileResolver {
+    pub fn new(prefixes: Vec<String>) -> Self {
+        Self {
+            prefixes,
+            preferred: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The prefixes bare model IDs are tried with, in order.
+    pub fn prefixes(&self) -> &[String] {
+        &self.prefixes
+    }
+
+    fn is_bare(&self, model: &str) -> bool {
+        !model.starts_with("arn:")
+            && !self
+                .prefixes
+                .iter()
+                .any(|prefix| model.starts_with(prefix.as_str()))
+    }
+
+    /// The model IDs to try for `model`, in order. Never empty.
+    pub fn candidates(&self, model: &str) -> Vec<String> {
+        if !self.is_bare(model) {
+            return vec![model.to_string()];
+        }
+
+        let mut candidates: Vec<String> = self
+            .prefixes
+            .iter()
+            .map(|prefix| format!("{prefix}{model}"))
+            .chain(std::iter::once(model.to_string()))
+            .collect();
+        let preferred = self.preferred.lock().unwrap().get(model).cloned();
+        if let Some(preferred) = preferred
+            && let Some(position) = candidates.iter().position(|c| *c == preferred)
+        {
+            let preferred = candidates.remove(position);
+            candidates.insert(0, preferred);
+        }
+        candidates
+    }
+
+    fn record_success(&self, model: &str, candidate: &str) {
+        if !self.is_bare(model) {
+            return;
+        }
+        let mut preferred = self.preferred.lock().unwrap();
+        if preferred.get(model).map(String::as_str) != Some(candidate) {
+            info!("Resolved model {} to {}", model, candidate);
+            preferred.insert(model.to_string(), candidate.to_string());
+        }
+    }
+
+    /// Remembers the candidate of a connect that
+    /// [`send_with_failover`](Self::send_with_failover) returned while it was
+    /// still in flight, now that it succeeded.
+    pub fn record_connected(&self, candidate: &Candidate) {
+        self.record_success(&candidate.model, &candidate.model_id);
+    }
+
+    /// Remembers `candidate` for `model` if `output` is connected, or hands
+    /// it to `output` to be remembered once it is.
+    fn accept<T: Connection>(&self, model: &str, candidate: String, output: &mut T) {
+        if output.is_connected() {
+            self.record_success(model, &candidate);
+        } else {
+            output.set_candidate(Candidate {
+                model: model.to_string(),
+                model_id: candidate,
+            });
+        }
+    }
+
+    /// Runs `send` with each candidate for `model` until one succeeds or
+    /// fails with an error that another prefix cannot fix. When the bare model
+    /// ID tried last is rejected as invalid or unknown, the earlier throttle
+    /// or access-denied error is returned instead, since that is what actually
+    /// stopped the request. Every failed attempt is counted in the Bedrock
+    /// error metrics. A [`Connection`] still in flight is committed to.
+    pub async fn send_with_failover<T, E, R, F, Fut>(
+        &self,
+        model: &str,
+        mut send: F,
+    ) -> Result<T, SdkError<E, R>>
+    where
+        T: Connection,
+        E: BedrockError + std::error::Error + 'static,
+        R: std::fmt::Debug,
+        F: FnMut(String) -> Fut,
+        Fut: Future<Output = Result<T, SdkError<E, R>>>,
+    {
+        let mut candidates = self.candidates(model);
+        let last = candidates.pop().unwrap_or_else(|| model.to_string());
+        let mut failover_error = None;
+
+        for candidate in candidates {
+            match send(candidate.clone()).await {
+                Ok(mut output) => {
+                    self.accept(model, candidate, &mut output);
+                    return Ok(output);
+                }
+                Err(e) if should_fail_over(&e) => {
+                    let (kind, message) = classify_sdk_error(&e);
+                    record_bedrock_error(kind);
+                    warn!(
+                        "Bedrock rejected {} for model {}, trying next inference profile: {}",
+                        candidate, model, message
+                    );
+                    let keep = match &failover_error {
+                        None => true,
+                        Some(kept) => {
+                            kind == ErrorKind::RateLimit
+                                && classify_sdk_error(kept).0 != ErrorKind::RateLimit
+                        }
+                    };
+                    if keep {
+                        failover_error = Some(e);
+                    }
+                }
+                Err(e) => {
+                    record_bedrock_error(classify_sdk_error(&e).0);
+                    return Err(e);
+                }
+            }
+        }
+
+        match send(last.clone()).await {
+            Ok(mut output) => {
+                self.accept(model, last, &mut output);
+                Ok(output)
+            }
+            Err(e) => {
+                let kind = classify_sdk_error(&e).0;
+                record_bedrock_error(kind);
+                match failover_error {
+                    Some(earlier)
+                        if matches!(kind, ErrorKind::InvalidRequest | ErrorKind::NotFound) =>
+                    {
+                        Err(earlier)
+                    }
+                    _ => Err(e),
+                }
+            }
+        }
+    }
+
+    /// Runs [`send_with_failover`](Self::send_with_failover) for `model`,
+    /// then for each of `fallbacks` in order while Bedrock throttles the
+    /// previous one or reports it unavailable. Callers only pass fallbacks
+    /// while nothing has been sent to the client, so a different model can
+    /// still answer.
+    pub async fn send_with_fallbacks<T, E, R, F, Fut>(
+        &self,
+        model: &str,
+        fallbacks: &[String],
+        mut send: F,
+    ) -> Result<T, SdkError<E, R>>
+    where
+        T: Connection,
+        E: BedrockError + std::error::Error + 'static,
+        R: std::fmt::Debug,
+        F: FnMut(String) -> Fut,
+        Fut: Future<Output = Result<T, SdkError<E, R>>>,
+    {
+        let mut current = model;
+        for fallback in fallbacks {
+            match self.send_with_failover(current, &mut send).await {
+                Err(e) if should_fall_back(&e) => {
+                    warn!(
+                        "Bedrock could not serve {}, falling back to {}: {}",
+                        current,
+                        fallback,
+                        classify_sdk_error(&e).1
+                    );
+                    record_model_fallback(current, fallback);
+                    current = fallback;
+                }
+                result => return result,
+            }
+        }
+        self.send_with_failover(current, &mut send).await
+    }
+}
+
+/// A throttled or unavailable model may be busy while a sibling model, or
+/// the same model in another region, is not.
+fn should_fall_back<E, R>(err: &SdkError<E, R>) -> bool
+where
+    E: BedrockError + std::error::Error + 'static,
+    R: std::fmt::Debug,
+{
+    matches!(
+        classify_sdk_error(err).0,
+        ErrorKind::RateLimit | ErrorKind::Overloaded
+    )
+}
+
+/// Access-denied and throttling are per-profile conditions (a region group
+/// may not be enabled for the account, or may be saturated), and an unknown
+/// or on-demand-unsupported model identifier means the prefix is wrong.
+/// Anything else would fail the same way with every prefix.
+fn should_fail_over<E, R>(err: &SdkError<E, R>) -> bool
+where
+    E: BedrockError + std::error::Error + 'static,
+    R: std::fmt::Debug,
+{
+    let (kind, message) = classify_sdk_error(err);
+    match kind {
+        ErrorKind::Permission | ErrorKind::RateLimit | ErrorKind::NotFound => true,
+        ErrorKind::InvalidRequest => {
+            let message = message.to_lowercase();
+            message.contains("model identifier")
+                || message.contains("on-demand throughput")
+                || message.contains("inference profile")
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_bedrockruntime::{
+        operation::converse::ConverseError,
+        types::error::{
+            AccessDeniedException, ServiceUnavailableException, ThrottlingException,
+            ValidationException,
+        },
+    };
+    use aws_smithy_runtime_api::http::{Response, StatusCode};
+    use aws_smithy_types::{body::SdkBody, error::ErrorMetadata};
+
+    impl Connection for String {}
+
+    impl Connection for () {}
+
+    /// A connect still in flight, as when streaming outlasts the error window.
+    #[derive(Debug, Default)]
+    struct Pending(Option<Candidate>);
+
+    impl Connection for Pending {
+        fn is_connected(&self) -> bool {
+            false
+        }
+
+        fn set_candidate(&mut self, candidate: Candidate) {
+            self.0 = Some(candidate);
+        }
+    }
+
+    fn resolver() -> InferenceProfileResolver {
+        InferenceProfileResolver::new(vec!["us.".to_string(), "global.".to_string()])
+    }
+
+    fn access_denied() -> SdkError<ConverseError, Response> {
+        SdkError::service_error(
+            ConverseError::AccessDeniedException(AccessDeniedException::builder().build()),
+            Response::new(StatusCode::try_from(403).unwrap(), SdkBody::from("")),
+        )
+    }
+
+    fn validation(message: &str) -> SdkError<ConverseError, Response> {
+        SdkError::service_error(
+            ConverseError::ValidationException(
+                ValidationException::builder()
+                    .meta(ErrorMetadata::builder().message(message).build())
+                    .build(),
+            ),
+            Response::new(StatusCode::try_from(400).unwrap(), SdkBody::from("")),
+        )
+    }
+
+    #[test]
+    fn prefixed_and_arn_models_are_not_expanded() {
+        let resolver = resolver();
+        assert_eq!(
+            resolver.candidates("us.anthropic.claude-a"),
+            ["us.anthropic.claude-a"]
+        );
+        let arn = "arn:aws:bedrock:us-east-1:123456789012:inference-profile/x";
+        assert_eq!(resolver.candidates(arn), [arn]);
+    }
+
+    #[test]
+    fn bare_model_tries_prefixes_then_itself() {
+        assert_eq!(
+            resolver().candidates("anthropic.claude-a"),
+            [
+                "us.anthropic.claude-a",
+                "global.anthropic.claude-a",
+                "anthropic.claude-a"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_over_on_access_denied_and_remembers_winner() {
+        let resolver = resolver();
+        let mut attempts = Vec::new();
+        let result = resolver
+            .send_with_failover("anthropic.claude-a", |candidate| {
+                attempts.push(candidate.clone());
+                async move {
+                    if candidate.starts_with("us.") {
+                        Err(access_denied())
+                    } else {
+                        Ok(candidate)
+                    }
+                }
+            })
+            .await;
+
+        assert_eq!(result.unwrap(), "global.anthropic.claude-a");
+        assert_eq!(
+            attempts,
+            ["us.anthropic.claude-a", "global.anthropic.claude-a"]
+        );
+        assert_eq!(
+            resolver.candidates("anthropic.claude-a")[0],
+            "global.anthropic.claude-a"
+        );
+    }
+
+    #[tokio::test]
+    async fn remembers_a_pending_connect_only_once_it_connects() {
+        let resolver = resolver();
+        let pending = resolver
+            .send_with_failover("anthropic.claude-a", |candidate| async move {
+                if candidate.starts_with("us.") {
+                    Err(access_denied())
+                } else {
+                    Ok(Pending::default())
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            resolver.candidates("anthropic.claude-a")[0],
+            "us.anthropic.claude-a"
+        );
+
+        resolver.record_connected(pending.0.as_ref().unwrap());
+        assert_eq!(
+            resolver.candidates("anthropic.claude-a")[0],
+            "global.anthropic.claude-a"
+        );
+    }
+
+    #[tokio::test]
+    async fn does_not_fail_over_on_unrelated_validation_error() {
+        let resolver = resolver();
+        let mut attempts = 0;
+        let result: Result<(), _> = resolver
+            .send_with_failover("anthropic.claude-a", |_| {
+                attempts += 1;
+                async { Err(validation("max_tokens: must be positive")) }
+            })
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn invalid_model_identifier_falls_through_to_bare_id() {
+        let resolver = resolver();
+        let result = resolver
+            .send_with_failover("meta.llama3-70b", |candidate| async move {
+                if candidate == "meta.llama3-70b" {
+                    Ok(candidate)
+                } else {
+                    Err(validation("The provided model identifier is invalid."))
+                }
+            })
+            .await;
+
+        assert_eq!(result.unwrap(), "meta.llama3-70b");
+        assert_eq!(resolver.candidates("meta.llama3-70b")[0], "meta.llama3-70b");
+    }
+
+    fn throttled() -> SdkError<ConverseError, Response> {
+        SdkError::service_error(
+            ConverseError::ThrottlingException(ThrottlingException::builder().build()),
+            Response::new(StatusCode::try_from(429).unwrap(), SdkBody::from("")),
+        )
+    }
+
+    fn unavailable() -> SdkError<ConverseError, Response> {
+        SdkError::service_error(
+            ConverseError::ServiceUnavailableException(
+                ServiceUnavailableException::builder().build(),
+            ),
+            Response::new(StatusCode::try_from(503).unwrap(), SdkBody::from("")),
+        )
+    }
+
+    #[tokio::test]
+    async fn falls_back_through_chain_while_throttled_or_unavailable() {
+        let resolver = resolver();
+        let mut attempts = Vec::new();
+        let fallbacks = [
+            "us.anthropic.claude-b".to_string(),
+            "global.anthropic.claude-a".to_string(),
+        ];
+        let result = resolver
+            .send_with_fallbacks("us.anthropic.claude-a", &fallbacks, |candidate| {
+                attempts.push(candidate.clone());
+                async move {
+                    match candidate.as_str() {
+                        "us.anthropic.claude-a" => Err(throttled()),
+                        "us.anthropic.claude-b" => Err(unavailable()),
+                        _ => Ok(candidate),
+                    }
+                }
+            })
+            .await;
+
+        assert_eq!(result.unwrap(), "global.anthropic.claude-a");
+        assert_eq!(attempts.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_fall_back_on_other_errors() {
+        let resolver = resolver();
+        let mut attempts = 0;
+        let result: Result<(), _> = resolver
+            .send_with_fallbacks(
+                "us.anthropic.claude-a",
+                &["us.anthropic.claude-b".to_string()],
+                |_| {
+                    attempts += 1;
+                    async { Err(validation("max_tokens: must be positive")) }
+                },
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn last_candidate_error_is_returned() {
+        let resolver = resolver();
+        let mut attempts = 0;
+        let result: Result<(), _> = resolver
+            .send_with_failover("anthropic.claude-a", |_| {
+                attempts += 1;
+                async { Err(access_denied()) }
+            })
+            .await;
+
+        assert_eq!(
+            classify_sdk_error(&result.unwrap_err()).0,
+            ErrorKind::Permission
+        );
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn throttle_is_returned_over_bare_model_rejection() {
+        let resolver = resolver();
+        let result: Result<(), _> = resolver
+            .send_with_failover("anthropic.claude-a", |candidate| async move {
+                match candidate.as_str() {
+                    "us.anthropic.claude-a" => Err(access_denied()),
+                    "global.anthropic.claude-a" => Err(throttled()),
+                    _ => Err(validation(
+                        "Invocation of model ID anthropic.claude-a with on-demand throughput isn't supported.",
+                    )),
+                }
+            })
+            .await;
+
+        assert_eq!(
+            classify_sdk_error(&result.unwrap_err()).0,
+            ErrorKind::RateLimit
+        );
+    }
+}
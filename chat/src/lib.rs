@@ -1,5 +1,9 @@
 pub mod bedrock;
+pub mod error;
+pub mod inference_profile;
+pub mod metrics;
 pub mod provider;
+pub mod usage;
 
 use axum::response::sse::Event;
 use response::ChatCompletionsResponse;
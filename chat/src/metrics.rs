@@ -0,0 +1,126 @@
+//! Prometheus metrics for Bedrock calls and SSE streams, registered in the
+//! default registry and exposed by the server's `GET /metrics`.
+
+use prometheus::{
+    HistogramVec, IntCounter, IntCounterVec, IntGauge, register_histogram_vec,
+    register_int_counter, register_int_counter_vec, register_int_gauge,
+};
+use std::{sync::LazyLock, time::Instant};
+
+use crate::error::ErrorKind;
+
+/// Buckets in seconds, from a fast first token to a long generation.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0,
+];
+
+static BEDROCK_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "llm_proxy_bedrock_errors_total",
+        "Bedrock errors by classified kind",
+        &["kind"]
+    )
+    .unwrap()
+});
+
+static STREAMS_IN_FLIGHT: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "llm_proxy_sse_streams_in_flight",
+        "SSE streams currently relaying Bedrock events"
+    )
+    .unwrap()
+});
+
+static TIME_TO_FIRST_TOKEN: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "llm_proxy_time_to_first_token_seconds",
+        "Time from the request to the first streamed content delta",
+        &["protocol", "model"],
+        LATENCY_BUCKETS.to_vec()
+    )
+    .unwrap()
+});
+
+static STREAM_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "llm_proxy_stream_duration_seconds",
+        "Time from the request to the end of its SSE stream",
+        &["protocol", "model"],
+        LATENCY_BUCKETS.to_vec()
+    )
+    .unwrap()
+});
+
+static MODEL_FALLBACKS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "llm_proxy_model_fallbacks_total",
+        "Requests moved to a fallback model because Bedrock throttled or could not serve the original",
+        &["from", "to"]
+    )
+    .unwrap()
+});
+
+static PINGS: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!("llm_proxy_sse_pings_total", "SSE ping events sent").unwrap()
+});
+
+pub fn record_model_fallback(from: &str, to: &str) {
+    MODEL_FALLBACKS.with_label_values(&[from, to]).inc();
+}
+
+pub fn record_bedrock_error(kind: ErrorKind) {
+    BEDROCK_ERRORS
+        .with_label_values(&[kind.anthropic_type()])
+        .inc();
+}
+
+pub fn record_ping() {
+    PINGS.inc();
+}
+
+/// The number of SSE streams currently relaying Bedrock events.
+pub fn streams_in_flight() -> i64 {
+    STREAMS_IN_FLIGHT.get()
+}
+
+/// Tracks one SSE stream: in flight from creation until dropped, when its
+/// duration is observed.
+pub struct StreamMetrics {
+    protocol: &'static str,
+    model: String,
+    started: Instant,
+    first_token: bool,
+}
+
+impl StreamMetrics {
+    /// `started` is when the request arrived, so both histograms include the
+    /// time spent connecting to Bedrock.
+    pub fn new(protocol: &'static str, model: &str, started: Instant) -> Self {
+        STREAMS_IN_FLIGHT.inc();
+        Self {
+            protocol,
+            model: model.to_string(),
+            started,
+            first_token: false,
+        }
+    }
+
+    /// Observes time-to-first-token on the first call.
+    pub fn record_token(&mut self) {
+        if !self.first_token {
+            self.first_token = true;
+            TIME_TO_FIRST_TOKEN
+                .with_label_values(&[self.protocol, &self.model])
+                .observe(self.started.elapsed().as_secs_f64());
+        }
+    }
+}
+
+impl Drop for StreamMetrics {
+    fn drop(&mut self) {
+        STREAMS_IN_FLIGHT.dec();
+        STREAM_DURATION
+            .with_label_values(&[self.protocol, &self.model])
+            .observe(self.started.elapsed().as_secs_f64());
+    }
+}
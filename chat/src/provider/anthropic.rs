@@ -4,13 +4,14 @@ use anthropic_request::{
     get_additional_model_request_fields,
 };
 use anthropic_response::{
-    EventConverter, Message as V1MessagesResponse, converse_output_to_message,
+    EventConverter, Message as V1MessagesResponse, MessageCollector, converse_output_to_message,
 };
 use async_trait::async_trait;
 use aws_sdk_bedrockruntime::{
     Client,
     error::SdkError,
     operation::{
+        RequestId,
         converse::ConverseOutput as ConverseSendOutput,
         converse_stream::{ConverseStreamError, ConverseStreamOutput as ConverseStreamSendOutput},
     },
@@ -18,7 +19,7 @@ use aws_sdk_bedrockruntime::{
     types::{
         ContentBlock, ConverseOutput as ConverseOutputVariant, ConverseStreamOutput,
         ConverseTokensRequest, CountTokensInput, Message as BedrockMessage, SystemContentBlock,
-        TokenUsage, error::ConverseStreamOutputError,
+        error::ConverseStreamOutputError,
     },
 };
 use aws_smithy_types::Document;
@@ -30,10 +31,14 @@ use tokio::{
     time::{Instant, interval_at, timeout},
 };
 use tokio_stream::wrappers::ReceiverStream;
-use tracing::{error, info};
+use tracing::{Instrument, error, info, info_span, instrument};
 use uuid::Uuid;
 
 use crate::bedrock::BedrockChatCompletion;
+use crate::error::{ErrorKind, classify_sdk_error};
+use crate::inference_profile::{Candidate, Connection, InferenceProfileResolver};
+use crate::metrics::{StreamMetrics, record_bedrock_error, record_ping};
+use crate::usage::UsageReporter;
 
 const PING_INTERVAL: Duration = Duration::from_secs(20);
 const EVENT_TX_SEND_TIMEOUT: Duration = Duration::from_secs(30);
@@ -42,36 +47,14 @@ const EVENT_TX_SEND_TIMEOUT: Duration = Duration::from_secs(30);
 /// flow through `AppError` as proper HTTP 4xx with the upstream status code.
 const CONNECT_ERROR_WINDOW: Duration = Duration::from_secs(15);
 
-/// Maps a Bedrock `ConverseStreamError` to an Anthropic error `type` and
-/// extracts the human-readable upstream message. Default arm falls back to
-/// `api_error` so newly-introduced Bedrock variants never panic.
-fn classify_bedrock_error(err: &SdkError<ConverseStreamError>) -> (&'static str, String) {
-    let msg = err
-        .as_service_error()
-        .and_then(|e| e.meta().message())
-        .map(String::from)
-        .unwrap_or_else(|| err.to_string());
-
-    let kind = match err.as_service_error() {
-        Some(ConverseStreamError::ValidationException(_)) => "invalid_request_error",
-        Some(ConverseStreamError::ThrottlingException(_)) => "rate_limit_error",
-        Some(ConverseStreamError::AccessDeniedException(_)) => "permission_error",
-        Some(ConverseStreamError::ServiceUnavailableException(_)) => "overloaded_error",
-        Some(ConverseStreamError::ModelTimeoutException(_)) => "timeout_error",
-        Some(ConverseStreamError::ModelStreamErrorException(_)) => "api_error",
-        Some(ConverseStreamError::InternalServerException(_)) => "api_error",
-        _ => "api_error",
-    };
-    (kind, msg)
-}
-
 /// Builds a single-line Anthropic-style SSE error frame. Used when the HTTP
 /// status has already been committed as 200 and we can no longer surface the
 /// failure as 4xx via `AppError`.
-fn anthropic_error_event(kind: &str, message: &str) -> anyhow::Result<Event> {
+pub fn anthropic_error_event(kind: &str, message: &str, request_id: &str) -> anyhow::Result<Event> {
     let payload = serde_json::json!({
         "type": "error",
-        "error": { "type": kind, "message": message }
+        "error": { "type": kind, "message": message },
+        "request_id": request_id,
     });
     Ok(Event::default().event("error").data(payload.to_string()))
 }
@@ -79,6 +62,7 @@ fn anthropic_error_event(kind: &str, message: &str) -> anyhow::Result<Event> {
 /// Sends a ping SSE event. Returns false if the consumer is gone or stuck.
 async fn send_ping(event_tx: &mpsc::Sender<anyhow::Result<Event>>) -> bool {
     info!("Sending ping event");
+    record_ping();
     let ping_event = Ok(Event::default().event("ping").data(r#"{"type": "ping"}"#));
     match timeout(EVENT_TX_SEND_TIMEOUT, event_tx.send(ping_event)).await {
         Ok(Ok(())) => true,
@@ -93,24 +77,37 @@ async fn send_ping(event_tx: &mpsc::Sender<anyhow::Result<Event>>) -> bool {
     }
 }
 
+/// Receives the complete message of a stream that ended cleanly.
+type MessageSink = Box<dyn FnOnce(V1MessagesResponse) + Send + Sync>;
+
 async fn process_bedrock_stream_events(
     mut stream: EventReceiver<ConverseStreamOutput, ConverseStreamOutputError>,
-    model: String,
-    stop_sequences: Option<Vec<String>>,
-    usage_callback: Arc<dyn Fn(&TokenUsage) + Send + Sync>,
+    mut event_converter: EventConverter,
+    usage: UsageReporter,
+    mut metrics: StreamMetrics,
     event_tx: mpsc::Sender<anyhow::Result<Event>>,
     mut ping_interval: tokio::time::Interval,
+    on_message: Option<MessageSink>,
 ) {
-    let id = format!("msg_{}", Uuid::new_v4());
-    let mut event_converter = EventConverter::new(id, model, stop_sequences, usage_callback);
+    let mut collector = on_message.as_ref().map(|_| MessageCollector::default());
     'outer: loop {
         tokio::select! {
             biased;
             result = stream.recv() => {
                 match result {
                     Ok(Some(output)) => {
+                        usage.observe(&output);
+                        if let ConverseStreamOutput::ContentBlockDelta(_) = output {
+                            metrics.record_token();
+                        }
                         if let Some(events) = event_converter.convert(&output) {
                             for (event_name, event) in events {
+                                if let Some(c) = &mut collector
+                                    && let Err(e) = c.push(&event)
+                                {
+                                    error!("Failed to collect the streamed message: {e}");
+                                    collector = None;
+                                }
                                 let mut serde_failed = false;
                                 let sse_event = match serde_json::to_string(&event) {
                                     Ok(json) => Ok(Event::default().event(event_name).data(json)),
@@ -119,6 +116,7 @@ async fn process_bedrock_stream_events(
                                         anthropic_error_event(
                                             "api_error",
                                             &format!("Failed to serialize event: {e}"),
+                                            usage.request_id(),
                                         )
                                     }
                                 };
@@ -135,6 +133,7 @@ async fn process_bedrock_stream_events(
                                 }
                                 if serde_failed {
                                     error!("Event serialization failed; terminating stream");
+                                    usage.fail_stream(ErrorKind::Api);
                                     break 'outer;
                                 }
                             }
@@ -152,19 +151,29 @@ async fn process_bedrock_stream_events(
                                     Err(e) => anthropic_error_event(
                                         "api_error",
                                         &format!("Failed to serialize event: {e}"),
+                                        usage.request_id(),
                                     ),
                                 };
                                 let _ = timeout(EVENT_TX_SEND_TIMEOUT, event_tx.send(sse_event)).await;
                             }
+                        } else if let Some((collector, on_message)) = collector.take().zip(on_message)
+                            && let Some(message) = collector.finish()
+                        {
+                            on_message(message);
                         }
                         break 'outer;
                     }
                     Err(e) => {
+                        error!("Bedrock stream receive error: {e:?}");
+                        let (kind, msg) = classify_sdk_error(&e);
+                        record_bedrock_error(kind);
                         let event = anthropic_error_event(
-                            "api_error",
-                            &format!("Stream receive error: {e}"),
+                            kind.anthropic_type(),
+                            &format!("Stream receive error: {msg}"),
+                            usage.request_id(),
                         );
                         let _ = timeout(EVENT_TX_SEND_TIMEOUT, event_tx.send(event)).await;
+                        usage.fail_stream(kind);
                         break 'outer;
                     }
                 }
@@ -176,30 +185,27 @@ async fn process_bedrock_stream_events(
             }
         }
     }
+    usage.finish();
     info!("Bedrock stream finished");
 }
 
 #[async_trait]
 pub trait V1MessagesProvider {
-    async fn v1_messages_stream<F>(
+    async fn v1_messages_stream(
         self,
         request: V1MessagesRequest,
         response_model_id: Option<String>,
         anthropic_beta: Option<Vec<String>>,
-        usage_callback: F,
-    ) -> anyhow::Result<BoxStream<'async_trait, anyhow::Result<Event>>>
-    where
-        F: Fn(&TokenUsage) + Send + Sync + 'static;
+        usage: UsageReporter,
+    ) -> anyhow::Result<BoxStream<'async_trait, anyhow::Result<Event>>>;
 
-    async fn v1_messages<F>(
+    async fn v1_messages(
         self,
         request: V1MessagesRequest,
         response_model_id: Option<String>,
         anthropic_beta: Option<Vec<String>>,
-        usage_callback: F,
-    ) -> anyhow::Result<V1MessagesResponse>
-    where
-        F: Fn(&TokenUsage) + Send + Sync + 'static;
+        usage: UsageReporter,
+    ) -> anyhow::Result<V1MessagesResponse>;
 
     async fn v1_messages_count_tokens(
         &self,
@@ -306,6 +312,9 @@ fn log_bedrock_messages(messages: &[BedrockMessage]) {
 
 pub struct BedrockV1MessagesProvider {
     bedrockruntime_client: Client,
+    inference_profiles: Arc<InferenceProfileResolver>,
+    fallback_models: Vec<String>,
+    on_streamed_message: Option<MessageSink>,
 }
 
 type ConverseStreamSendFut = Pin<
@@ -335,142 +344,245 @@ fn send_converse_stream(
 
 enum StreamConnect {
     Ready(Box<ConverseStreamSendOutput>),
-    Pending(ConverseStreamSendFut),
+    Pending(ConverseStreamSendFut, Option<Candidate>),
+}
+
+impl Connection for StreamConnect {
+    fn is_connected(&self) -> bool {
+        matches!(self, StreamConnect::Ready(_))
+    }
+
+    fn set_candidate(&mut self, candidate: Candidate) {
+        if let StreamConnect::Pending(_, pending) = self {
+            *pending = Some(candidate);
+        }
+    }
 }
 
 /// Races `converse_stream`'s connect against the error window. Transient errors
 /// are already retried by the SDK's `standard` strategy before they surface
 /// here, so this only classifies the outcome: a fast connect (`Ready`), a fast
 /// error (becomes a 4xx via `AppError`), or a slow connect (`Pending`) that
-/// falls to a 200 SSE response with pings.
+/// falls to a 200 SSE response with pings. Fast errors on a bare model ID fail
+/// over to the next inference profile, and fast throttling or unavailability
+/// to the next of `fallback_models`; a `Pending` connect is committed to, and
+/// its inference profile only remembered once it connects.
+#[instrument(name = "bedrock.connect", skip_all, fields(gen_ai.request.model = %request.model))]
 async fn try_connect_stream(
     client: &Client,
+    inference_profiles: &InferenceProfileResolver,
+    fallback_models: &[String],
     request: &V1MessagesRequest,
     additional_model_request_fields: Option<Document>,
+    usage: &UsageReporter,
 ) -> anyhow::Result<StreamConnect> {
     let bcc = BedrockChatCompletion::try_from(request)?;
-    let mut send_fut = send_converse_stream(client, bcc, additional_model_request_fields);
-
-    match timeout(CONNECT_ERROR_WINDOW, &mut send_fut).await {
-        Ok(Ok(response)) => Ok(StreamConnect::Ready(Box::new(response))),
-        Ok(Err(e)) => {
+    inference_profiles
+        .send_with_fallbacks(&request.model, fallback_models, |model_id| {
+            usage.set_model(&model_id);
+            let mut send_fut = send_converse_stream(
+                client,
+                BedrockChatCompletion {
+                    model_id,
+                    ..bcc.clone()
+                },
+                additional_model_request_fields.clone(),
+            );
+            async move {
+                match timeout(CONNECT_ERROR_WINDOW, &mut send_fut).await {
+                    Ok(Ok(response)) => Ok(StreamConnect::Ready(Box::new(response))),
+                    Ok(Err(e)) => Err(e),
+                    Err(_) => Ok(StreamConnect::Pending(send_fut, None)),
+                }
+            }
+        })
+        .await
+        .inspect(|connect| {
+            if let StreamConnect::Ready(response) = connect {
+                usage.set_bedrock_request_id(response.request_id());
+            }
+        })
+        .map_err(|e| {
             error!("Bedrock API error: {e:?}");
-            Err(e.into())
-        }
-        Err(_) => Ok(StreamConnect::Pending(send_fut)),
-    }
+            usage.set_bedrock_request_id(e.request_id());
+            e.into()
+        })
 }
 
-/// Sends one `converse` request. Retries (exponential backoff + jitter +
-/// retry-quota) are handled by the SDK client configured in `main`.
+/// Sends one `converse` request per inference-profile candidate, then per
+/// fallback model while Bedrock throttles or is unavailable. Retries
+/// (exponential backoff + jitter + retry-quota) are handled by the SDK client
+/// configured in `main`.
+#[instrument(name = "bedrock.converse", skip_all, fields(gen_ai.request.model = %request.model))]
 async fn converse(
     client: &Client,
+    inference_profiles: &InferenceProfileResolver,
+    fallback_models: &[String],
     request: &V1MessagesRequest,
     additional_model_request_fields: Option<Document>,
+    usage: &UsageReporter,
 ) -> anyhow::Result<ConverseSendOutput> {
     let bcc = BedrockChatCompletion::try_from(request)?;
-    client
-        .converse()
-        .model_id(bcc.model_id)
-        .set_system(bcc.system_content_blocks)
-        .set_messages(bcc.messages)
-        .set_tool_config(bcc.tool_config)
-        .set_inference_config(Some(bcc.inference_config))
-        .set_additional_model_request_fields(additional_model_request_fields)
-        .set_output_config(bcc.output_config)
-        .send()
+    inference_profiles
+        .send_with_fallbacks(&request.model, fallback_models, |model_id| {
+            usage.set_model(&model_id);
+            let bcc = bcc.clone();
+            client
+                .converse()
+                .model_id(model_id)
+                .set_system(bcc.system_content_blocks)
+                .set_messages(bcc.messages)
+                .set_tool_config(bcc.tool_config)
+                .set_inference_config(Some(bcc.inference_config))
+                .set_additional_model_request_fields(additional_model_request_fields.clone())
+                .set_output_config(bcc.output_config)
+                .send()
+        })
         .await
+        .inspect(|output| usage.set_bedrock_request_id(output.request_id()))
         .map_err(|e| {
             error!("Bedrock Converse API error: {e:?}");
+            usage.set_bedrock_request_id(e.request_id());
             e.into()
         })
 }
 
 fn spawn_stream_relay(
     stream: EventReceiver<ConverseStreamOutput, ConverseStreamOutputError>,
-    model: String,
-    stop_sequences: Option<Vec<String>>,
-    usage_callback: Arc<dyn Fn(&TokenUsage) + Send + Sync>,
+    event_converter: EventConverter,
+    usage: UsageReporter,
+    metrics: StreamMetrics,
     event_tx: mpsc::Sender<anyhow::Result<Event>>,
+    on_message: Option<MessageSink>,
 ) {
     let ping_interval = interval_at(Instant::now() + PING_INTERVAL, PING_INTERVAL);
-    tokio::spawn(process_bedrock_stream_events(
-        stream,
-        model,
-        stop_sequences,
-        usage_callback,
-        event_tx,
-        ping_interval,
-    ));
+    tokio::spawn(
+        process_bedrock_stream_events(
+            stream,
+            event_converter,
+            usage,
+            metrics,
+            event_tx,
+            ping_interval,
+            on_message,
+        )
+        .instrument(info_span!("bedrock.stream")),
+    );
 }
 
 fn spawn_pending_stream_relay(
     mut send_fut: ConverseStreamSendFut,
-    model: String,
-    stop_sequences: Option<Vec<String>>,
-    usage_callback: Arc<dyn Fn(&TokenUsage) + Send + Sync>,
+    on_connected: impl FnOnce() + Send + 'static,
+    event_converter: EventConverter,
+    usage: UsageReporter,
+    metrics: StreamMetrics,
     event_tx: mpsc::Sender<anyhow::Result<Event>>,
+    on_message: Option<MessageSink>,
 ) {
-    tokio::spawn(async move {
-        let mut ping_interval = interval_at(Instant::now() + PING_INTERVAL, PING_INTERVAL);
-        let result = loop {
-            tokio::select! {
-                biased;
-                r = &mut send_fut => break r,
-                _ = ping_interval.tick() => {
-                    if !send_ping(&event_tx).await { return; }
+    tokio::spawn(
+        async move {
+            let mut ping_interval = interval_at(Instant::now() + PING_INTERVAL, PING_INTERVAL);
+            let result = loop {
+                tokio::select! {
+                    biased;
+                    r = &mut send_fut => break r,
+                    _ = ping_interval.tick() => {
+                        if !send_ping(&event_tx).await { return; }
+                    }
+                }
+            };
+            match result {
+                Ok(response) => {
+                    usage.set_bedrock_request_id(response.request_id());
+                    on_connected();
+                    process_bedrock_stream_events(
+                        response.stream,
+                        event_converter,
+                        usage,
+                        metrics,
+                        event_tx,
+                        ping_interval,
+                        on_message,
+                    )
+                    .await;
+                }
+                Err(e) => {
+                    error!("Bedrock API error after connect window: {:?}", e);
+                    usage.set_bedrock_request_id(e.request_id());
+                    let (kind, msg) = classify_sdk_error(&e);
+                    record_bedrock_error(kind);
+                    let _ = timeout(
+                        EVENT_TX_SEND_TIMEOUT,
+                        event_tx.send(anthropic_error_event(
+                            kind.anthropic_type(),
+                            &msg,
+                            usage.request_id(),
+                        )),
+                    )
+                    .await;
+                    usage.fail_stream(kind);
                 }
-            }
-        };
-        match result {
-            Ok(response) => {
-                process_bedrock_stream_events(
-                    response.stream,
-                    model,
-                    stop_sequences,
-                    usage_callback,
-                    event_tx,
-                    ping_interval,
-                )
-                .await;
-            }
-            Err(e) => {
-                error!("Bedrock API error after connect window: {:?}", e);
-                let (kind, msg) = classify_bedrock_error(&e);
-                let _ = timeout(
-                    EVENT_TX_SEND_TIMEOUT,
-                    event_tx.send(anthropic_error_event(kind, &msg)),
-                )
-                .await;
             }
         }
-    });
+        .instrument(info_span!("bedrock.stream")),
+    );
 }
 
 impl BedrockV1MessagesProvider {
     pub fn new(bedrockruntime_client: Client) -> Self {
         Self {
             bedrockruntime_client,
+            inference_profiles: Arc::default(),
+            fallback_models: Vec::new(),
+            on_streamed_message: None,
         }
     }
+
+    pub fn with_inference_profiles(
+        mut self,
+        inference_profiles: Arc<InferenceProfileResolver>,
+    ) -> Self {
+        self.inference_profiles = inference_profiles;
+        self
+    }
+
+    /// Models to try in order when Bedrock throttles the requested one or
+    /// reports it unavailable before the response has started.
+    pub fn with_fallback_models(mut self, fallback_models: Vec<String>) -> Self {
+        self.fallback_models = fallback_models;
+        self
+    }
+
+    /// Called with the complete message when a stream ends cleanly, as it
+    /// would have been returned without streaming. Not called for streams
+    /// that fail, are cut short or lose their client.
+    pub fn with_streamed_message(
+        mut self,
+        on_streamed_message: impl FnOnce(V1MessagesResponse) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_streamed_message = Some(Box::new(on_streamed_message));
+        self
+    }
 }
 
 #[async_trait]
 impl V1MessagesProvider for BedrockV1MessagesProvider {
-    async fn v1_messages_stream<F>(
+    async fn v1_messages_stream(
         self,
         request: V1MessagesRequest,
         response_model_id: Option<String>,
         anthropic_beta: Option<Vec<String>>,
-        usage_callback: F,
-    ) -> anyhow::Result<BoxStream<'async_trait, anyhow::Result<Event>>>
-    where
-        F: Fn(&TokenUsage) + Send + Sync + 'static,
-    {
+        usage: UsageReporter,
+    ) -> anyhow::Result<BoxStream<'async_trait, anyhow::Result<Event>>> {
         let model = response_model_id.unwrap_or(request.model.clone());
-        let stop_sequences = request.stop_sequences.clone();
+        let event_converter = EventConverter::new(
+            format!("msg_{}", Uuid::new_v4()),
+            model,
+            request.stop_sequences.clone(),
+        );
         log_v1_messages_request(&request);
-        let bedrock_chat_completion = BedrockChatCompletion::try_from(&request)?;
+        let bedrock_chat_completion = info_span!("translate_request")
+            .in_scope(|| BedrockChatCompletion::try_from(&request))?;
         let additional_model_request_fields = get_additional_model_request_fields(
             request.thinking.as_ref(),
             request.output_config.as_ref(),
@@ -494,30 +606,49 @@ impl V1MessagesProvider for BedrockV1MessagesProvider {
         );
 
         let (event_tx, event_rx) = mpsc::channel::<anyhow::Result<Event>>(1);
-        let usage_callback = Arc::new(usage_callback);
+        usage.set_streaming(true);
         let client = &self.bedrockruntime_client;
 
         // Race the connect against a short window: errors caught here flow
         // through `AppError` as proper HTTP 4xx with the upstream Bedrock
         // status. Slower connects fall to a 200 SSE response with pings.
-        match try_connect_stream(client, &request, additional_model_request_fields).await? {
+        match try_connect_stream(
+            client,
+            &self.inference_profiles,
+            &self.fallback_models,
+            &request,
+            additional_model_request_fields,
+            &usage,
+        )
+        .await?
+        {
             StreamConnect::Ready(response) => {
                 info!("Successfully connected to Bedrock stream for Anthropic format");
+                let metrics = StreamMetrics::new("anthropic", &request.model, usage.started());
                 spawn_stream_relay(
                     response.stream,
-                    model,
-                    stop_sequences,
-                    usage_callback,
+                    event_converter,
+                    usage,
+                    metrics,
                     event_tx,
+                    self.on_streamed_message,
                 );
             }
-            StreamConnect::Pending(send_fut) => {
+            StreamConnect::Pending(send_fut, candidate) => {
+                let metrics = StreamMetrics::new("anthropic", &request.model, usage.started());
+                let inference_profiles = self.inference_profiles.clone();
                 spawn_pending_stream_relay(
                     send_fut,
-                    model,
-                    stop_sequences,
-                    usage_callback,
+                    move || {
+                        if let Some(candidate) = candidate {
+                            inference_profiles.record_connected(&candidate);
+                        }
+                    },
+                    event_converter,
+                    usage,
+                    metrics,
                     event_tx,
+                    self.on_streamed_message,
                 );
             }
         }
@@ -525,16 +656,13 @@ impl V1MessagesProvider for BedrockV1MessagesProvider {
         Ok(ReceiverStream::new(event_rx).boxed())
     }
 
-    async fn v1_messages<F>(
+    async fn v1_messages(
         self,
         request: V1MessagesRequest,
         response_model_id: Option<String>,
         anthropic_beta: Option<Vec<String>>,
-        usage_callback: F,
-    ) -> anyhow::Result<V1MessagesResponse>
-    where
-        F: Fn(&TokenUsage) + Send + Sync + 'static,
-    {
+        usage: UsageReporter,
+    ) -> anyhow::Result<V1MessagesResponse> {
         let model = response_model_id.unwrap_or(request.model.clone());
         let stop_sequences = request.stop_sequences.clone();
         log_v1_messages_request(&request);
@@ -547,25 +675,34 @@ impl V1MessagesProvider for BedrockV1MessagesProvider {
         let client = &self.bedrockruntime_client;
 
         info!("Sending Anthropic request to Bedrock Converse API (non-streaming)");
-        let output = converse(client, &request, additional_model_request_fields).await?;
-
-        if let Some(usage) = output.usage() {
-            usage_callback(usage);
-        }
+        let output = converse(
+            client,
+            &self.inference_profiles,
+            &self.fallback_models,
+            &request,
+            additional_model_request_fields,
+            &usage,
+        )
+        .await?;
 
         let content_blocks = match output.output() {
             Some(ConverseOutputVariant::Message(message)) => message.content(),
             _ => &[],
         };
 
-        Ok(converse_output_to_message(
-            format!("msg_{}", Uuid::new_v4()),
-            model,
-            content_blocks,
-            output.stop_reason(),
-            output.usage(),
-            stop_sequences.as_deref(),
-        )?)
+        let message = info_span!("translate_response").in_scope(|| {
+            converse_output_to_message(
+                format!("msg_{}", Uuid::new_v4()),
+                model,
+                content_blocks,
+                output.stop_reason(),
+                output.usage(),
+                stop_sequences.as_deref(),
+            )
+        })?;
+        usage.set_usage(output.usage(), Some(output.stop_reason()));
+        usage.finish();
+        Ok(message)
     }
 
     async fn v1_messages_count_tokens(
@@ -618,6 +755,7 @@ impl V1MessagesProvider for BedrockV1MessagesProvider {
             Ok(response) => Ok(response.input_tokens),
             Err(e) => {
                 error!("Bedrock API error: {:?}", e);
+                record_bedrock_error(classify_sdk_error(&e).0);
                 Err(e.into())
             }
         }
@@ -1,183 +1,343 @@
 use anthropic_request::{get_additional_model_request_fields, output_config::OutputConfig};
-use anyhow::anyhow;
 use async_trait::async_trait;
 use aws_sdk_bedrockruntime::{
     Client,
+    operation::RequestId,
     primitives::event_stream::EventReceiver,
-    types::{ConverseStreamOutput, TokenUsage, error::ConverseStreamOutputError},
+    types::{
+        ConverseOutput as ConverseOutputVariant, ConverseStreamOutput,
+        error::ConverseStreamOutputError,
+    },
 };
+use aws_smithy_types::Document;
 use axum::response::sse::Event;
 use chrono::offset::Utc;
 use futures::stream::{BoxStream, StreamExt};
 use request::ChatCompletionsRequest;
-use response::converse_stream_output_to_chat_completions_response_builder;
+use response::{
+    ChatCompletionsResponse, converse_output_to_chat_completions_response,
+    converse_stream_output_to_chat_completions_response_builder,
+};
 use std::{sync::Arc, time::Duration};
 use tokio::{sync::mpsc, time::timeout};
 use tokio_stream::wrappers::ReceiverStream;
-use tracing::{error, info};
+use tracing::{Instrument, error, info, info_span, instrument};
 use uuid::Uuid;
 
+use crate::bedrock::BedrockChatCompletion;
 use crate::bedrock::openai::build_bedrock_chat_completion;
+use crate::error::{ErrorKind, classify_sdk_error};
+use crate::inference_profile::InferenceProfileResolver;
+use crate::metrics::{StreamMetrics, record_bedrock_error};
+use crate::usage::UsageReporter;
 use crate::{DONE_MESSAGE, create_sse_event};
 
 const EVENT_TX_SEND_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// Builds an OpenAI-style error chunk. Used when the HTTP status has already
+/// been committed as 200 and the failure can no longer surface via `AppError`.
+pub fn openai_error_event(
+    kind: ErrorKind,
+    message: &str,
+    request_id: &str,
+) -> anyhow::Result<Event> {
+    let payload = serde_json::json!({
+        "error": {
+            "message": message,
+            "type": kind.openai_type(),
+            "param": null,
+            "code": kind.openai_code(),
+        },
+        "request_id": request_id,
+    });
+    Ok(Event::default().data(payload.to_string()))
+}
+
 fn process_bedrock_stream(
     mut stream: EventReceiver<ConverseStreamOutput, ConverseStreamOutputError>,
     id: String,
     created: i64,
-    usage_callback: Arc<dyn Fn(&TokenUsage) + Send + Sync>,
+    model: String,
+    usage: UsageReporter,
+    mut metrics: StreamMetrics,
 ) -> BoxStream<'static, anyhow::Result<Event>> {
     let (event_tx, event_rx) = mpsc::channel::<anyhow::Result<Event>>(1);
 
-    tokio::spawn(async move {
-        loop {
-            match stream.recv().await {
-                Ok(Some(output)) => {
-                    let usage_callback = usage_callback.clone();
-                    if let Some(builder) =
-                        converse_stream_output_to_chat_completions_response_builder(
-                            &output,
-                            usage_callback,
-                        )
-                    {
-                        let response = builder.id(Some(id.clone())).created(Some(created)).build();
-
-                        let sse_event = create_sse_event(&response);
-                        match timeout(EVENT_TX_SEND_TIMEOUT, event_tx.send(sse_event)).await {
-                            Ok(Ok(())) => {}
-                            Ok(Err(_)) => {
-                                info!("SSE client disconnected, stopping Bedrock stream");
-                                return;
-                            }
-                            Err(_) => {
-                                error!("Channel send timed out, consumer likely stuck");
-                                return;
+    tokio::spawn(
+        async move {
+            loop {
+                match stream.recv().await {
+                    Ok(Some(output)) => {
+                        usage.observe(&output);
+                        if let ConverseStreamOutput::ContentBlockDelta(_) = output {
+                            metrics.record_token();
+                        }
+                        if let Some(builder) =
+                            converse_stream_output_to_chat_completions_response_builder(&output)
+                        {
+                            let response = builder
+                                .id(Some(id.clone()))
+                                .created(Some(created))
+                                .model(Some(model.clone()))
+                                .object(Some("chat.completion.chunk".to_string()))
+                                .build();
+
+                            let sse_event = create_sse_event(&response);
+                            match timeout(EVENT_TX_SEND_TIMEOUT, event_tx.send(sse_event)).await {
+                                Ok(Ok(())) => {}
+                                Ok(Err(_)) => {
+                                    info!("SSE client disconnected, stopping Bedrock stream");
+                                    return;
+                                }
+                                Err(_) => {
+                                    error!("Channel send timed out, consumer likely stuck");
+                                    return;
+                                }
                             }
                         }
                     }
-                }
-                Ok(None) => break,
-                Err(e) => {
-                    let _ = timeout(
-                        EVENT_TX_SEND_TIMEOUT,
-                        event_tx.send(Err(anyhow!("Stream receive error: {}", e))),
-                    )
-                    .await;
-                    break;
+                    Ok(None) => break,
+                    Err(e) => {
+                        error!("Bedrock stream receive error: {e:?}");
+                        let (kind, msg) = classify_sdk_error(&e);
+                        record_bedrock_error(kind);
+                        let _ = timeout(
+                            EVENT_TX_SEND_TIMEOUT,
+                            event_tx.send(openai_error_event(
+                                kind,
+                                &format!("Stream receive error: {msg}"),
+                                usage.request_id(),
+                            )),
+                        )
+                        .await;
+                        usage.fail_stream(kind);
+                        break;
+                    }
                 }
             }
-        }
+            usage.finish();
 
-        info!("Stream finished, sending DONE message");
-        let _ = timeout(
-            EVENT_TX_SEND_TIMEOUT,
-            event_tx.send(Ok(Event::default().data(DONE_MESSAGE))),
-        )
-        .await;
-    });
+            info!("Stream finished, sending DONE message");
+            let _ = timeout(
+                EVENT_TX_SEND_TIMEOUT,
+                event_tx.send(Ok(Event::default().data(DONE_MESSAGE))),
+            )
+            .await;
+        }
+        .instrument(info_span!("bedrock.stream")),
+    );
 
     ReceiverStream::new(event_rx).boxed()
 }
 
+/// Translates an OpenAI request into its Bedrock form plus the
+/// `additionalModelRequestFields` carrying `reasoning_effort`.
+#[instrument(name = "translate_request", skip_all)]
+fn prepare_bedrock_request(
+    request: &ChatCompletionsRequest,
+) -> anyhow::Result<(BedrockChatCompletion, Option<Document>)> {
+    let bedrock_chat_completion = build_bedrock_chat_completion(request)?;
+    let output_config =
+        request
+            .reasoning_effort
+            .as_ref()
+            .map(|reasoning_effort| OutputConfig::Effort {
+                effort: reasoning_effort.clone(),
+            });
+    let anthropic_beta = request
+        .reasoning_effort
+        .as_ref()
+        .map(|_| vec!["effort-2025-11-24".to_string()]);
+    let additional_model_request_fields = get_additional_model_request_fields(
+        None,
+        output_config.as_ref(),
+        anthropic_beta.as_deref(),
+        None,
+    );
+    info!(
+        "Processed OpenAI request to Bedrock format with {} messages",
+        bedrock_chat_completion
+            .messages
+            .as_ref()
+            .map_or(0, |m| m.len())
+    );
+    Ok((bedrock_chat_completion, additional_model_request_fields))
+}
+
 #[async_trait]
 pub trait ChatCompletionsProvider {
-    async fn chat_completions_stream<F>(
+    async fn chat_completions_stream(
         self,
         request: ChatCompletionsRequest,
-        usage_callback: F,
-    ) -> anyhow::Result<BoxStream<'async_trait, anyhow::Result<Event>>>
-    where
-        F: Fn(&TokenUsage) + Send + Sync + 'static;
+        response_model_id: Option<String>,
+        usage: UsageReporter,
+    ) -> anyhow::Result<BoxStream<'async_trait, anyhow::Result<Event>>>;
+
+    async fn chat_completions(
+        self,
+        request: ChatCompletionsRequest,
+        response_model_id: Option<String>,
+        usage: UsageReporter,
+    ) -> anyhow::Result<ChatCompletionsResponse>;
 }
 
 pub struct BedrockChatCompletionsProvider {
     bedrockruntime_client: Client,
+    inference_profiles: Arc<InferenceProfileResolver>,
+    fallback_models: Vec<String>,
 }
 
 impl BedrockChatCompletionsProvider {
     pub fn new(bedrockruntime_client: Client) -> Self {
         Self {
             bedrockruntime_client,
+            inference_profiles: Arc::default(),
+            fallback_models: Vec::new(),
         }
     }
+
+    pub fn with_inference_profiles(
+        mut self,
+        inference_profiles: Arc<InferenceProfileResolver>,
+    ) -> Self {
+        self.inference_profiles = inference_profiles;
+        self
+    }
+
+    /// Models to try in order when Bedrock throttles the requested one or
+    /// reports it unavailable before the response has started.
+    pub fn with_fallback_models(mut self, fallback_models: Vec<String>) -> Self {
+        self.fallback_models = fallback_models;
+        self
+    }
 }
 
 #[async_trait]
 impl ChatCompletionsProvider for BedrockChatCompletionsProvider {
-    async fn chat_completions_stream<F>(
+    async fn chat_completions_stream(
         self,
         request: ChatCompletionsRequest,
-        usage_callback: F,
-    ) -> anyhow::Result<BoxStream<'async_trait, anyhow::Result<Event>>>
-    where
-        F: Fn(&TokenUsage) + Send + Sync + 'static,
-    {
-        let bedrock_chat_completion = build_bedrock_chat_completion(&request)?;
-        let output_config =
-            request
-                .reasoning_effort
-                .as_ref()
-                .map(|reasoning_effort| OutputConfig::Effort {
-                    effort: reasoning_effort.clone(),
-                });
-        let anthropic_beta = request
-            .reasoning_effort
-            .as_ref()
-            .map(|_| vec!["effort-2025-11-24".to_string()]);
-        let additional_model_request_fields = get_additional_model_request_fields(
-            None,
-            output_config.as_ref(),
-            anthropic_beta.as_deref(),
-            None,
-        );
-        info!(
-            "Processed OpenAI request to Bedrock format with {} messages",
-            bedrock_chat_completion
-                .messages
-                .as_ref()
-                .map_or(0, |m| m.len())
-        );
+        response_model_id: Option<String>,
+        usage: UsageReporter,
+    ) -> anyhow::Result<BoxStream<'async_trait, anyhow::Result<Event>>> {
+        let model = response_model_id.unwrap_or(request.model.clone());
+        let (bedrock_chat_completion, additional_model_request_fields) =
+            prepare_bedrock_request(&request)?;
 
         info!(
             "Sending OpenAI request to Bedrock API for model: {}",
             bedrock_chat_completion.model_id
         );
 
-        let converse_builder = self
-            .bedrockruntime_client
-            .converse_stream()
-            .model_id(&bedrock_chat_completion.model_id)
-            .set_system(bedrock_chat_completion.system_content_blocks)
-            .set_messages(bedrock_chat_completion.messages)
-            .set_tool_config(bedrock_chat_completion.tool_config)
-            .set_inference_config(Some(bedrock_chat_completion.inference_config))
-            .set_additional_model_request_fields(additional_model_request_fields);
-
         info!("About to send OpenAI request to Bedrock...");
-        let result = converse_builder.send().await;
+        usage.set_streaming(true);
+        let result = self
+            .inference_profiles
+            .send_with_fallbacks(&request.model, &self.fallback_models, |model_id| {
+                usage.set_model(&model_id);
+                let bcc = bedrock_chat_completion.clone();
+                self.bedrockruntime_client
+                    .converse_stream()
+                    .model_id(model_id)
+                    .set_system(bcc.system_content_blocks)
+                    .set_messages(bcc.messages)
+                    .set_tool_config(bcc.tool_config)
+                    .set_inference_config(Some(bcc.inference_config))
+                    .set_additional_model_request_fields(additional_model_request_fields.clone())
+                    .send()
+            })
+            .instrument(info_span!(
+                "bedrock.connect",
+                gen_ai.request.model = %request.model
+            ))
+            .await;
 
         let bedrock_stream = match result {
             Ok(response) => {
                 info!("Successfully connected to Bedrock stream");
+                usage.set_bedrock_request_id(response.request_id());
                 response.stream
             }
             Err(e) => {
                 tracing::error!("Bedrock API error: {:?}", e);
+                usage.set_bedrock_request_id(e.request_id());
                 return Err(e.into());
             }
         };
 
-        let request_id = Uuid::new_v4().to_string();
+        let completion_id = Uuid::new_v4().to_string();
         let created_timestamp = Utc::now().timestamp();
-
-        let usage_callback = Arc::new(usage_callback);
+        let metrics = StreamMetrics::new("openai", &request.model, usage.started());
 
         Ok(process_bedrock_stream(
             bedrock_stream,
-            request_id,
+            completion_id,
             created_timestamp,
-            usage_callback,
+            model,
+            usage,
+            metrics,
         ))
     }
+
+    async fn chat_completions(
+        self,
+        request: ChatCompletionsRequest,
+        response_model_id: Option<String>,
+        usage: UsageReporter,
+    ) -> anyhow::Result<ChatCompletionsResponse> {
+        let model = response_model_id.unwrap_or(request.model.clone());
+        let (bedrock_chat_completion, additional_model_request_fields) =
+            prepare_bedrock_request(&request)?;
+
+        info!(
+            "Sending OpenAI request to Bedrock Converse API (non-streaming) for model: {}",
+            bedrock_chat_completion.model_id
+        );
+
+        let output = self
+            .inference_profiles
+            .send_with_fallbacks(&request.model, &self.fallback_models, |model_id| {
+                usage.set_model(&model_id);
+                let bcc = bedrock_chat_completion.clone();
+                self.bedrockruntime_client
+                    .converse()
+                    .model_id(model_id)
+                    .set_system(bcc.system_content_blocks)
+                    .set_messages(bcc.messages)
+                    .set_tool_config(bcc.tool_config)
+                    .set_inference_config(Some(bcc.inference_config))
+                    .set_additional_model_request_fields(additional_model_request_fields.clone())
+                    .send()
+            })
+            .instrument(info_span!(
+                "bedrock.converse",
+                gen_ai.request.model = %request.model
+            ))
+            .await
+            .inspect(|output| usage.set_bedrock_request_id(output.request_id()))
+            .map_err(|e| {
+                error!("Bedrock Converse API error: {e:?}");
+                usage.set_bedrock_request_id(e.request_id());
+                e
+            })?;
+
+        let content_blocks = match output.output() {
+            Some(ConverseOutputVariant::Message(message)) => message.content(),
+            _ => &[],
+        };
+
+        let response = info_span!("translate_response").in_scope(|| {
+            converse_output_to_chat_completions_response(
+                format!("chatcmpl-{}", Uuid::new_v4()),
+                Utc::now().timestamp(),
+                model,
+                content_blocks,
+                output.stop_reason(),
+                output.usage(),
+            )
+        })?;
+        usage.set_usage(output.usage(), Some(output.stop_reason()));
+        usage.finish();
+        Ok(response)
+    }
 }
@@ -0,0 +1,255 @@
+use aws_sdk_bedrockruntime::types::{ConverseStreamOutput, StopReason, TokenUsage};
+use axum::http::StatusCode;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::info;
+
+use crate::error::ErrorKind;
+
+/// What one request consumed, reported exactly once when it finishes:
+/// after the response for non-streaming calls, when the relay ends for
+/// streams, or when the request fails before producing a response.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UsageEvent {
+    pub request_id: String,
+    /// Bedrock's `x-amzn-requestid` for the last upstream call, if one got
+    /// far enough to receive a response.
+    pub bedrock_request_id: Option<String>,
+    pub principal: String,
+    /// The model name the client sent, before alias resolution.
+    pub requested_model: String,
+    /// The Bedrock model ID last sent upstream, after alias and inference
+    /// profile resolution.
+    pub model: String,
+    pub streaming: bool,
+    /// The HTTP status of the response. A stream that fails after the 200
+    /// was committed keeps its 200 and reports the failure in `error`.
+    pub status: StatusCode,
+    pub error: Option<ErrorKind>,
+    /// Set when the proxy itself refused the request, e.g. for a model the
+    /// key may not use, so `error` did not come from Bedrock.
+    pub rejected: bool,
+    /// `None` when Bedrock never reported usage, e.g. the client disconnected.
+    pub usage: Option<TokenUsage>,
+    pub stop_reason: Option<StopReason>,
+    pub latency: Duration,
+}
+
+/// Receives [`UsageEvent`]s. Implemented for closures taking `&UsageEvent`.
+pub trait UsageSink: Send + Sync {
+    fn record(&self, event: &UsageEvent);
+}
+
+impl<F> UsageSink for F
+where
+    F: Fn(&UsageEvent) + Send + Sync,
+{
+    fn record(&self, event: &UsageEvent) {
+        self(event)
+    }
+}
+
+struct Inner {
+    sink: Arc<dyn UsageSink>,
+    request_id: String,
+    started: Instant,
+    /// The Bedrock model ID last sent to, kept after the event is reported.
+    model: Mutex<String>,
+    /// Taken when the event is reported, so it is reported once.
+    event: Mutex<Option<UsageEvent>>,
+}
+
+impl Inner {
+    fn report(&self, update: impl FnOnce(&mut UsageEvent)) {
+        let event = self.event.lock().unwrap().take();
+        if let Some(mut event) = event {
+            update(&mut event);
+            event.latency = self.started.elapsed();
+            self.sink.record(&event);
+        }
+    }
+}
+
+impl Drop for Inner {
+    /// A request abandoned without an explicit outcome, e.g. a stream whose
+    /// client went away, is still reported with whatever was observed.
+    fn drop(&mut self) {
+        self.report(|_| {});
+    }
+}
+
+/// Collects a [`UsageEvent`] as a request progresses and hands it to the
+/// sink exactly once. Clones share the same event: the handler keeps one to
+/// report failures while the provider, or its stream relay, fills it in.
+#[derive(Clone)]
+pub struct UsageReporter {
+    inner: Arc<Inner>,
+}
+
+impl UsageReporter {
+    pub fn new(
+        sink: Arc<dyn UsageSink>,
+        request_id: String,
+        principal: String,
+        requested_model: String,
+    ) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                sink,
+                request_id: request_id.clone(),
+                started: Instant::now(),
+                model: Mutex::new(requested_model.clone()),
+                event: Mutex::new(Some(UsageEvent {
+                    request_id,
+                    bedrock_request_id: None,
+                    principal,
+                    model: requested_model.clone(),
+                    requested_model,
+                    streaming: false,
+                    status: StatusCode::OK,
+                    error: None,
+                    rejected: false,
+                    usage: None,
+                    stop_reason: None,
+                    latency: Duration::ZERO,
+                })),
+            }),
+        }
+    }
+
+    /// The proxy's ID for the request, returned to the client.
+    pub fn request_id(&self) -> &str {
+        &self.inner.request_id
+    }
+
+    /// When the request arrived.
+    pub fn started(&self) -> Instant {
+        self.inner.started
+    }
+
+    fn update(&self, update: impl FnOnce(&mut UsageEvent)) {
+        if let Some(event) = self.inner.event.lock().unwrap().as_mut() {
+            update(event);
+        }
+    }
+
+    /// The Bedrock model ID the request was last sent to: the requested
+    /// model until the provider resolves an inference profile or falls back.
+    pub fn model(&self) -> String {
+        self.inner.model.lock().unwrap().clone()
+    }
+
+    pub fn set_model(&self, model: &str) {
+        *self.inner.model.lock().unwrap() = model.to_string();
+        self.update(|event| event.model = model.to_string());
+    }
+
+    pub fn set_bedrock_request_id(&self, bedrock_request_id: Option<&str>) {
+        if let Some(id) = bedrock_request_id {
+            info!("Bedrock request id: {id}");
+            self.update(|event| event.bedrock_request_id = Some(id.to_string()));
+        }
+    }
+
+    pub fn set_streaming(&self, streaming: bool) {
+        self.update(|event| event.streaming = streaming);
+    }
+
+    pub fn set_usage(&self, usage: Option<&TokenUsage>, stop_reason: Option<&StopReason>) {
+        self.update(|event| {
+            event.usage = usage.cloned();
+            event.stop_reason = stop_reason.cloned();
+        });
+    }
+
+    /// Records the usage and stop reason carried by a stream event.
+    pub fn observe(&self, output: &ConverseStreamOutput) {
+        match output {
+            ConverseStreamOutput::MessageStop(event) => {
+                self.update(|e| e.stop_reason = Some(event.stop_reason.clone()));
+            }
+            ConverseStreamOutput::Metadata(event) => {
+                self.update(|e| e.usage = event.usage.clone());
+            }
+            _ => {}
+        }
+    }
+
+    /// Reports the request as completed.
+    pub fn finish(&self) {
+        self.inner.report(|_| {});
+    }
+
+    /// Reports a failure after the response status was committed.
+    pub fn fail_stream(&self, kind: ErrorKind) {
+        self.inner.report(|event| event.error = Some(kind));
+    }
+
+    /// Reports a request that failed with `status` before any response.
+    pub fn fail(&self, status: StatusCode, kind: ErrorKind) {
+        self.inner.report(|event| {
+            event.status = status;
+            event.error = Some(kind);
+        });
+    }
+
+    /// Reports a request the proxy refused with `status` instead of sending
+    /// it to Bedrock.
+    pub fn reject(&self, status: StatusCode, kind: ErrorKind) {
+        self.inner.report(|event| {
+            event.status = status;
+            event.error = Some(kind);
+            event.rejected = true;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reporter() -> (UsageReporter, Arc<Mutex<Vec<UsageEvent>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let reporter = UsageReporter::new(
+            Arc::new(move |event: &UsageEvent| sink.lock().unwrap().push(event.clone())),
+            "req_1".to_string(),
+            "alice".to_string(),
+            "claude".to_string(),
+        );
+        (reporter, events)
+    }
+
+    #[test]
+    fn reports_once_despite_later_failure() {
+        let (reporter, events) = reporter();
+        reporter.set_model("us.anthropic.claude");
+        reporter.set_usage(None, Some(&StopReason::EndTurn));
+        reporter.finish();
+        reporter.fail(StatusCode::BAD_GATEWAY, ErrorKind::Api);
+        drop(reporter);
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].model, "us.anthropic.claude");
+        assert_eq!(events[0].requested_model, "claude");
+        assert_eq!(events[0].status, StatusCode::OK);
+        assert_eq!(events[0].stop_reason, Some(StopReason::EndTurn));
+    }
+
+    #[test]
+    fn dropping_last_clone_reports_abandoned_request() {
+        let (reporter, events) = reporter();
+        let relay = reporter.clone();
+        drop(reporter);
+        assert!(events.lock().unwrap().is_empty());
+
+        drop(relay);
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].usage, None);
+        assert_eq!(events[0].error, None);
+    }
+}
@@ -1,9 +1,14 @@
 use aws_sdk_bedrockruntime::types::{
     ContentBlockDelta, ContentBlockStart, ConversationRole, ConverseStreamOutput,
-    ReasoningContentBlockDelta, StopReason, TokenUsage, ToolUseBlockDelta, ToolUseBlockStart,
+    ReasoningContentBlockDelta, StopReason, ToolUseBlockDelta, ToolUseBlockStart,
 };
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+
+pub mod message;
+pub mod model;
+
+pub use message::*;
+pub use model::*;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ChatCompletionsResponse {
@@ -13,6 +18,8 @@ pub struct ChatCompletionsResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub object: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub usage: Option<Usage>,
@@ -27,6 +34,8 @@ pub struct Choice {
     pub index: i32,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub logprobs: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<Message>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -77,6 +86,7 @@ pub struct ChatCompletionsResponseBuilder {
     choices: Vec<Choice>,
     created: Option<i64>,
     id: Option<String>,
+    model: Option<String>,
     object: Option<String>,
     usage: Option<Usage>,
 }
@@ -97,6 +107,11 @@ impl ChatCompletionsResponseBuilder {
         self
     }
 
+    pub fn model(mut self, model: Option<String>) -> Self {
+        self.model = model;
+        self
+    }
+
     pub fn object(mut self, object: Option<String>) -> Self {
         self.object = object;
         self
@@ -112,6 +127,7 @@ impl ChatCompletionsResponseBuilder {
             choices: self.choices,
             created: self.created,
             id: self.id,
+            model: self.model,
             object: self.object,
             usage: self.usage,
         }
@@ -124,6 +140,7 @@ pub struct ChoiceBuilder {
     pub finish_reason: Option<String>,
     pub index: i32,
     pub logprobs: Option<String>,
+    pub message: Option<Message>,
 }
 
 impl ChoiceBuilder {
@@ -147,12 +164,24 @@ impl ChoiceBuilder {
         self
     }
 
+    pub fn message(mut self, message: Option<Message>) -> Self {
+        self.message = message;
+        self
+    }
+
+    /// Streaming chunks always carry a `delta` (empty when there is nothing to
+    /// report); non-streaming choices carry a `message` instead.
     pub fn build(self) -> Choice {
+        let delta = match self.message {
+            Some(_) => self.delta,
+            None => self.delta.or(Some(Delta::Empty {})),
+        };
         Choice {
-            delta: self.delta.or(Some(Delta::Empty {})),
+            delta,
             finish_reason: self.finish_reason,
             index: self.index,
             logprobs: self.logprobs,
+            message: self.message,
         }
     }
 }
@@ -219,9 +248,22 @@ fn tool_use_block_start_to_tool_call(
     }
 }
 
+/// Maps a Bedrock stop reason to an OpenAI `finish_reason`.
+pub fn stop_reason_to_finish_reason(stop_reason: &StopReason) -> Option<String> {
+    match stop_reason {
+        StopReason::EndTurn => Some("stop".to_string()),
+        StopReason::ToolUse => Some("tool_calls".to_string()),
+        StopReason::MaxTokens => Some("length".to_string()),
+        StopReason::StopSequence => Some("stop".to_string()),
+        StopReason::ContentFiltered | StopReason::GuardrailIntervened => {
+            Some("content_filter".to_string())
+        }
+        _ => None,
+    }
+}
+
 pub fn converse_stream_output_to_chat_completions_response_builder(
     output: &ConverseStreamOutput,
-    usage_callback: Arc<dyn Fn(&TokenUsage)>,
 ) -> Option<ChatCompletionsResponseBuilder> {
     let builder = ChatCompletionsResponse::builder();
 
@@ -290,13 +332,7 @@ pub fn converse_stream_output_to_chat_completions_response_builder(
             }
         }
         ConverseStreamOutput::MessageStop(event) => {
-            let finish_reason = match event.stop_reason {
-                StopReason::EndTurn => Some("stop".to_string()),
-                StopReason::ToolUse => Some("tool_calls".to_string()),
-                StopReason::MaxTokens => Some("length".to_string()),
-                StopReason::StopSequence => Some("stop".to_string()),
-                _ => None,
-            };
+            let finish_reason = stop_reason_to_finish_reason(&event.stop_reason);
 
             let choice = ChoiceBuilder::default()
                 .finish_reason(finish_reason)
@@ -306,8 +342,6 @@ pub fn converse_stream_output_to_chat_completions_response_builder(
         }
         ConverseStreamOutput::Metadata(event) => {
             let usage = event.usage.as_ref().map(|u| {
-                usage_callback(u);
-
                 UsageBuilder::default()
                     .completion_tokens(u.output_tokens)
                     .prompt_tokens(u.input_tokens)
@@ -0,0 +1,245 @@
+use aws_sdk_bedrockruntime::types::{
+    ContentBlock, ReasoningContentBlock, StopReason, TokenUsage, ToolUseBlock,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ChatCompletionsResponse, ChoiceBuilder, Function, ToolCall, UsageBuilder,
+    stop_reason_to_finish_reason,
+};
+
+/// The assistant message of a non-streaming `chat.completion` choice.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Message {
+    pub role: String,
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+}
+
+impl Message {
+    pub fn builder() -> MessageBuilder {
+        MessageBuilder::default()
+    }
+}
+
+#[derive(Default)]
+pub struct MessageBuilder {
+    role: String,
+    content: Option<String>,
+    reasoning_content: Option<String>,
+    tool_calls: Option<Vec<ToolCall>>,
+}
+
+impl MessageBuilder {
+    pub fn role(mut self, role: String) -> Self {
+        self.role = role;
+        self
+    }
+
+    pub fn content(mut self, content: Option<String>) -> Self {
+        self.content = content;
+        self
+    }
+
+    pub fn reasoning_content(mut self, reasoning_content: Option<String>) -> Self {
+        self.reasoning_content = reasoning_content;
+        self
+    }
+
+    pub fn tool_calls(mut self, tool_calls: Option<Vec<ToolCall>>) -> Self {
+        self.tool_calls = tool_calls;
+        self
+    }
+
+    pub fn build(self) -> Message {
+        Message {
+            role: self.role,
+            content: self.content,
+            reasoning_content: self.reasoning_content,
+            tool_calls: self.tool_calls,
+        }
+    }
+}
+
+fn tool_use_block_to_tool_call(tool_use: &ToolUseBlock) -> Result<ToolCall, serde_json::Error> {
+    Ok(ToolCall {
+        id: Some(tool_use.tool_use_id().to_string()),
+        tool_call_type: "function".to_string(),
+        function: Some(Function {
+            name: Some(tool_use.name().to_string()),
+            arguments: Some(serde_json::to_string(&common::document_to_value(
+                tool_use.input(),
+            ))?),
+        }),
+        index: None,
+    })
+}
+
+fn non_empty(s: String) -> Option<String> {
+    if s.is_empty() { None } else { Some(s) }
+}
+
+/// Folds the content blocks of a Bedrock `Converse` response into a single
+/// OpenAI assistant message. Text and reasoning blocks are concatenated in
+/// order; tool uses become `tool_calls` with JSON-encoded arguments.
+pub fn content_blocks_to_message(
+    content_blocks: &[ContentBlock],
+) -> Result<Message, serde_json::Error> {
+    let mut content = String::new();
+    let mut reasoning_content = String::new();
+    let mut tool_calls = Vec::new();
+
+    for block in content_blocks {
+        match block {
+            ContentBlock::Text(text) => content.push_str(text),
+            ContentBlock::ReasoningContent(ReasoningContentBlock::ReasoningText(reasoning)) => {
+                reasoning_content.push_str(reasoning.text());
+            }
+            ContentBlock::ToolUse(tool_use) => {
+                tool_calls.push(tool_use_block_to_tool_call(tool_use)?);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(Message::builder()
+        .role("assistant".to_string())
+        .content(non_empty(content))
+        .reasoning_content(non_empty(reasoning_content))
+        .tool_calls(if tool_calls.is_empty() {
+            None
+        } else {
+            Some(tool_calls)
+        })
+        .build())
+}
+
+pub fn converse_output_to_chat_completions_response(
+    id: String,
+    created: i64,
+    model: String,
+    content_blocks: &[ContentBlock],
+    stop_reason: &StopReason,
+    usage: Option<&TokenUsage>,
+) -> Result<ChatCompletionsResponse, serde_json::Error> {
+    let message = content_blocks_to_message(content_blocks)?;
+
+    let choice = ChoiceBuilder::default()
+        .message(Some(message))
+        .finish_reason(stop_reason_to_finish_reason(stop_reason))
+        .build();
+
+    let usage = usage.map(|u| {
+        UsageBuilder::default()
+            .completion_tokens(u.output_tokens)
+            .prompt_tokens(u.input_tokens)
+            .total_tokens(u.total_tokens)
+            .build()
+    });
+
+    Ok(ChatCompletionsResponse::builder()
+        .id(Some(id))
+        .created(Some(created))
+        .model(Some(model))
+        .object(Some("chat.completion".to_string()))
+        .choice(choice)
+        .usage(Some(usage.unwrap_or_default()))
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_bedrockruntime::types::ReasoningTextBlock;
+    use common::value_to_document;
+
+    fn usage() -> TokenUsage {
+        TokenUsage::builder()
+            .input_tokens(10)
+            .output_tokens(20)
+            .total_tokens(30)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn converse_output_maps_text_reasoning_and_usage() {
+        let blocks = vec![
+            ContentBlock::ReasoningContent(ReasoningContentBlock::ReasoningText(
+                ReasoningTextBlock::builder()
+                    .text("pondering")
+                    .build()
+                    .unwrap(),
+            )),
+            ContentBlock::Text("hello".to_string()),
+        ];
+
+        let response = converse_output_to_chat_completions_response(
+            "chatcmpl-1".to_string(),
+            1700000000,
+            "claude".to_string(),
+            &blocks,
+            &StopReason::EndTurn,
+            Some(&usage()),
+        )
+        .unwrap();
+
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["object"], "chat.completion");
+        assert_eq!(json["model"], "claude");
+        assert_eq!(json["choices"][0]["index"], 0);
+        assert_eq!(json["choices"][0]["finish_reason"], "stop");
+        assert!(json["choices"][0].get("delta").is_none());
+        assert_eq!(json["choices"][0]["message"]["role"], "assistant");
+        assert_eq!(json["choices"][0]["message"]["content"], "hello");
+        assert_eq!(
+            json["choices"][0]["message"]["reasoning_content"],
+            "pondering"
+        );
+        assert_eq!(json["usage"]["prompt_tokens"], 10);
+        assert_eq!(json["usage"]["completion_tokens"], 20);
+        assert_eq!(json["usage"]["total_tokens"], 30);
+    }
+
+    #[test]
+    fn converse_output_maps_tool_use_to_tool_calls() {
+        let blocks = vec![ContentBlock::ToolUse(
+            ToolUseBlock::builder()
+                .tool_use_id("call_1")
+                .name("get_weather")
+                .input(value_to_document(&serde_json::json!({"city": "NYC"})))
+                .build()
+                .unwrap(),
+        )];
+
+        let response = converse_output_to_chat_completions_response(
+            "chatcmpl-1".to_string(),
+            1700000000,
+            "claude".to_string(),
+            &blocks,
+            &StopReason::ToolUse,
+            None,
+        )
+        .unwrap();
+
+        let json = serde_json::to_value(&response).unwrap();
+        let message = &json["choices"][0]["message"];
+        assert_eq!(json["choices"][0]["finish_reason"], "tool_calls");
+        assert!(message["content"].is_null());
+        assert_eq!(message["tool_calls"][0]["id"], "call_1");
+        assert_eq!(message["tool_calls"][0]["type"], "function");
+        assert_eq!(message["tool_calls"][0]["function"]["name"], "get_weather");
+        assert!(message["tool_calls"][0].get("index").is_none());
+        let arguments: serde_json::Value = serde_json::from_str(
+            message["tool_calls"][0]["function"]["arguments"]
+                .as_str()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(arguments, serde_json::json!({"city": "NYC"}));
+        assert_eq!(json["usage"]["total_tokens"], 0);
+    }
+}
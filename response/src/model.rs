@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+/// A model entry as returned by OpenAI's `GET /models`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Model {
+    pub id: String,
+    pub object: String,
+    /// Unix timestamp (seconds) of the model's release.
+    pub created: i64,
+    pub owned_by: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ModelList {
+    pub object: String,
+    pub data: Vec<Model>,
+}
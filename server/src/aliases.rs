@@ -0,0 +1,157 @@
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::utils::wildcard_capture;
+
+/// An entry of the `[[model_aliases]]` table in `config.toml`.
+///
+/// `pattern` is either an exact model name or contains a single `*` wildcard,
+/// e.g. `claude-sonnet-4-5*`. A `*` in `target` is replaced by the text the
+/// wildcard matched, so `claude-*` → `us.anthropic.claude-*-v1:0` maps
+/// `claude-sonnet-4-5-20250929` to `us.anthropic.claude-sonnet-4-5-20250929-v1:0`.
+/// `target` may be a Bedrock model ID, an inference-profile ID, or an ARN.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelAlias {
+    pub pattern: String,
+    pub target: String,
+}
+
+impl ModelAlias {
+    fn is_exact(&self) -> bool {
+        !self.pattern.contains('*')
+    }
+
+    fn apply(&self, model: &str) -> Option<String> {
+        wildcard_capture(&self.pattern, model).map(|captured| self.target.replace('*', captured))
+    }
+}
+
+/// Rewrites client-facing model names to Bedrock model IDs. Exact aliases
+/// take precedence over wildcard rules; wildcard rules are tried in config
+/// order and the first match wins. Unmatched names pass through unchanged.
+#[derive(Clone, Debug, Default)]
+pub struct ModelAliases {
+    aliases: Vec<ModelAlias>,
+}
+
+impl ModelAliases {
+    pub fn new(aliases: Vec<ModelAlias>) -> anyhow::Result<Self> {
+        for alias in &aliases {
+            if alias.pattern.matches('*').count() > 1 {
+                bail!(
+                    "model alias pattern {:?} has more than one '*'",
+                    alias.pattern
+                );
+            }
+            if alias.is_exact() && alias.target.contains('*') {
+                bail!(
+                    "model alias target {:?} uses '*' but pattern {:?} has no wildcard",
+                    alias.target,
+                    alias.pattern
+                );
+            }
+        }
+        Ok(Self { aliases })
+    }
+
+    pub fn resolve(&self, model: &str) -> Option<String> {
+        self.aliases
+            .iter()
+            .filter(|alias| alias.is_exact())
+            .chain(self.aliases.iter().filter(|alias| !alias.is_exact()))
+            .find_map(|alias| alias.apply(model))
+    }
+
+    /// Rewrites `model` in place and returns the name the client asked for,
+    /// which is what responses must echo back.
+    pub fn rewrite(&self, model: &mut String) -> String {
+        let requested = model.clone();
+        if let Some(target) = self.resolve(model) {
+            info!("Model alias {} -> {}", requested, target);
+            *model = target;
+        }
+        requested
+    }
+
+    /// Aliases without a wildcard, as `(alias, target)` pairs.
+    pub fn exact(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.aliases
+            .iter()
+            .filter(|alias| alias.is_exact())
+            .map(|alias| (alias.pattern.as_str(), alias.target.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alias(pattern: &str, target: &str) -> ModelAlias {
+        ModelAlias {
+            pattern: pattern.to_string(),
+            target: target.to_string(),
+        }
+    }
+
+    #[test]
+    fn exact_alias_wins_over_earlier_wildcard() {
+        let aliases = ModelAliases::new(vec![
+            alias("claude-*", "us.anthropic.claude-*-v1:0"),
+            alias("claude-opus-4-8", "global.anthropic.claude-opus-4-8"),
+        ])
+        .unwrap();
+        assert_eq!(
+            aliases.resolve("claude-opus-4-8").as_deref(),
+            Some("global.anthropic.claude-opus-4-8")
+        );
+    }
+
+    #[test]
+    fn wildcard_substitutes_captured_text() {
+        let aliases =
+            ModelAliases::new(vec![alias("claude-*", "us.anthropic.claude-*-v1:0")]).unwrap();
+        assert_eq!(
+            aliases.resolve("claude-sonnet-4-5-20250929").as_deref(),
+            Some("us.anthropic.claude-sonnet-4-5-20250929-v1:0")
+        );
+        assert_eq!(aliases.resolve("gpt-4o"), None);
+    }
+
+    #[test]
+    fn wildcard_with_suffix_does_not_overlap() {
+        let aliases = ModelAliases::new(vec![alias("ab*ba", "x-*")]).unwrap();
+        assert_eq!(aliases.resolve("aba"), None);
+        assert_eq!(aliases.resolve("abba").as_deref(), Some("x-"));
+        assert_eq!(aliases.resolve("ab-ba").as_deref(), Some("x--"));
+    }
+
+    #[test]
+    fn wildcard_target_maps_to_fixed_model() {
+        let aliases = ModelAliases::new(vec![alias(
+            "gpt-4*",
+            "arn:aws:bedrock:us-east-1:123456789012:inference-profile/us.anthropic.claude-sonnet-4-5-20250929-v1:0",
+        )])
+        .unwrap();
+        assert!(aliases.resolve("gpt-4o-mini").unwrap().starts_with("arn:"));
+    }
+
+    #[test]
+    fn rewrite_returns_requested_name() {
+        let aliases =
+            ModelAliases::new(vec![alias("fast", "us.anthropic.claude-haiku-4-5")]).unwrap();
+        let mut model = "fast".to_string();
+        assert_eq!(aliases.rewrite(&mut model), "fast");
+        assert_eq!(model, "us.anthropic.claude-haiku-4-5");
+
+        let mut model = "us.anthropic.claude-haiku-4-5".to_string();
+        assert_eq!(aliases.rewrite(&mut model), "us.anthropic.claude-haiku-4-5");
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        assert!(ModelAliases::new(vec![alias("a*b*", "x")]).is_err());
+        assert!(ModelAliases::new(vec![alias("a", "x-*")]).is_err());
+    }
+}
@@ -0,0 +1,507 @@
+use anyhow::{Context, bail};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, header::AUTHORIZATION},
+    middleware::Next,
+    response::Response,
+};
+use chat::error::ErrorKind;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+use tracing::{Span, warn};
+
+use crate::{
+    AppState,
+    error::{AppError, Protocol},
+    identity::AwsIdentity,
+    model_access::ModelRules,
+    rate_limit::RateLimits,
+    tls::TlsConnectInfo,
+};
+
+const HASH_PREFIX: &str = "sha256:";
+
+/// The name of [`Principal::anonymous`], which configured keys may not use.
+const ANONYMOUS: &str = "anonymous";
+
+/// An entry of the `[[api_keys]]` table in `config.toml`. Keys are stored as
+/// `sha256:<hex digest>` so the config file never holds a usable secret.
+/// Over mutual TLS, a client certificate whose subject equals
+/// `client_cert_subject` identifies the same principal without a key.
+/// `admin` keys may call the `/admin` endpoints. With an `identity`, the
+/// key's Bedrock calls are made as that AWS role or profile. A key with a
+/// `tenant` runs with that entry of `[[tenants]]`. `models` restricts the
+/// models the key may use, on top of its tenant's restrictions.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(from = "ApiKeyEntry", into = "ApiKeyEntry")]
+pub struct ApiKeyConfig {
+    pub name: String,
+    pub key_hash: Option<String>,
+    pub client_cert_subject: Option<String>,
+    pub admin: bool,
+    pub limits: RateLimits,
+    pub identity: Option<AwsIdentity>,
+    pub tenant: Option<String>,
+    pub models: ModelRules,
+}
+
+/// The flat TOML form of [`ApiKeyConfig`]. serde cannot reject unknown
+/// fields of a struct with a `flatten`ed member, so the limits are spelled
+/// out here.
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ApiKeyEntry {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    client_cert_subject: Option<String>,
+    #[serde(default)]
+    admin: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    requests_per_minute: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_tokens_per_minute: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_tokens_per_minute: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_concurrent_requests: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    role_arn: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    aws_profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    external_id: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    session_tags: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tenant: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    allowed_models: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    denied_models: Vec<String>,
+}
+
+impl From<ApiKeyEntry> for ApiKeyConfig {
+    fn from(entry: ApiKeyEntry) -> Self {
+        Self {
+            identity: AwsIdentity::new(
+                &entry.name,
+                entry.role_arn,
+                entry.aws_profile,
+                entry.external_id,
+                entry.session_tags,
+            ),
+            name: entry.name,
+            key_hash: entry.key_hash,
+            client_cert_subject: entry.client_cert_subject,
+            admin: entry.admin,
+            limits: RateLimits {
+                requests_per_minute: entry.requests_per_minute,
+                input_tokens_per_minute: entry.input_tokens_per_minute,
+                output_tokens_per_minute: entry.output_tokens_per_minute,
+                max_concurrent_requests: entry.max_concurrent_requests,
+            },
+            tenant: entry.tenant,
+            models: ModelRules {
+                allowed_models: entry.allowed_models,
+                denied_models: entry.denied_models,
+            },
+        }
+    }
+}
+
+impl From<ApiKeyConfig> for ApiKeyEntry {
+    fn from(config: ApiKeyConfig) -> Self {
+        let identity = config.identity.unwrap_or_default();
+        Self {
+            name: config.name,
+            key_hash: config.key_hash,
+            client_cert_subject: config.client_cert_subject,
+            admin: config.admin,
+            requests_per_minute: config.limits.requests_per_minute,
+            input_tokens_per_minute: config.limits.input_tokens_per_minute,
+            output_tokens_per_minute: config.limits.output_tokens_per_minute,
+            max_concurrent_requests: config.limits.max_concurrent_requests,
+            role_arn: identity.role_arn,
+            aws_profile: identity.profile,
+            external_id: identity.external_id,
+            session_tags: identity.session_tags,
+            tenant: config.tenant,
+            allowed_models: config.models.allowed_models,
+            denied_models: config.models.denied_models,
+        }
+    }
+}
+
+/// The caller a request is attributed to, available to handlers as an
+/// `Extension<Principal>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Principal {
+    pub name: String,
+    pub admin: bool,
+    pub limits: RateLimits,
+    /// The AWS identity Bedrock is called as; the tenant's or the proxy's own
+    /// if `None`.
+    pub identity: Option<AwsIdentity>,
+    /// The name of the principal's entry in `[[tenants]]`.
+    pub tenant: Option<String>,
+    pub models: ModelRules,
+}
+
+impl Principal {
+    /// The principal of every request when no API keys are configured.
+    pub fn anonymous() -> Self {
+        Self {
+            name: ANONYMOUS.to_string(),
+            admin: false,
+            limits: RateLimits::default(),
+            identity: None,
+            tenant: None,
+            models: ModelRules::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ApiKeys {
+    keys: HashMap<[u8; 32], Principal>,
+    client_cert_subjects: HashMap<String, Principal>,
+}
+
+impl ApiKeys {
+    pub fn new(configs: Vec<ApiKeyConfig>) -> anyhow::Result<Self> {
+        let mut keys = HashMap::new();
+        let mut client_cert_subjects = HashMap::new();
+        let mut names = HashSet::new();
+        for config in configs {
+            if config.name.is_empty() {
+                bail!("api keys must have a non-empty name");
+            }
+            if config.name == ANONYMOUS {
+                bail!("api key name {ANONYMOUS:?} is reserved");
+            }
+            if !names.insert(config.name.clone()) {
+                bail!("duplicate api key name {:?}", config.name);
+            }
+            if config.key_hash.is_none() && config.client_cert_subject.is_none() {
+                bail!(
+                    "api key {:?}: set key_hash, client_cert_subject or both",
+                    config.name
+                );
+            }
+            let digest = config
+                .key_hash
+                .as_deref()
+                .map(|key_hash| {
+                    key_hash
+                        .strip_prefix(HASH_PREFIX)
+                        .with_context(|| {
+                            format!(
+                                "api key {:?}: key_hash must start with {HASH_PREFIX:?}",
+                                config.name
+                            )
+                        })
+                        .and_then(|hex_digest| {
+                            let bytes = hex::decode(hex_digest)?;
+                            <[u8; 32]>::try_from(bytes.as_slice())
+                                .map_err(|_| anyhow::anyhow!("expected a 32-byte digest"))
+                        })
+                        .with_context(|| format!("api key {:?}: invalid key_hash", config.name))
+                })
+                .transpose()?;
+            let RateLimits {
+                requests_per_minute,
+                input_tokens_per_minute,
+                output_tokens_per_minute,
+                max_concurrent_requests,
+            } = config.limits;
+            if [
+                requests_per_minute,
+                input_tokens_per_minute,
+                output_tokens_per_minute,
+                max_concurrent_requests,
+            ]
+            .contains(&Some(0))
+            {
+                bail!("api key {:?}: limits must be positive", config.name);
+            }
+            if let Some(identity) = &config.identity {
+                identity
+                    .validate()
+                    .with_context(|| format!("api key {:?}", config.name))?;
+            }
+            config
+                .models
+                .validate()
+                .with_context(|| format!("api key {:?}", config.name))?;
+            let principal = Principal {
+                name: config.name,
+                admin: config.admin,
+                limits: config.limits,
+                identity: config.identity,
+                tenant: config.tenant,
+                models: config.models,
+            };
+            if let Some(digest) = digest
+                && keys.insert(digest, principal.clone()).is_some()
+            {
+                bail!(
+                    "duplicate api key hash {:?}",
+                    config.key_hash.unwrap_or_default()
+                );
+            }
+            if let Some(subject) = config.client_cert_subject
+                && client_cert_subjects
+                    .insert(subject.clone(), principal)
+                    .is_some()
+            {
+                bail!("duplicate client_cert_subject {:?}", subject);
+            }
+        }
+        Ok(Self {
+            keys,
+            client_cert_subjects,
+        })
+    }
+
+    /// Authentication is disabled when no keys are configured.
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty() || !self.client_cert_subjects.is_empty()
+    }
+
+    pub fn authenticate(&self, key: &str) -> Option<&Principal> {
+        self.keys.get(Sha256::digest(key.as_bytes()).as_slice())
+    }
+
+    /// The principal of a verified client certificate with this subject.
+    pub fn authenticate_client_cert(&self, subject: &str) -> Option<&Principal> {
+        self.client_cert_subjects.get(subject)
+    }
+}
+
+/// The `key_hash` config value for `key`.
+pub fn hash_api_key(key: &str) -> String {
+    format!(
+        "{HASH_PREFIX}{}",
+        hex::encode(Sha256::digest(key.as_bytes()))
+    )
+}
+
+/// Anthropic clients send `x-api-key`; OpenAI clients send
+/// `Authorization: Bearer`. Either is accepted on every route.
+fn presented_key(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get("x-api-key")
+        .and_then(|value| value.to_str().ok())
+        .or_else(|| {
+            headers
+                .get(AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+        })
+        .map(str::trim)
+}
+
+/// Resolves the caller's [`Principal`] and attaches it to the request
+/// extensions. Failures are rendered in the error envelope of the protocol
+/// the route speaks.
+pub async fn authenticate(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let protocol = Protocol::from_path(request.uri().path());
+
+    let settings = state.settings.load();
+    let principal = if settings.api_keys.is_enabled() {
+        let client_subject = request
+            .extensions()
+            .get::<ConnectInfo<TlsConnectInfo>>()
+            .and_then(|ConnectInfo(info)| info.client_subject.as_deref());
+        let client_cert_principal =
+            client_subject.and_then(|subject| settings.api_keys.authenticate_client_cert(subject));
+        let principal = match (client_cert_principal, presented_key(request.headers())) {
+            (Some(principal), _) => Ok(principal.clone()),
+            (None, Some(key)) => settings
+                .api_keys
+                .authenticate(key)
+                .cloned()
+                .ok_or("invalid API key"),
+            (None, None) if client_subject.is_some() => {
+                Err("client certificate is not mapped to a principal and no API key was sent")
+            }
+            (None, None) => {
+                Err("missing API key: set the x-api-key or Authorization: Bearer header")
+            }
+        };
+        if let (Some(subject), Err(_)) = (client_subject, &principal) {
+            warn!("No principal for client certificate subject {:?}", subject);
+        }
+        match principal {
+            Ok(principal) => principal,
+            Err(message) => {
+                warn!("Rejected {} request: {}", request.uri().path(), message);
+                return AppError::new(ErrorKind::Authentication, message)
+                    .into_response_for(protocol);
+            }
+        }
+    } else {
+        Principal::anonymous()
+    };
+
+    drop(settings);
+    Span::current().record("principal", principal.name.as_str());
+    request.extensions_mut().insert(principal);
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configured_hash_authenticates_matching_key() {
+        let keys = ApiKeys::new(vec![ApiKeyConfig {
+            name: "alice".to_string(),
+            key_hash: Some(hash_api_key("sk-alice")),
+            client_cert_subject: None,
+            admin: false,
+            limits: RateLimits::default(),
+            identity: None,
+            tenant: None,
+            models: ModelRules::default(),
+        }])
+        .unwrap();
+
+        assert!(keys.is_enabled());
+        assert_eq!(keys.authenticate("sk-alice").unwrap().name, "alice");
+        assert!(keys.authenticate("sk-bob").is_none());
+    }
+
+    #[test]
+    fn malformed_or_duplicate_hashes_are_rejected() {
+        let config = |key_hash: &str| ApiKeyConfig {
+            name: "alice".to_string(),
+            key_hash: Some(key_hash.to_string()),
+            client_cert_subject: None,
+            admin: false,
+            limits: RateLimits::default(),
+            identity: None,
+            tenant: None,
+            models: ModelRules::default(),
+        };
+        assert!(ApiKeys::new(vec![config("sk-alice")]).is_err());
+        assert!(ApiKeys::new(vec![config("sha256:abcd")]).is_err());
+        assert!(
+            ApiKeys::new(vec![ApiKeyConfig {
+                limits: RateLimits {
+                    requests_per_minute: Some(0),
+                    ..Default::default()
+                },
+                ..config(&hash_api_key("sk-alice"))
+            }])
+            .is_err()
+        );
+        assert!(
+            ApiKeys::new(vec![
+                config(&hash_api_key("sk-alice")),
+                ApiKeyConfig {
+                    name: "alice-2".to_string(),
+                    ..config(&hash_api_key("sk-alice"))
+                },
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn client_cert_subject_identifies_principal_without_key() {
+        let config = |client_cert_subject: Option<&str>| ApiKeyConfig {
+            name: "ci".to_string(),
+            key_hash: None,
+            client_cert_subject: client_cert_subject.map(str::to_string),
+            admin: false,
+            limits: RateLimits::default(),
+            identity: None,
+            tenant: None,
+            models: ModelRules::default(),
+        };
+        let keys = ApiKeys::new(vec![config(Some("O=Acme, CN=ci"))]).unwrap();
+        assert!(keys.is_enabled());
+        assert_eq!(
+            keys.authenticate_client_cert("O=Acme, CN=ci").unwrap().name,
+            "ci"
+        );
+        assert!(keys.authenticate_client_cert("CN=ci").is_none());
+
+        assert!(ApiKeys::new(vec![config(None)]).is_err());
+        assert!(
+            ApiKeys::new(vec![
+                config(Some("O=Acme, CN=ci")),
+                ApiKeyConfig {
+                    name: "ci-2".to_string(),
+                    ..config(Some("O=Acme, CN=ci"))
+                },
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn empty_duplicate_or_reserved_names_are_rejected() {
+        let config = |name: &str, key: &str| ApiKeyConfig {
+            name: name.to_string(),
+            key_hash: Some(hash_api_key(key)),
+            ..Default::default()
+        };
+        assert!(ApiKeys::new(vec![config("", "sk-alice")]).is_err());
+        assert!(ApiKeys::new(vec![config("anonymous", "sk-alice")]).is_err());
+        assert!(
+            ApiKeys::new(vec![config("alice", "sk-alice"), config("alice", "sk-bob")]).is_err()
+        );
+        assert!(ApiKeys::new(vec![config("alice", "sk-alice"), config("bob", "sk-bob")]).is_ok());
+    }
+
+    #[test]
+    fn aws_identity_is_read_from_key_entry() {
+        let config: ApiKeyConfig = toml::from_str(
+            r#"
+            name = "search"
+            key_hash = "sha256:0000000000000000000000000000000000000000000000000000000000000000"
+            role_arn = "arn:aws:iam::123456789012:role/search"
+            session_tags = { team = "search" }
+            "#,
+        )
+        .unwrap();
+        let identity = config.identity.clone().unwrap();
+        assert_eq!(identity.session_name, "llm-proxy-search");
+        assert_eq!(identity.session_tags["team"], "search");
+        assert!(ApiKeys::new(vec![config]).is_ok());
+
+        let profile_with_tags: ApiKeyConfig = toml::from_str(
+            r#"
+            name = "search"
+            key_hash = "sha256:0000000000000000000000000000000000000000000000000000000000000000"
+            aws_profile = "search"
+            session_tags = { team = "search" }
+            "#,
+        )
+        .unwrap();
+        assert!(ApiKeys::new(vec![profile_with_tags]).is_err());
+    }
+
+    #[test]
+    fn bearer_token_is_accepted_when_x_api_key_is_absent() {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "Bearer sk-alice".parse().unwrap());
+        assert_eq!(presented_key(&headers), Some("sk-alice"));
+
+        headers.insert("x-api-key", "sk-bob".parse().unwrap());
+        assert_eq!(presented_key(&headers), Some("sk-bob"));
+    }
+}
@@ -0,0 +1,605 @@
+use anyhow::bail;
+use aws_config::{BehaviorVersion, Region, SdkConfig, retry::RetryConfig, sts::AssumeRoleProvider};
+use aws_sdk_bedrockruntime::Client;
+use chat::error::ErrorKind;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+use crate::{
+    error::AppError, identity::AwsIdentity, metrics::record_bedrock_client, utils::client_region,
+};
+
+const ASSUME_ROLE_SESSION_NAME: &str = "llm-proxy";
+
+/// The `[bedrock_pool]` table of `config.toml`. Without `clients`, every
+/// request goes to the single client built from the default AWS
+/// configuration.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BedrockPoolConfig {
+    #[serde(default)]
+    pub routing: RoutingStrategy,
+    /// Consecutive throttling or server errors after which a client is
+    /// taken out of rotation.
+    #[serde(default = "default_unhealthy_after")]
+    pub unhealthy_after: u32,
+    /// How long an unhealthy client is skipped before it is tried again.
+    #[serde(default = "default_unhealthy_cooldown_secs")]
+    pub unhealthy_cooldown_secs: u64,
+    #[serde(default)]
+    pub clients: Vec<BedrockClientConfig>,
+}
+
+impl Default for BedrockPoolConfig {
+    fn default() -> Self {
+        Self {
+            routing: RoutingStrategy::default(),
+            unhealthy_after: default_unhealthy_after(),
+            unhealthy_cooldown_secs: default_unhealthy_cooldown_secs(),
+            clients: Vec::new(),
+        }
+    }
+}
+
+fn default_unhealthy_after() -> u32 {
+    3
+}
+
+fn default_unhealthy_cooldown_secs() -> u64 {
+    30
+}
+
+/// How a request picks among the healthy clients.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutingStrategy {
+    #[default]
+    RoundRobin,
+    /// The client with the fewest requests in flight, streams included.
+    LeastOutstanding,
+    /// Round robin in proportion to each client's `weight`.
+    Weighted,
+}
+
+/// An entry of `[[bedrock_pool.clients]]`: a region, optionally with a named
+/// AWS profile and a role to assume, so that clients can span accounts.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BedrockClientConfig {
+    /// Labels the client in logs and metrics and keys its adaptive throttle;
+    /// defaults to the region, or `<region>/<profile>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub region: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role_arn: Option<String>,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+impl BedrockClientConfig {
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| match &self.profile {
+            Some(profile) => format!("{}/{}", self.region, profile),
+            None => self.region.clone(),
+        })
+    }
+
+    /// Loads the AWS configuration for this client: its region, the
+    /// profile's credentials or the default chain, and the assumed role's
+    /// credentials on top. `retry_config` applies as for the default client.
+    async fn load(&self, retry_config: RetryConfig) -> SdkConfig {
+        let region = Region::new(self.region.clone());
+        let loader = || {
+            let loader = aws_config::defaults(BehaviorVersion::latest())
+                .retry_config(retry_config.clone())
+                .region(region.clone());
+            match &self.profile {
+                Some(profile) => loader.profile_name(profile),
+                None => loader,
+            }
+        };
+        let sdk_config = loader().load().await;
+        let Some(role_arn) = &self.role_arn else {
+            return sdk_config;
+        };
+        let provider = AssumeRoleProvider::builder(role_arn)
+            .session_name(ASSUME_ROLE_SESSION_NAME)
+            .region(region.clone())
+            .configure(&sdk_config)
+            .build()
+            .await;
+        loader().credentials_provider(provider).load().await
+    }
+}
+
+impl BedrockPoolConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.unhealthy_after == 0 {
+            bail!("bedrock_pool.unhealthy_after must be positive");
+        }
+        let mut names = HashSet::new();
+        for client in &self.clients {
+            let name = client.name();
+            if client.region.is_empty() {
+                bail!("bedrock_pool client {:?}: region must not be empty", name);
+            }
+            if client.weight == 0 {
+                bail!("bedrock_pool client {:?}: weight must be positive", name);
+            }
+            if !names.insert(name.clone()) {
+                bail!("duplicate bedrock_pool client {:?}", name);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    consecutive_failures: u32,
+    unhealthy_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct Member {
+    name: String,
+    client: Client,
+    /// The AWS configuration `client` was built from.
+    source: SdkConfig,
+    weight: u32,
+    outstanding: AtomicUsize,
+    health: Mutex<Health>,
+    /// This client's counterparts for principals with their own identity.
+    identities: Mutex<HashMap<AwsIdentity, Client>>,
+}
+
+impl Member {
+    fn is_healthy(&self, now: Instant) -> bool {
+        self.health
+            .lock()
+            .unwrap()
+            .unhealthy_until
+            .is_none_or(|until| until <= now)
+    }
+
+    async fn client_for(&self, identity: &AwsIdentity) -> Client {
+        if let Some(client) = self.identities.lock().unwrap().get(identity) {
+            return client.clone();
+        }
+        let client = identity.client(&self.client, &self.source).await;
+        info!(
+            "Bedrock client {} will call as {} for session {}",
+            self.name,
+            identity.label(),
+            identity.session_name
+        );
+        self.identities
+            .lock()
+            .unwrap()
+            .entry(identity.clone())
+            .or_insert(client)
+            .clone()
+    }
+
+    fn record_metrics(&self) {
+        record_bedrock_client(
+            &self.name,
+            self.outstanding.load(Ordering::Relaxed),
+            self.is_healthy(Instant::now()),
+        );
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    routing: RoutingStrategy,
+    unhealthy_after: u32,
+    unhealthy_cooldown: Duration,
+    members: Vec<Member>,
+    next: AtomicUsize,
+}
+
+/// Bedrock runtime clients across regions and accounts. Each request leases
+/// one, picked by the configured [`RoutingStrategy`] among the healthy
+/// clients; a client that keeps failing with throttling or server errors is
+/// skipped for a cooldown. When every client is unhealthy all of them are
+/// candidates again, since a request that might succeed beats a certain
+/// error.
+#[derive(Clone, Debug)]
+pub struct BedrockPool {
+    inner: Arc<Inner>,
+}
+
+impl From<Client> for BedrockPool {
+    /// A pool of one client, named after its region.
+    fn from(client: Client) -> Self {
+        let name = client_region(&client).to_string();
+        let source = source_config(&client);
+        Self::new(
+            &BedrockPoolConfig::default(),
+            vec![(name, client, source, 1)],
+        )
+    }
+}
+
+impl BedrockPool {
+    fn new(config: &BedrockPoolConfig, clients: Vec<(String, Client, SdkConfig, u32)>) -> Self {
+        let members = clients
+            .into_iter()
+            .map(|(name, client, source, weight)| Member {
+                name,
+                client,
+                source,
+                weight,
+                outstanding: AtomicUsize::new(0),
+                health: Mutex::new(Health::default()),
+                identities: Mutex::new(HashMap::new()),
+            })
+            .collect::<Vec<_>>();
+        members.iter().for_each(Member::record_metrics);
+        Self {
+            inner: Arc::new(Inner {
+                routing: config.routing,
+                unhealthy_after: config.unhealthy_after,
+                unhealthy_cooldown: Duration::from_secs(config.unhealthy_cooldown_secs),
+                members,
+                next: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Builds one client per configured entry, or a single client from
+    /// `default` when there are none.
+    pub async fn connect(config: &BedrockPoolConfig, default: &SdkConfig) -> anyhow::Result<Self> {
+        config.validate()?;
+        if config.clients.is_empty() {
+            let client = Client::new(default);
+            let name = client_region(&client).to_string();
+            return Ok(Self::new(config, vec![(name, client, default.clone(), 1)]));
+        }
+        let retry_config = default
+            .retry_config()
+            .cloned()
+            .unwrap_or_else(RetryConfig::standard);
+        let mut clients = Vec::with_capacity(config.clients.len());
+        for client_config in &config.clients {
+            let name = client_config.name();
+            let sdk_config = client_config.load(retry_config.clone()).await;
+            info!(
+                "Bedrock client {} in {}{}",
+                name,
+                client_config.region,
+                client_config
+                    .role_arn
+                    .as_ref()
+                    .map(|role_arn| format!(" as {role_arn}"))
+                    .unwrap_or_default()
+            );
+            clients.push((
+                name,
+                Client::new(&sdk_config),
+                sdk_config,
+                client_config.weight,
+            ));
+        }
+        Ok(Self::new(config, clients))
+    }
+
+    /// A pool of `clients` by name and weight, for tests.
+    pub fn with_clients(config: &BedrockPoolConfig, clients: Vec<(String, Client, u32)>) -> Self {
+        assert!(!clients.is_empty(), "a Bedrock pool needs a client");
+        let clients = clients
+            .into_iter()
+            .map(|(name, client, weight)| {
+                let source = source_config(&client);
+                (name, client, source, weight)
+            })
+            .collect();
+        Self::new(config, clients)
+    }
+
+    /// Picks a client for one request.
+    pub fn lease(&self) -> BedrockLease {
+        let inner = &self.inner;
+        let now = Instant::now();
+        let healthy = inner
+            .members
+            .iter()
+            .enumerate()
+            .filter(|(_, member)| member.is_healthy(now))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        let candidates = if healthy.is_empty() {
+            (0..inner.members.len()).collect()
+        } else {
+            healthy
+        };
+        let turn = inner.next.fetch_add(1, Ordering::Relaxed);
+        let index = match inner.routing {
+            RoutingStrategy::RoundRobin => candidates[turn % candidates.len()],
+            // Ties go to the next client in turn, so an idle pool still
+            // spreads requests.
+            RoutingStrategy::LeastOutstanding => (0..candidates.len())
+                .map(|offset| candidates[(turn + offset) % candidates.len()])
+                .min_by_key(|&index| inner.members[index].outstanding.load(Ordering::Relaxed))
+                .unwrap(),
+            RoutingStrategy::Weighted => {
+                let total: usize = candidates
+                    .iter()
+                    .map(|&index| inner.members[index].weight as usize)
+                    .sum();
+                let mut slot = turn % total;
+                *candidates
+                    .iter()
+                    .find(|&&index| {
+                        let weight = inner.members[index].weight as usize;
+                        if slot < weight {
+                            return true;
+                        }
+                        slot -= weight;
+                        false
+                    })
+                    .unwrap()
+            }
+        };
+        let member = &inner.members[index];
+        member.outstanding.fetch_add(1, Ordering::Relaxed);
+        member.record_metrics();
+        BedrockLease {
+            inner: inner.clone(),
+            index,
+            client: member.client.clone(),
+            name: member.name.clone(),
+            identity: false,
+        }
+    }
+
+    /// Picks a client for one request made as `identity`, if any. Each
+    /// client of the pool gets a counterpart per identity, built on first
+    /// use and kept so its credentials stay cached.
+    pub async fn lease_for(&self, identity: Option<&AwsIdentity>) -> BedrockLease {
+        let mut lease = self.lease();
+        if let Some(identity) = identity {
+            lease.client = lease.member().client_for(identity).await;
+            lease.name = format!("{}/{}", lease.name, identity.label());
+            lease.identity = true;
+        }
+        lease
+    }
+}
+
+/// What can be recovered of the AWS configuration of a client built
+/// elsewhere, e.g. a test's: everything but its credentials, which the
+/// client does not expose. Assuming a role needs [`BedrockPool::connect`].
+fn source_config(client: &Client) -> SdkConfig {
+    let config = client.config();
+    let mut builder = SdkConfig::builder().behavior_version(BehaviorVersion::latest());
+    builder
+        .set_region(config.region().cloned())
+        .set_http_client(config.http_client())
+        .set_sleep_impl(config.sleep_impl())
+        .set_time_source(Some(config.time_source().unwrap_or_default()));
+    builder.build()
+}
+
+/// A client picked for one request, counted as outstanding until dropped.
+#[derive(Debug)]
+pub struct BedrockLease {
+    inner: Arc<Inner>,
+    index: usize,
+    client: Client,
+    name: String,
+    /// Whether the lease is for a principal's own identity.
+    identity: bool,
+}
+
+impl BedrockLease {
+    fn member(&self) -> &Member {
+        &self.inner.members[self.index]
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// The client's name, followed by the identity's role or profile for a
+    /// principal with its own identity, whose quotas are its account's.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Feeds the outcome of the Bedrock call into the client's health.
+    /// Throttling and server errors count against it; anything else,
+    /// including a client error, shows the client is reachable. Throttling
+    /// under a principal's own identity is that account's, so it does not.
+    pub fn record<T>(&self, result: &Result<T, AppError>) {
+        let member = self.member();
+        let failed = result.as_ref().is_err_and(|error| {
+            (error.kind == ErrorKind::RateLimit && !self.identity)
+                || error.kind == ErrorKind::Overloaded
+                || error.status.is_server_error()
+        });
+        let mut health = member.health.lock().unwrap();
+        if !failed {
+            health.consecutive_failures = 0;
+            health.unhealthy_until = None;
+        } else {
+            health.consecutive_failures += 1;
+            if health.consecutive_failures >= self.inner.unhealthy_after {
+                health.consecutive_failures = 0;
+                health.unhealthy_until = Some(Instant::now() + self.inner.unhealthy_cooldown);
+                warn!(
+                    "Bedrock client {} failed {} times in a row, skipping it for {:?}",
+                    member.name, self.inner.unhealthy_after, self.inner.unhealthy_cooldown
+                );
+            }
+        }
+        drop(health);
+        member.record_metrics();
+    }
+
+    /// Keeps the lease outstanding until `stream` ends or is dropped.
+    pub fn hold<S: Stream>(self, stream: S) -> impl Stream<Item = S::Item> {
+        stream.map(move |item| {
+            let _lease = &self;
+            item
+        })
+    }
+}
+
+impl Drop for BedrockLease {
+    fn drop(&mut self) {
+        let member = self.member();
+        member.outstanding.fetch_sub(1, Ordering::Relaxed);
+        member.record_metrics();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_bedrockruntime::config::BehaviorVersion as ClientBehaviorVersion;
+    use std::collections::BTreeMap;
+
+    fn client(region: &str) -> Client {
+        Client::from_conf(
+            aws_sdk_bedrockruntime::Config::builder()
+                .behavior_version(ClientBehaviorVersion::latest())
+                .region(Region::new(region.to_string()))
+                .build(),
+        )
+    }
+
+    fn pool(routing: RoutingStrategy, weights: &[u32]) -> BedrockPool {
+        BedrockPool::with_clients(
+            &BedrockPoolConfig {
+                routing,
+                unhealthy_after: 2,
+                ..Default::default()
+            },
+            weights
+                .iter()
+                .enumerate()
+                .map(|(index, &weight)| (format!("c{index}"), client("us-east-1"), weight))
+                .collect(),
+        )
+    }
+
+    fn names(leases: &[BedrockLease]) -> Vec<&str> {
+        leases.iter().map(BedrockLease::name).collect()
+    }
+
+    fn throttled() -> Result<(), AppError> {
+        Err(AppError::new(ErrorKind::RateLimit, "throttled"))
+    }
+
+    #[test]
+    fn round_robin_and_weighted_rotate_through_clients() {
+        let pool = pool(RoutingStrategy::RoundRobin, &[1, 1, 1]);
+        let leases = (0..4).map(|_| pool.lease()).collect::<Vec<_>>();
+        assert_eq!(names(&leases), ["c0", "c1", "c2", "c0"]);
+
+        let pool = self::pool(RoutingStrategy::Weighted, &[2, 1]);
+        let leases = (0..6).map(|_| pool.lease()).collect::<Vec<_>>();
+        assert_eq!(names(&leases), ["c0", "c0", "c1", "c0", "c0", "c1"]);
+    }
+
+    #[test]
+    fn least_outstanding_prefers_idle_clients() {
+        let pool = pool(RoutingStrategy::LeastOutstanding, &[1, 1]);
+        let first = pool.lease();
+        let second = pool.lease();
+        assert_ne!(first.name(), second.name());
+
+        let idle = second.name().to_string();
+        drop(second);
+        assert_eq!(pool.lease().name(), idle);
+        assert_eq!(pool.lease().name(), idle);
+    }
+
+    #[test]
+    fn failing_client_is_skipped_until_it_recovers() {
+        let pool = pool(RoutingStrategy::RoundRobin, &[1, 1]);
+        for _ in 0..2 {
+            let lease = pool.lease();
+            assert_eq!(lease.name(), "c0");
+            lease.record(&throttled());
+            pool.lease().record(&Ok(()));
+        }
+        let leases = (0..3).map(|_| pool.lease()).collect::<Vec<_>>();
+        assert_eq!(names(&leases), ["c1", "c1", "c1"]);
+
+        // With every client unhealthy, all are tried again.
+        for _ in 0..2 {
+            pool.lease().record(&throttled());
+        }
+        let leases = (0..2).map(|_| pool.lease()).collect::<Vec<_>>();
+        let mut names = names(&leases);
+        names.sort();
+        assert_eq!(names, ["c0", "c1"]);
+    }
+
+    #[tokio::test]
+    async fn identity_clients_are_built_once_and_reused() {
+        let pool = pool(RoutingStrategy::RoundRobin, &[1]);
+        let role_arn = "arn:aws:iam::123456789012:role/search";
+        let identity = AwsIdentity::new(
+            "search",
+            Some(role_arn.to_string()),
+            None,
+            None,
+            BTreeMap::new(),
+        )
+        .unwrap();
+
+        let lease = pool.lease_for(Some(&identity)).await;
+        assert_eq!(lease.name(), format!("c0/{role_arn}"));
+        drop(lease);
+        pool.lease_for(Some(&identity)).await;
+        assert_eq!(pool.lease_for(None).await.name(), "c0");
+        assert_eq!(pool.inner.members[0].identities.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn invalid_pools_are_rejected() {
+        let client = |region: &str, weight: u32| BedrockClientConfig {
+            name: None,
+            region: region.to_string(),
+            profile: None,
+            role_arn: None,
+            weight,
+        };
+        let config = |clients| BedrockPoolConfig {
+            clients,
+            ..Default::default()
+        };
+        assert!(
+            config(vec![client("us-east-1", 1), client("us-west-2", 2)])
+                .validate()
+                .is_ok()
+        );
+        assert!(config(vec![client("us-east-1", 0)]).validate().is_err());
+        assert!(config(vec![client("", 1)]).validate().is_err());
+        assert!(
+            config(vec![client("us-east-1", 1), client("us-east-1", 1)])
+                .validate()
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,329 @@
+use anyhow::bail;
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use chat::error::ErrorKind;
+use chrono::{DateTime, Datelike, Days, Months, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, sync::Arc};
+use tracing::warn;
+
+use crate::{
+    AppState,
+    auth::Principal,
+    error::AppError,
+    ledger::{Spend, UsageLedger},
+    utils::reject,
+};
+
+/// Set on responses to keys past a soft budget limit, once per budget.
+pub const BUDGET_WARNING: HeaderName = HeaderName::from_static("x-budget-warning");
+
+/// A calendar period in UTC over which a budget's spend accumulates.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetPeriod {
+    Day,
+    Month,
+}
+
+impl BudgetPeriod {
+    pub const ALL: [BudgetPeriod; 2] = [BudgetPeriod::Day, BudgetPeriod::Month];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BudgetPeriod::Day => "day",
+            BudgetPeriod::Month => "month",
+        }
+    }
+
+    fn adjective(self) -> &'static str {
+        match self {
+            BudgetPeriod::Day => "daily",
+            BudgetPeriod::Month => "monthly",
+        }
+    }
+
+    /// The start of the period containing `time`.
+    pub fn start(self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let day = match self {
+            BudgetPeriod::Day => time.day(),
+            BudgetPeriod::Month => 1,
+        };
+        Utc.with_ymd_and_hms(time.year(), time.month(), day, 0, 0, 0)
+            .unwrap()
+    }
+
+    /// The start of the period after the one containing `time`.
+    pub fn end(self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let start = self.start(time);
+        match self {
+            BudgetPeriod::Day => start + Days::new(1),
+            BudgetPeriod::Month => start + Months::new(1),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetUnit {
+    Tokens,
+    Usd,
+}
+
+impl BudgetUnit {
+    fn as_str(self) -> &'static str {
+        match self {
+            BudgetUnit::Tokens => "tokens",
+            BudgetUnit::Usd => "usd",
+        }
+    }
+
+    fn amount(self, spend: &Spend) -> f64 {
+        match self {
+            BudgetUnit::Tokens => spend.tokens as f64,
+            BudgetUnit::Usd => spend.cost_usd,
+        }
+    }
+
+    fn format(self, amount: f64) -> String {
+        match self {
+            BudgetUnit::Tokens => format!("{amount:.0} tokens"),
+            BudgetUnit::Usd => format!("${amount:.2}"),
+        }
+    }
+}
+
+/// An entry of the `[[budgets]]` table in `config.toml`: how many tokens,
+/// or dollars per the `[[prices]]` table, the API key `key` may spend per
+/// `period`. Past `soft_limit` responses carry a warning; past
+/// `hard_limit` requests are rejected until the period ends or the budget
+/// is reset.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BudgetConfig {
+    pub key: String,
+    pub period: BudgetPeriod,
+    pub unit: BudgetUnit,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub soft_limit: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hard_limit: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetStatus {
+    Ok,
+    SoftLimit,
+    HardLimit,
+}
+
+/// A budget and what its key has spent in the current period, as reported
+/// by `GET /admin/budgets`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BudgetState {
+    #[serde(flatten)]
+    pub budget: BudgetConfig,
+    pub spent: f64,
+    pub status: BudgetStatus,
+    pub resets_at: DateTime<Utc>,
+}
+
+impl BudgetState {
+    fn describe(&self) -> String {
+        let limit = match self.status {
+            BudgetStatus::HardLimit => self.budget.hard_limit,
+            _ => self.budget.soft_limit,
+        };
+        format!(
+            "{} of the {} {} budget used",
+            self.budget.unit.format(self.spent),
+            self.budget.period.adjective(),
+            self.budget.unit.format(limit.unwrap_or_default()),
+        )
+    }
+}
+
+/// Validated [`BudgetConfig`]s.
+#[derive(Clone, Debug, Default)]
+pub struct Budgets {
+    budgets: Vec<BudgetConfig>,
+}
+
+impl Budgets {
+    pub fn new(budgets: Vec<BudgetConfig>) -> anyhow::Result<Self> {
+        let mut seen = HashSet::new();
+        for budget in &budgets {
+            let name = format!(
+                "{} {} budget for {:?}",
+                budget.period.adjective(),
+                budget.unit.as_str(),
+                budget.key
+            );
+            let limits = [budget.soft_limit, budget.hard_limit];
+            if limits.iter().all(Option::is_none) {
+                bail!("{name}: set soft_limit, hard_limit or both");
+            }
+            if limits
+                .iter()
+                .flatten()
+                .any(|limit| !limit.is_finite() || *limit <= 0.0)
+            {
+                bail!("{name}: limits must be positive");
+            }
+            if let [Some(soft), Some(hard)] = limits
+                && soft >= hard
+            {
+                bail!("{name}: soft_limit must be below hard_limit");
+            }
+            if !seen.insert((&budget.key, budget.period, budget.unit)) {
+                bail!("duplicate {name}");
+            }
+        }
+        Ok(Self { budgets })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BudgetConfig> {
+        self.budgets.iter()
+    }
+
+    /// The budgets of `key`, or of every key if `None`, against what each
+    /// key has spent in the period containing `now`.
+    pub fn states(
+        &self,
+        ledger: &UsageLedger,
+        key: Option<&str>,
+        now: DateTime<Utc>,
+    ) -> Vec<BudgetState> {
+        self.budgets
+            .iter()
+            .filter(|budget| key.is_none_or(|key| budget.key == key))
+            .map(|budget| {
+                let spent = budget
+                    .unit
+                    .amount(&ledger.spend(&budget.key, budget.period, now));
+                let reached = |limit: Option<f64>| limit.is_some_and(|limit| spent >= limit);
+                let status = if reached(budget.hard_limit) {
+                    BudgetStatus::HardLimit
+                } else if reached(budget.soft_limit) {
+                    BudgetStatus::SoftLimit
+                } else {
+                    BudgetStatus::Ok
+                };
+                BudgetState {
+                    budget: budget.clone(),
+                    spent,
+                    status,
+                    resets_at: budget.period.end(now),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Rejects requests from keys past a hard budget limit with a
+/// `rate_limit_error` until the period ends, recording each refusal in the
+/// usage ledger, and adds a [`BUDGET_WARNING`] header to responses for keys
+/// past a soft limit. Must run after authentication.
+pub async fn enforce_budgets(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let principal = request
+        .extensions()
+        .get::<Principal>()
+        .cloned()
+        .unwrap_or_else(Principal::anonymous);
+
+    let now = Utc::now();
+    let states = state
+        .settings
+        .load()
+        .budgets
+        .states(&state.ledger, Some(&principal.name), now);
+    if let Some(exceeded) = states
+        .iter()
+        .find(|budget| budget.status == BudgetStatus::HardLimit)
+    {
+        let description = exceeded.describe();
+        warn!("Budget exceeded for {}: {}", principal.name, description);
+        let error = AppError::new(
+            ErrorKind::RateLimit,
+            format!(
+                "Your API key has exhausted its budget: {description}. It resets at {}.",
+                exceeded.resets_at.to_rfc3339()
+            ),
+        )
+        .with_retry_after((exceeded.resets_at - now).to_std().unwrap_or_default());
+        return reject(&state, request, next, &principal, error).await;
+    }
+
+    let warnings: Vec<String> = states
+        .iter()
+        .filter(|budget| budget.status == BudgetStatus::SoftLimit)
+        .map(BudgetState::describe)
+        .collect();
+    for warning in &warnings {
+        warn!(
+            "Soft budget limit reached for {}: {}",
+            principal.name, warning
+        );
+    }
+    let mut response = next.run(request).await;
+    for warning in warnings {
+        if let Ok(value) = HeaderValue::try_from(warning) {
+            response.headers_mut().append(BUDGET_WARNING, value);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(soft_limit: Option<f64>, hard_limit: Option<f64>) -> BudgetConfig {
+        BudgetConfig {
+            key: "ci".to_string(),
+            period: BudgetPeriod::Month,
+            unit: BudgetUnit::Usd,
+            soft_limit,
+            hard_limit,
+        }
+    }
+
+    #[test]
+    fn periods_follow_the_utc_calendar() {
+        let time = "2026-12-17T15:30:00Z".parse().unwrap();
+        assert_eq!(
+            BudgetPeriod::Day.start(time).to_rfc3339(),
+            "2026-12-17T00:00:00+00:00"
+        );
+        assert_eq!(
+            BudgetPeriod::Day.end(time).to_rfc3339(),
+            "2026-12-18T00:00:00+00:00"
+        );
+        assert_eq!(
+            BudgetPeriod::Month.start(time).to_rfc3339(),
+            "2026-12-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            BudgetPeriod::Month.end(time).to_rfc3339(),
+            "2027-01-01T00:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn invalid_budgets_are_rejected() {
+        assert!(Budgets::new(vec![budget(Some(80.0), Some(100.0))]).is_ok());
+        assert!(Budgets::new(vec![budget(None, None)]).is_err());
+        assert!(Budgets::new(vec![budget(None, Some(0.0))]).is_err());
+        assert!(Budgets::new(vec![budget(Some(100.0), Some(80.0))]).is_err());
+        assert!(Budgets::new(vec![budget(None, Some(100.0)), budget(Some(80.0), None)]).is_err());
+    }
+}
@@ -0,0 +1,476 @@
+use anyhow::bail;
+use axum::http::StatusCode;
+use chat::error::ErrorKind;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    pin::pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{sync::Notify, time::Instant};
+use tracing::warn;
+
+use crate::{
+    auth::Principal,
+    error::{AppError, Protocol},
+    metrics::{record_concurrency, record_shed_request},
+    tenants::Tenant,
+    utils::glob_match,
+};
+
+/// Anthropic's status for `overloaded_error`.
+const ANTHROPIC_OVERLOADED: u16 = 529;
+
+/// The `[concurrency]` table of `config.toml`. Unset limits are not
+/// enforced; per-key and per-tenant limits are set in `[[api_keys]]` and
+/// `[[tenants]]`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ConcurrencyConfig {
+    /// Requests in flight across all keys and models.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_requests: Option<usize>,
+    /// Requests waiting for a slot; more are rejected immediately.
+    #[serde(default = "default_max_queued_requests")]
+    pub max_queued_requests: usize,
+    /// How long a request waits for a slot before it is rejected.
+    #[serde(default = "default_queue_timeout_secs")]
+    pub queue_timeout_secs: u64,
+    #[serde(default)]
+    pub models: Vec<ModelConcurrencyLimit>,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_requests: None,
+            max_queued_requests: default_max_queued_requests(),
+            queue_timeout_secs: default_queue_timeout_secs(),
+            models: vec![],
+        }
+    }
+}
+
+fn default_max_queued_requests() -> usize {
+    100
+}
+
+fn default_queue_timeout_secs() -> u64 {
+    10
+}
+
+/// An entry of `[[concurrency.models]]`: each Bedrock model ID matching
+/// `model`, exactly or as a glob pattern, may have up to
+/// `max_concurrent_requests` requests in flight.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ModelConcurrencyLimit {
+    pub model: String,
+    pub max_concurrent_requests: usize,
+}
+
+/// Validated [`ConcurrencyConfig`]. Exact model entries take precedence
+/// over patterns; patterns are tried in config order.
+#[derive(Clone, Debug, Default)]
+pub struct ConcurrencyLimits {
+    config: ConcurrencyConfig,
+}
+
+impl ConcurrencyLimits {
+    pub fn new(config: ConcurrencyConfig) -> anyhow::Result<Self> {
+        if config.max_concurrent_requests == Some(0) {
+            bail!("concurrency.max_concurrent_requests must be positive");
+        }
+        if let Some(limit) = config
+            .models
+            .iter()
+            .find(|limit| limit.max_concurrent_requests == 0)
+        {
+            bail!(
+                "concurrency limit for {:?}: max_concurrent_requests must be positive",
+                limit.model
+            );
+        }
+        Ok(Self { config })
+    }
+
+    fn queue_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.queue_timeout_secs)
+    }
+
+    fn model_limit(&self, model: &str) -> Option<usize> {
+        let (exact, patterns): (Vec<_>, Vec<_>) = self
+            .config
+            .models
+            .iter()
+            .partition(|limit| !limit.model.contains('*'));
+        exact
+            .into_iter()
+            .chain(patterns)
+            .find(|limit| glob_match(&limit.model, model))
+            .map(|limit| limit.max_concurrent_requests)
+    }
+}
+
+/// Why a request was not admitted, and which limit held it back.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConcurrencyExceeded {
+    /// The wait queue was full.
+    QueueFull { limit: &'static str },
+    /// No slot freed up within `queue_timeout_secs`.
+    QueueTimeout { limit: &'static str },
+}
+
+impl ConcurrencyExceeded {
+    /// An `overloaded_error` with HTTP 529 for Anthropic clients, which
+    /// retry it; OpenAI clients get a 503.
+    pub fn into_app_error(self, protocol: Protocol) -> AppError {
+        let (limit, reason) = match self {
+            Self::QueueFull { limit } => (limit, "the wait queue is full"),
+            Self::QueueTimeout { limit } => (limit, "no slot freed up in time"),
+        };
+        let scope = match limit {
+            "key" => "your API key's",
+            "tenant" => "your tenant's",
+            "model" => "this model's",
+            _ => "the proxy's",
+        };
+        let mut error = AppError::new(
+            ErrorKind::Overloaded,
+            format!(
+                "Too many concurrent requests: {scope} limit is reached and {reason}. Please retry the request."
+            ),
+        );
+        if protocol == Protocol::Anthropic {
+            error.status = StatusCode::from_u16(ANTHROPIC_OVERLOADED).unwrap();
+        }
+        error
+    }
+}
+
+#[derive(Debug, Default)]
+struct InFlight {
+    total: usize,
+    models: HashMap<String, usize>,
+    keys: HashMap<String, usize>,
+    tenants: HashMap<String, usize>,
+    queued: usize,
+}
+
+impl InFlight {
+    /// The first limit, from most to least specific, that has no free slot.
+    fn blocking_limit(
+        &self,
+        limits: &ConcurrencyLimits,
+        principal: &Principal,
+        tenant: Option<&Tenant>,
+        model: &str,
+    ) -> Option<&'static str> {
+        let count = |counts: &HashMap<String, usize>, key: &str| {
+            counts.get(key).copied().unwrap_or_default()
+        };
+        [
+            (
+                "key",
+                principal.limits.max_concurrent_requests.map(|l| l as usize),
+                count(&self.keys, &principal.name),
+            ),
+            (
+                "tenant",
+                tenant.and_then(|tenant| tenant.limits.max_concurrent_requests.map(|l| l as usize)),
+                tenant.map_or(0, |tenant| count(&self.tenants, &tenant.name)),
+            ),
+            (
+                "model",
+                limits.model_limit(model),
+                count(&self.models, model),
+            ),
+            ("global", limits.config.max_concurrent_requests, self.total),
+        ]
+        .into_iter()
+        .find(|(_, limit, in_flight)| limit.is_some_and(|limit| *in_flight >= limit))
+        .map(|(name, _, _)| name)
+    }
+
+    fn record(&self) {
+        record_concurrency(self.total, self.queued);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    in_flight: Mutex<InFlight>,
+    released: Notify,
+}
+
+/// Counts requests in flight globally, per model, per tenant and per key,
+/// and holds requests over a limit in a bounded queue until a slot frees
+/// up. Waiters are woken together on every release, so admission is not
+/// strictly FIFO.
+#[derive(Clone, Debug, Default)]
+pub struct ConcurrencyLimiter {
+    inner: Arc<Inner>,
+}
+
+impl ConcurrencyLimiter {
+    /// Waits for a slot under every limit that applies to `principal`, its
+    /// `tenant` and `model`. The slot is held until the returned permit is
+    /// dropped.
+    pub async fn acquire(
+        &self,
+        limits: &ConcurrencyLimits,
+        principal: &Principal,
+        tenant: Option<&Tenant>,
+        model: &str,
+    ) -> Result<ConcurrencyPermit, ConcurrencyExceeded> {
+        let deadline = Instant::now() + limits.queue_timeout();
+        let mut queued: Option<QueueSlot> = None;
+        loop {
+            // Registered before checking so a release in between is not missed.
+            let mut released = pin!(self.inner.released.notified());
+            released.as_mut().enable();
+
+            let limit = {
+                let mut in_flight = self.inner.in_flight.lock().unwrap();
+                let Some(limit) = in_flight.blocking_limit(limits, principal, tenant, model) else {
+                    in_flight.total += 1;
+                    *in_flight.models.entry(model.to_string()).or_default() += 1;
+                    *in_flight.keys.entry(principal.name.clone()).or_default() += 1;
+                    if let Some(tenant) = tenant {
+                        *in_flight.tenants.entry(tenant.name.clone()).or_default() += 1;
+                    }
+                    in_flight.record();
+                    drop(in_flight);
+                    drop(queued);
+                    return Ok(ConcurrencyPermit {
+                        inner: self.inner.clone(),
+                        model: model.to_string(),
+                        principal: principal.name.clone(),
+                        tenant: tenant.map(|tenant| tenant.name.clone()),
+                    });
+                };
+                if queued.is_none() {
+                    if in_flight.queued >= limits.config.max_queued_requests {
+                        drop(in_flight);
+                        warn!(
+                            "Rejected request from {} for {}: {} concurrency limit reached and the queue is full",
+                            principal.name, model, limit
+                        );
+                        record_shed_request(limit, "queue_full");
+                        return Err(ConcurrencyExceeded::QueueFull { limit });
+                    }
+                    in_flight.queued += 1;
+                    in_flight.record();
+                    queued = Some(QueueSlot(self.inner.clone()));
+                }
+                limit
+            };
+
+            if tokio::time::timeout_at(deadline, released).await.is_err() {
+                warn!(
+                    "Rejected request from {} for {}: waited {:?} at the {} concurrency limit",
+                    principal.name,
+                    model,
+                    limits.queue_timeout(),
+                    limit
+                );
+                record_shed_request(limit, "queue_timeout");
+                return Err(ConcurrencyExceeded::QueueTimeout { limit });
+            }
+        }
+    }
+}
+
+/// A place in the wait queue, given up when the request is admitted,
+/// rejected or cancelled.
+struct QueueSlot(Arc<Inner>);
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        let mut in_flight = self.0.in_flight.lock().unwrap();
+        in_flight.queued -= 1;
+        in_flight.record();
+    }
+}
+
+/// A request's slot, released when dropped.
+#[derive(Debug)]
+pub struct ConcurrencyPermit {
+    inner: Arc<Inner>,
+    model: String,
+    principal: String,
+    tenant: Option<String>,
+}
+
+impl ConcurrencyPermit {
+    /// Holds the slot until `stream` ends or is dropped.
+    pub fn hold<S: Stream>(self, stream: S) -> impl Stream<Item = S::Item> {
+        stream.map(move |item| {
+            let _permit = &self;
+            item
+        })
+    }
+}
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        let mut guard = self.inner.in_flight.lock().unwrap();
+        let in_flight = &mut *guard;
+        in_flight.total -= 1;
+        for (counts, key) in [
+            (&mut in_flight.models, &self.model),
+            (&mut in_flight.keys, &self.principal),
+        ]
+        .into_iter()
+        .chain(
+            self.tenant
+                .as_ref()
+                .map(|tenant| (&mut in_flight.tenants, tenant)),
+        ) {
+            if let Some(count) = counts.get_mut(key) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.remove(key);
+                }
+            }
+        }
+        in_flight.record();
+        drop(guard);
+        self.inner.released.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_access::ModelRules;
+    use crate::rate_limit::RateLimits;
+
+    fn limits(config: ConcurrencyConfig) -> ConcurrencyLimits {
+        ConcurrencyLimits::new(config).unwrap()
+    }
+
+    fn principal(name: &str, max_concurrent_requests: Option<u32>) -> Principal {
+        Principal {
+            name: name.to_string(),
+            admin: false,
+            limits: RateLimits {
+                max_concurrent_requests,
+                ..Default::default()
+            },
+            identity: None,
+            tenant: None,
+            models: ModelRules::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn queued_request_is_admitted_when_slot_frees_up() {
+        let limiter = ConcurrencyLimiter::default();
+        let limits = limits(ConcurrencyConfig {
+            max_concurrent_requests: Some(1),
+            ..Default::default()
+        });
+        let alice = principal("alice", None);
+        let first = limiter
+            .acquire(&limits, &alice, None, "opus")
+            .await
+            .unwrap();
+
+        let waiting = tokio::spawn({
+            let limiter = limiter.clone();
+            let limits = limits.clone();
+            let alice = alice.clone();
+            async move { limiter.acquire(&limits, &alice, None, "sonnet").await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+        assert_eq!(limiter.inner.in_flight.lock().unwrap().queued, 1);
+
+        drop(first);
+        assert!(waiting.await.unwrap().is_ok());
+        assert_eq!(limiter.inner.in_flight.lock().unwrap().queued, 0);
+    }
+
+    #[tokio::test]
+    async fn full_queue_and_timeout_are_rejected() {
+        let limiter = ConcurrencyLimiter::default();
+        let limits = limits(ConcurrencyConfig {
+            max_queued_requests: 0,
+            models: vec![ModelConcurrencyLimit {
+                model: "*opus*".to_string(),
+                max_concurrent_requests: 1,
+            }],
+            ..Default::default()
+        });
+        let alice = principal("alice", Some(1));
+        let bob = principal("bob", None);
+        let _opus = limiter
+            .acquire(&limits, &bob, None, "us.opus")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            limiter
+                .acquire(&limits, &bob, None, "us.opus")
+                .await
+                .unwrap_err(),
+            ConcurrencyExceeded::QueueFull { limit: "model" }
+        );
+        // Each matching model has its own slots.
+        let _global_opus = limiter
+            .acquire(&limits, &bob, None, "global.opus")
+            .await
+            .unwrap();
+        let _sonnet = limiter
+            .acquire(&limits, &alice, None, "sonnet")
+            .await
+            .unwrap();
+        assert_eq!(
+            limiter
+                .acquire(&limits, &alice, None, "haiku")
+                .await
+                .unwrap_err(),
+            ConcurrencyExceeded::QueueFull { limit: "key" }
+        );
+
+        let limits = ConcurrencyLimits {
+            config: ConcurrencyConfig {
+                max_queued_requests: 1,
+                queue_timeout_secs: 0,
+                ..limits.config
+            },
+        };
+        assert_eq!(
+            limiter
+                .acquire(&limits, &bob, None, "us.opus")
+                .await
+                .unwrap_err(),
+            ConcurrencyExceeded::QueueTimeout { limit: "model" }
+        );
+        assert_eq!(limiter.inner.in_flight.lock().unwrap().queued, 0);
+    }
+
+    #[test]
+    fn zero_limits_are_rejected() {
+        assert!(
+            ConcurrencyLimits::new(ConcurrencyConfig {
+                max_concurrent_requests: Some(0),
+                ..Default::default()
+            })
+            .is_err()
+        );
+        assert!(
+            ConcurrencyLimits::new(ConcurrencyConfig {
+                models: vec![ModelConcurrencyLimit {
+                    model: "*".to_string(),
+                    max_concurrent_requests: 0,
+                }],
+                ..Default::default()
+            })
+            .is_err()
+        );
+    }
+}
@@ -6,13 +6,110 @@ use aws_sdk_bedrockruntime::{
         count_tokens::CountTokensError,
     },
 };
-use axum::{http::StatusCode, response::IntoResponse};
+use axum::{
+    Json,
+    extract::rejection::JsonRejection,
+    http::{HeaderValue, StatusCode, header::RETRY_AFTER},
+    response::{IntoResponse, Response},
+};
+use chat::error::{BedrockError, ErrorKind, classify_sdk_error};
+use std::time::Duration;
+
+/// The wire protocol a route speaks, which decides the error envelope.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    Anthropic,
+    OpenAI,
+}
+
+impl Protocol {
+    /// The OpenAI front-end serves `/chat/completions` and `/models`;
+    /// everything else speaks the Anthropic protocol.
+    pub fn from_path(path: &str) -> Self {
+        if path == "/chat/completions" || path == "/models" || path.starts_with("/models/") {
+            Protocol::OpenAI
+        } else {
+            Protocol::Anthropic
+        }
+    }
+}
+
+/// A classified proxy failure. Rendered as an Anthropic or OpenAI error
+/// envelope through [`AnthropicError`] / [`OpenAIError`], or directly via
+/// [`AppError::into_response_for`].
+#[derive(Debug)]
+pub struct AppError {
+    pub status: StatusCode,
+    pub kind: ErrorKind,
+    pub message: String,
+    /// Sent as a `retry-after` header, in whole seconds.
+    pub retry_after: Option<Duration>,
+}
+
+impl AppError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            status: kind.status_code(),
+            kind,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
 
-pub struct AppError(StatusCode, String);
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(retry_after);
+        self
+    }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> axum::response::Response {
-        (self.0, self.1).into_response()
+    pub fn into_response_for(self, protocol: Protocol) -> Response {
+        let body = match protocol {
+            Protocol::Anthropic => serde_json::json!({
+                "type": "error",
+                "error": {
+                    "type": self.kind.anthropic_type(),
+                    "message": self.message,
+                }
+            }),
+            Protocol::OpenAI => serde_json::json!({
+                "error": {
+                    "message": self.message,
+                    "type": self.kind.openai_type(),
+                    "param": null,
+                    "code": self.kind.openai_code(),
+                }
+            }),
+        };
+        let mut response = (self.status, Json(body)).into_response();
+        if let Some(retry_after) = self.retry_after {
+            let secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
+    }
+}
+
+/// Preserves the upstream Bedrock status code alongside the classified kind.
+/// Error codes the SDK does not model are classified by that status instead.
+fn from_sdk_error<E>(err: &SdkError<E>) -> AppError
+where
+    E: BedrockError + std::error::Error + 'static,
+{
+    let (kind, message) = classify_sdk_error(err);
+    let upstream_status = err
+        .raw_response()
+        .and_then(|r| StatusCode::from_u16(r.status().as_u16()).ok());
+    let (status, kind) = match (upstream_status, kind) {
+        (Some(status), ErrorKind::Api) => (status, ErrorKind::from_status_code(status)),
+        (Some(status), kind) => (status, kind),
+        (None, kind) => (kind.status_code(), kind),
+    };
+    AppError {
+        status,
+        kind,
+        message,
+        retry_after: None,
     }
 }
 
@@ -22,46 +119,70 @@ where
 {
     fn from(err: E) -> Self {
         let err: AnyhowError = err.into();
-        let status = err
-            .downcast_ref::<SdkError<ConverseStreamError>>()
-            .and_then(|e| e.raw_response())
-            .map(|r| r.status().as_u16())
-            .or_else(|| {
-                err.downcast_ref::<SdkError<ConverseError>>()
-                    .and_then(|e| e.raw_response())
-                    .map(|r| r.status().as_u16())
-            })
-            .or_else(|| {
-                err.downcast_ref::<SdkError<CountTokensError>>()
-                    .and_then(|e| e.raw_response())
-                    .map(|r| r.status().as_u16())
-            })
-            .and_then(|code| StatusCode::from_u16(code).ok())
-            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
-        let message = err
-            .downcast_ref::<SdkError<ConverseStreamError>>()
-            .and_then(|e| e.as_service_error())
-            .and_then(|se| se.meta().message())
+        if let Some(rejection) = err.downcast_ref::<JsonRejection>() {
+            return Self {
+                status: rejection.status(),
+                kind: ErrorKind::InvalidRequest,
+                message: rejection.body_text(),
+                retry_after: None,
+            };
+        }
+        err.downcast_ref::<SdkError<ConverseStreamError>>()
+            .map(from_sdk_error)
             .or_else(|| {
                 err.downcast_ref::<SdkError<ConverseError>>()
-                    .and_then(|e| e.as_service_error())
-                    .and_then(|se| se.meta().message())
+                    .map(from_sdk_error)
             })
             .or_else(|| {
                 err.downcast_ref::<SdkError<CountTokensError>>()
-                    .and_then(|e| e.as_service_error())
-                    .and_then(|se| se.meta().message())
+                    .map(from_sdk_error)
             })
-            .map(String::from)
-            .unwrap_or_else(|| err.to_string());
-        Self(status, message)
+            .unwrap_or_else(|| Self::new(ErrorKind::Api, err.to_string()))
+    }
+}
+
+/// `AppError` rendered as `{"type":"error","error":{"type","message"}}`.
+#[derive(Debug)]
+pub struct AnthropicError(pub AppError);
+
+impl IntoResponse for AnthropicError {
+    fn into_response(self) -> Response {
+        self.0.into_response_for(Protocol::Anthropic)
+    }
+}
+
+impl<E> From<E> for AnthropicError
+where
+    E: Into<AppError>,
+{
+    fn from(err: E) -> Self {
+        Self(err.into())
+    }
+}
+
+/// `AppError` rendered as `{"error":{"message","type","param","code"}}`.
+#[derive(Debug)]
+pub struct OpenAIError(pub AppError);
+
+impl IntoResponse for OpenAIError {
+    fn into_response(self) -> Response {
+        self.0.into_response_for(Protocol::OpenAI)
+    }
+}
+
+impl<E> From<E> for OpenAIError
+where
+    E: Into<AppError>,
+{
+    fn from(err: E) -> Self {
+        Self(err.into())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use aws_sdk_bedrockruntime::types::error::ValidationException;
+    use aws_sdk_bedrockruntime::types::error::{ThrottlingException, ValidationException};
     use aws_smithy_runtime_api::http::{
         Response as SmithyResponse, StatusCode as SmithyStatusCode,
     };
@@ -89,28 +210,54 @@ mod tests {
         sdk_err.into()
     }
 
+    fn make_converse_throttling_error() -> anyhow::Error {
+        let raw = SmithyResponse::new(
+            SmithyStatusCode::try_from(429).unwrap(),
+            SdkBody::from("error"),
+        );
+        let err = ConverseError::ThrottlingException(
+            ThrottlingException::builder()
+                .message("Too many requests")
+                .meta(
+                    ErrorMetadata::builder()
+                        .message("Too many requests")
+                        .build(),
+                )
+                .build(),
+        );
+        let sdk_err: SdkError<ConverseError> = SdkError::service_error(err, raw);
+        sdk_err.into()
+    }
+
+    async fn body_json(response: Response) -> serde_json::Value {
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&body).unwrap()
+    }
+
     #[test]
     fn converse_stream_error_preserves_429() {
         let app_error = AppError::from(make_converse_stream_error(429));
-        assert_eq!(app_error.0, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(app_error.status, StatusCode::TOO_MANY_REQUESTS);
     }
 
     #[test]
     fn converse_stream_error_preserves_400() {
         let app_error = AppError::from(make_converse_stream_error(400));
-        assert_eq!(app_error.0, StatusCode::BAD_REQUEST);
+        assert_eq!(app_error.status, StatusCode::BAD_REQUEST);
+        assert_eq!(app_error.kind, ErrorKind::InvalidRequest);
     }
 
     #[test]
     fn count_tokens_error_preserves_429() {
         let app_error = AppError::from(make_count_tokens_error(429));
-        assert_eq!(app_error.0, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(app_error.status, StatusCode::TOO_MANY_REQUESTS);
     }
 
     #[test]
     fn generic_error_defaults_to_500() {
         let app_error = AppError::from(anyhow::anyhow!("something broke"));
-        assert_eq!(app_error.0, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(app_error.status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(app_error.kind, ErrorKind::Api);
     }
 
     #[tokio::test]
@@ -129,10 +276,57 @@ mod tests {
         let sdk_err: SdkError<ConverseStreamError> = SdkError::service_error(err, raw);
         let app_error = AppError::from(anyhow::Error::from(sdk_err));
 
-        assert_eq!(app_error.0, StatusCode::BAD_REQUEST);
+        assert_eq!(app_error.status, StatusCode::BAD_REQUEST);
+        assert_eq!(app_error.kind, ErrorKind::InvalidRequest);
 
-        let response = app_error.into_response();
-        let body = response.into_body().collect().await.unwrap().to_bytes();
-        assert_eq!(String::from_utf8(body.to_vec()).unwrap(), expected);
+        let response = AnthropicError(app_error).into_response();
+        let json = body_json(response).await;
+        assert_eq!(json["type"], "error");
+        assert_eq!(json["error"]["type"], "invalid_request_error");
+        assert_eq!(json["error"]["message"], expected);
+    }
+
+    #[tokio::test]
+    async fn anthropic_envelope_for_throttling() {
+        let response = AnthropicError::from(make_converse_throttling_error()).into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let json = body_json(response).await;
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "error",
+                "error": {"type": "rate_limit_error", "message": "Too many requests"}
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn openai_envelope_for_throttling() {
+        let response = OpenAIError::from(make_converse_throttling_error()).into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let json = body_json(response).await;
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "error": {
+                    "message": "Too many requests",
+                    "type": "rate_limit_error",
+                    "param": null,
+                    "code": "rate_limit_exceeded"
+                }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn proxy_error_uses_kind_status() {
+        let response = AppError::new(ErrorKind::Authentication, "invalid x-api-key")
+            .into_response_for(Protocol::Anthropic);
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let json = body_json(response).await;
+        assert_eq!(json["error"]["type"], "authentication_error");
     }
 }
@@ -0,0 +1,105 @@
+use anyhow::bail;
+use axum::http::HeaderName;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::glob_match;
+
+/// The Bedrock model ID that served a chat request, which differs from the
+/// requested one after an inference-profile failover or a fallback.
+pub const BEDROCK_MODEL_ID: HeaderName = HeaderName::from_static("x-bedrock-model-id");
+
+/// An entry of the `[[fallbacks]]` table in `config.toml`. When Bedrock
+/// throttles `model` (an exact Bedrock model ID after alias resolution, or a
+/// glob pattern) or reports it unavailable, the request is sent to each of
+/// `fallbacks` in order, as long as nothing has been streamed to the client.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FallbackChain {
+    pub model: String,
+    pub fallbacks: Vec<String>,
+}
+
+/// Fallback chains by model. Exact entries take precedence over patterns;
+/// patterns are tried in config order.
+#[derive(Clone, Debug, Default)]
+pub struct FallbackChains {
+    chains: Vec<FallbackChain>,
+}
+
+impl FallbackChains {
+    pub fn new(chains: Vec<FallbackChain>) -> anyhow::Result<Self> {
+        for chain in &chains {
+            if chain.fallbacks.is_empty() {
+                bail!("fallbacks for {:?} must not be empty", chain.model);
+            }
+            if let Some(fallback) = chain
+                .fallbacks
+                .iter()
+                .find(|fallback| fallback.contains('*') || **fallback == chain.model)
+            {
+                bail!(
+                    "fallback {:?} for {:?} must be a model ID other than the model",
+                    fallback,
+                    chain.model
+                );
+            }
+        }
+        Ok(Self { chains })
+    }
+
+    /// The models to try after `model`, in order; empty if none are
+    /// configured.
+    pub fn chain(&self, model: &str) -> Vec<String> {
+        let (exact, patterns): (Vec<_>, Vec<_>) = self
+            .chains
+            .iter()
+            .partition(|chain| !chain.model.contains('*'));
+        exact
+            .into_iter()
+            .chain(patterns)
+            .find(|chain| glob_match(&chain.model, model))
+            .map(|chain| chain.fallbacks.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(model: &str, fallbacks: &[&str]) -> FallbackChain {
+        FallbackChain {
+            model: model.to_string(),
+            fallbacks: fallbacks.iter().map(|model| model.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn exact_chain_wins_over_pattern() {
+        let chains = FallbackChains::new(vec![
+            chain("*claude-opus-4*", &["us.anthropic.claude-sonnet-4"]),
+            chain(
+                "us.anthropic.claude-opus-4-1",
+                &["eu.anthropic.claude-opus-4-1"],
+            ),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            chains.chain("us.anthropic.claude-opus-4-1"),
+            ["eu.anthropic.claude-opus-4-1"]
+        );
+        assert_eq!(
+            chains.chain("global.anthropic.claude-opus-4-5"),
+            ["us.anthropic.claude-sonnet-4"]
+        );
+        assert!(chains.chain("us.anthropic.claude-haiku-4-5").is_empty());
+    }
+
+    #[test]
+    fn empty_or_pattern_fallbacks_are_rejected() {
+        assert!(FallbackChains::new(vec![chain("opus", &[])]).is_err());
+        assert!(FallbackChains::new(vec![chain("opus", &["*sonnet*"])]).is_err());
+        assert!(FallbackChains::new(vec![chain("opus", &["sonnet", "opus"])]).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use axum::{
+    Extension, Json,
+    extract::{
+        Query, State,
+        rejection::{JsonRejection, QueryRejection},
+    },
+    response::IntoResponse,
+};
+use chat::error::ErrorKind;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::{
+    AppState,
+    auth::Principal,
+    budgets::BudgetPeriod,
+    error::{AnthropicError, AppError},
+    ledger::{TimeBucket, UsageQuery},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct UsageParams {
+    /// Comma-separated list of `key` and `model`.
+    pub group_by: Option<String>,
+    #[serde(default)]
+    pub bucket: TimeBucket,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub key: Option<String>,
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BudgetParams {
+    pub key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BudgetReset {
+    pub key: String,
+    /// Resets both periods if omitted.
+    pub period: Option<BudgetPeriod>,
+}
+
+fn require_admin(principal: &Principal) -> Result<(), AppError> {
+    if principal.admin {
+        Ok(())
+    } else {
+        Err(AppError::new(
+            ErrorKind::Permission,
+            "this endpoint requires an admin API key",
+        ))
+    }
+}
+
+pub async fn handle_usage(
+    State(state): State<Arc<AppState>>,
+    Extension(principal): Extension<Principal>,
+    params: Result<Query<UsageParams>, QueryRejection>,
+) -> Result<impl IntoResponse, AnthropicError> {
+    require_admin(&principal)?;
+    let Query(params) =
+        params.map_err(|e| AppError::new(ErrorKind::InvalidRequest, e.body_text()))?;
+
+    let mut query = UsageQuery {
+        bucket: params.bucket,
+        from: params.from,
+        to: params.to,
+        key: params.key,
+        model: params.model,
+        ..Default::default()
+    };
+    for field in params.group_by.iter().flat_map(|g| g.split(',')) {
+        match field.trim() {
+            "key" => query.by_key = true,
+            "model" => query.by_model = true,
+            "" => {}
+            other => {
+                return Err(AppError::new(
+                    ErrorKind::InvalidRequest,
+                    format!("group_by: unknown field {other:?}, expected key or model"),
+                )
+                .into());
+            }
+        }
+    }
+
+    let data = state.ledger.query(query).await?;
+    Ok(Json(serde_json::json!({ "data": data })))
+}
+
+pub async fn handle_budgets(
+    State(state): State<Arc<AppState>>,
+    Extension(principal): Extension<Principal>,
+    params: Result<Query<BudgetParams>, QueryRejection>,
+) -> Result<impl IntoResponse, AnthropicError> {
+    require_admin(&principal)?;
+    let Query(params) =
+        params.map_err(|e| AppError::new(ErrorKind::InvalidRequest, e.body_text()))?;
+
+    let data =
+        state
+            .settings
+            .load()
+            .budgets
+            .states(&state.ledger, params.key.as_deref(), Utc::now());
+    Ok(Json(serde_json::json!({ "data": data })))
+}
+
+/// Forgets what a key has spent so far in the current day and/or month,
+/// lifting any budget limits it has reached.
+pub async fn handle_reset_budget(
+    State(state): State<Arc<AppState>>,
+    Extension(principal): Extension<Principal>,
+    payload: Result<Json<BudgetReset>, JsonRejection>,
+) -> Result<impl IntoResponse, AnthropicError> {
+    require_admin(&principal)?;
+    let Json(reset) =
+        payload.map_err(|e| AppError::new(ErrorKind::InvalidRequest, e.body_text()))?;
+
+    let periods = match reset.period {
+        Some(period) => vec![period],
+        None => BudgetPeriod::ALL.to_vec(),
+    };
+    for period in periods {
+        state.ledger.reset_spend(&reset.key, period).await?;
+    }
+    info!("{} reset the budgets of {}", principal.name, reset.key);
+
+    let data = state
+        .settings
+        .load()
+        .budgets
+        .states(&state.ledger, Some(&reset.key), Utc::now());
+    Ok(Json(serde_json::json!({ "data": data })))
+}
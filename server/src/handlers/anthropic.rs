@@ -1,27 +1,61 @@
 use anthropic_request::{OutputConfig, V1MessagesCountTokensRequest, V1MessagesRequest};
 use anthropic_response::V1MessagesCountTokensResponse;
 use axum::{
-    Json,
-    extract::State,
+    Extension, Json,
+    extract::{
+        Path, Query, State,
+        rejection::{JsonRejection, QueryRejection},
+    },
     http::{HeaderMap, StatusCode},
     response::{IntoResponse, sse::Sse},
 };
-use chat::provider::{BedrockV1MessagesProvider, V1MessagesProvider};
+use chat::{
+    error::ErrorKind,
+    provider::{BedrockV1MessagesProvider, V1MessagesProvider, anthropic_error_event},
+};
 use common::filter_anthropic_beta;
+use serde::Deserialize;
 use std::sync::Arc;
 use tracing::info;
 
-use crate::{AppState, error::AppError, utils::log_token_usage};
+use crate::{
+    AppState,
+    auth::Principal,
+    error::{AnthropicError, AppError, Protocol},
+    fallbacks::BEDROCK_MODEL_ID,
+    metrics::RequestModel,
+    model_access::check_model_access,
+    models::{DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT},
+    request_id::RequestId,
+    response_cache::{CacheLookup, CacheRequest, IDEMPOTENCY_KEY},
+    shutdown::SHUTDOWN_MESSAGE,
+    telemetry::record_chat_request,
+    tenants::aws_identity,
+    utils::{PendingRejection, usage_reporter},
+};
 
 pub async fn handle_v1_messages(
     State(state): State<Arc<AppState>>,
+    Extension(principal): Extension<Principal>,
+    Extension(request_id): Extension<RequestId>,
+    Extension(request_model): Extension<RequestModel>,
+    rejection: Option<Extension<PendingRejection>>,
     headers: HeaderMap,
-    Json(payload): Json<V1MessagesRequest>,
-) -> Result<impl IntoResponse, AppError> {
+    payload: Result<Json<V1MessagesRequest>, JsonRejection>,
+) -> Result<impl IntoResponse, AnthropicError> {
+    let Json(mut payload) = payload?;
     info!(
         "Received Anthropic v1/messages request for model: {}",
         payload.model
     );
+    let settings = state.settings.load_full();
+    let requested_model = settings.model_aliases.rewrite(&mut payload.model);
+    request_model.set(&payload.model);
+    record_chat_request(&payload.model);
+    let tenant = settings.tenants.of(&principal);
+    if let Some(tenant) = tenant {
+        tenant.defaults.apply_to_v1_messages(&mut payload);
+    }
 
     if let Some(ref output_config) = payload.output_config {
         match output_config {
@@ -40,36 +74,155 @@ pub async fn handle_v1_messages(
         }
     }
 
-    let anthropic_beta = filter_anthropic_beta(&headers, &state.anthropic_beta_whitelist);
+    let anthropic_beta =
+        filter_anthropic_beta(&headers, settings.anthropic_beta_whitelist_for(tenant));
     info!("anthropic_beta: {:?}", anthropic_beta);
 
-    let provider = BedrockV1MessagesProvider::new(state.bedrockruntime_client.clone());
+    let usage = usage_reporter(
+        &state,
+        &request_id,
+        &principal,
+        &requested_model,
+        &payload.model,
+    );
+    if let Some(error) = rejection.and_then(|Extension(rejection)| rejection.take()) {
+        usage.reject(error.status, error.kind);
+        return Err(AnthropicError(error));
+    }
+    check_model_access(&principal, tenant, &payload.model)
+        .map_err(AnthropicError)
+        .inspect_err(|e| usage.reject(e.0.status, e.0.kind))?;
+    let idempotency_key = headers
+        .get(IDEMPOTENCY_KEY)
+        .and_then(|value| value.to_str().ok());
+    let fallback_models = settings.fallbacks_for(&principal, tenant, &payload.model);
+    let lookup = state
+        .response_cache
+        .lookup(
+            &settings.response_cache,
+            &principal,
+            idempotency_key,
+            &CacheRequest {
+                requested_model: &requested_model,
+                payload: &payload,
+                anthropic_beta: anthropic_beta.as_deref(),
+                inference_profile_prefixes: settings.inference_profile_prefixes_for(tenant),
+                fallback_models: &fallback_models,
+            },
+        )
+        .map_err(AnthropicError)
+        .inspect_err(|e| usage.fail(e.0.status, e.0.kind))?;
+    let cache_ticket = match lookup {
+        CacheLookup::Hit(hit) => {
+            let response = hit
+                .into_response(payload.stream == Some(true))
+                .map_err(AnthropicError)
+                .inspect_err(|e| usage.fail(e.0.status, e.0.kind))?;
+            usage.finish();
+            return Ok(response);
+        }
+        CacheLookup::Miss(ticket) => ticket,
+    };
+    let permit = state
+        .concurrency
+        .acquire(&settings.concurrency, &principal, tenant, &payload.model)
+        .await
+        .map_err(|e| AnthropicError(e.into_app_error(Protocol::Anthropic)))
+        .inspect_err(|e| usage.reject(e.0.status, e.0.kind))?;
+    let bedrock = state
+        .bedrock
+        .lease_for(aws_identity(&principal, tenant))
+        .await;
+    let provider = BedrockV1MessagesProvider::new(bedrock.client().clone())
+        .with_inference_profiles(settings.inference_profiles_for(tenant))
+        .with_fallback_models(fallback_models);
+    let ticket = state
+        .throttle
+        .acquire(&settings.throttling, bedrock.name(), &payload.model)
+        .await
+        .map_err(AnthropicError)
+        .inspect_err(|e| usage.reject(e.0.status, e.0.kind))?;
 
     if payload.stream == Some(true) {
-        let stream = provider
-            .v1_messages_stream(payload, None, anthropic_beta, log_token_usage)
-            .await?;
-        return Ok((StatusCode::OK, Sse::new(stream)).into_response());
+        let provider = if cache_ticket.will_store() {
+            let served = usage.clone();
+            provider
+                .with_streamed_message(move |message| cache_ticket.store(&served.model(), &message))
+        } else {
+            provider
+        };
+        let result = provider
+            .v1_messages_stream(
+                payload,
+                Some(requested_model),
+                anthropic_beta,
+                usage.clone(),
+            )
+            .await
+            .map_err(AppError::from);
+        bedrock.record(&result);
+        let stream = ticket
+            .record_served(result, &usage.model())
+            .map_err(AnthropicError)
+            .inspect_err(|e| usage.fail(e.0.status, e.0.kind))?;
+        let served_model = [(BEDROCK_MODEL_ID, usage.model())];
+        let stream = state
+            .shutdown
+            .guard(bedrock.hold(permit.hold(stream)), move || {
+                usage.fail_stream(ErrorKind::Overloaded);
+                vec![anthropic_error_event(
+                    ErrorKind::Overloaded.anthropic_type(),
+                    SHUTDOWN_MESSAGE,
+                    usage.request_id(),
+                )]
+            });
+        return Ok((StatusCode::OK, served_model, Sse::new(stream)).into_response());
     }
 
-    let message = provider
-        .v1_messages(payload, None, anthropic_beta, log_token_usage)
-        .await?;
-    Ok((StatusCode::OK, Json(message)).into_response())
+    let result = provider
+        .v1_messages(
+            payload,
+            Some(requested_model),
+            anthropic_beta,
+            usage.clone(),
+        )
+        .await
+        .map_err(AppError::from);
+    bedrock.record(&result);
+    let message = ticket
+        .record_served(result, &usage.model())
+        .map_err(AnthropicError)
+        .inspect_err(|e| usage.fail(e.0.status, e.0.kind))?;
+    drop(permit);
+    cache_ticket.store(&usage.model(), &message);
+    let served_model = [(BEDROCK_MODEL_ID, usage.model())];
+    Ok((StatusCode::OK, served_model, Json(message)).into_response())
 }
 
 pub async fn handle_v1_messages_count_tokens(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<V1MessagesCountTokensRequest>,
-) -> Result<impl IntoResponse, AppError> {
+    Extension(principal): Extension<Principal>,
+    Extension(request_model): Extension<RequestModel>,
+    payload: Result<Json<V1MessagesCountTokensRequest>, JsonRejection>,
+) -> Result<impl IntoResponse, AnthropicError> {
+    let Json(mut payload) = payload?;
     info!(
         "Received Anthropic v1/messages/count_tokens request for model: {}",
         payload.model
     );
+    let settings = state.settings.load_full();
+    settings.model_aliases.rewrite(&mut payload.model);
+    request_model.set(&payload.model);
+    let tenant = settings.tenants.of(&principal);
+    check_model_access(&principal, tenant, &payload.model)?;
 
-    let v1_messages_provider = BedrockV1MessagesProvider::new(state.bedrockruntime_client.clone());
+    let bedrock = state
+        .bedrock
+        .lease_for(aws_identity(&principal, tenant))
+        .await;
+    let v1_messages_provider = BedrockV1MessagesProvider::new(bedrock.client().clone());
     let input_token_count = v1_messages_provider
-        .v1_messages_count_tokens(&payload, &state.inference_profile_prefixes)
+        .v1_messages_count_tokens(&payload, settings.inference_profile_prefixes_for(tenant))
         .await?;
 
     Ok((
@@ -79,3 +232,53 @@ pub async fn handle_v1_messages_count_tokens(
         }),
     ))
 }
+
+#[derive(Debug, Deserialize)]
+pub struct ListModelsParams {
+    pub before_id: Option<String>,
+    pub after_id: Option<String>,
+    pub limit: Option<usize>,
+}
+
+pub async fn handle_v1_models(
+    State(state): State<Arc<AppState>>,
+    Extension(principal): Extension<Principal>,
+    params: Result<Query<ListModelsParams>, QueryRejection>,
+) -> Result<impl IntoResponse, AnthropicError> {
+    let Query(params) =
+        params.map_err(|e| AppError::new(ErrorKind::InvalidRequest, e.body_text()))?;
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+        return Err(AppError::new(
+            ErrorKind::InvalidRequest,
+            format!("limit: must be between 1 and {MAX_PAGE_LIMIT}"),
+        )
+        .into());
+    }
+
+    let settings = state.settings.load();
+    let tenant = settings.tenants.of(&principal);
+    let page = settings
+        .models_for(&principal, tenant)
+        .page(
+            params.before_id.as_deref(),
+            params.after_id.as_deref(),
+            limit,
+        )
+        .map_err(|e| AppError::new(ErrorKind::InvalidRequest, e.to_string()))?;
+    Ok((StatusCode::OK, Json(page)))
+}
+
+pub async fn handle_v1_model(
+    State(state): State<Arc<AppState>>,
+    Extension(principal): Extension<Principal>,
+    Path(model_id): Path<String>,
+) -> Result<impl IntoResponse, AnthropicError> {
+    let settings = state.settings.load();
+    let tenant = settings.tenants.of(&principal);
+    let models = settings.models_for(&principal, tenant);
+    let model = models
+        .get(&model_id)
+        .ok_or_else(|| AppError::new(ErrorKind::NotFound, format!("model: {model_id}")))?;
+    Ok((StatusCode::OK, Json(model.to_anthropic())))
+}
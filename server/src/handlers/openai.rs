@@ -1,34 +1,160 @@
-use anyhow::anyhow;
 use axum::{
-    Json,
-    extract::State,
+    Extension, Json,
+    extract::{Path, State, rejection::JsonRejection},
     http::StatusCode,
-    response::{IntoResponse, sse::Sse},
+    response::{
+        IntoResponse,
+        sse::{Event, Sse},
+    },
+};
+use chat::{
+    DONE_MESSAGE,
+    error::ErrorKind,
+    provider::{BedrockChatCompletionsProvider, ChatCompletionsProvider, openai_error_event},
 };
-use chat::provider::{BedrockChatCompletionsProvider, ChatCompletionsProvider};
 use request::ChatCompletionsRequest;
+use response::ModelList;
 use std::sync::Arc;
-use tracing::{error, info};
+use tracing::info;
 
-use crate::{AppState, error::AppError, utils::log_token_usage};
+use crate::{
+    AppState,
+    auth::Principal,
+    error::{AppError, OpenAIError, Protocol},
+    fallbacks::BEDROCK_MODEL_ID,
+    metrics::RequestModel,
+    model_access::check_model_access,
+    models::CatalogModel,
+    request_id::RequestId,
+    shutdown::SHUTDOWN_MESSAGE,
+    telemetry::record_chat_request,
+    tenants::aws_identity,
+    utils::{PendingRejection, usage_reporter},
+};
 
 pub async fn handle_chat_completions(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<ChatCompletionsRequest>,
-) -> Result<impl IntoResponse, AppError> {
+    Extension(principal): Extension<Principal>,
+    Extension(request_id): Extension<RequestId>,
+    Extension(request_model): Extension<RequestModel>,
+    rejection: Option<Extension<PendingRejection>>,
+    payload: Result<Json<ChatCompletionsRequest>, JsonRejection>,
+) -> Result<impl IntoResponse, OpenAIError> {
+    let Json(mut payload) = payload?;
     info!(
         "Received OpenAI chat completions request for model: {}",
         payload.model
     );
+    let settings = state.settings.load_full();
+    let requested_model = settings.model_aliases.rewrite(&mut payload.model);
+    request_model.set(&payload.model);
+    record_chat_request(&payload.model);
+    let tenant = settings.tenants.of(&principal);
+    if let Some(tenant) = tenant {
+        tenant.defaults.apply_to_chat_completions(&mut payload);
+    }
 
-    if payload.stream == Some(false) {
-        error!("Stream is set to false");
-        return Err(anyhow!("Stream is set to false").into());
+    let usage = usage_reporter(
+        &state,
+        &request_id,
+        &principal,
+        &requested_model,
+        &payload.model,
+    );
+    if let Some(error) = rejection.and_then(|Extension(rejection)| rejection.take()) {
+        usage.reject(error.status, error.kind);
+        return Err(OpenAIError(error));
     }
+    check_model_access(&principal, tenant, &payload.model)
+        .map_err(OpenAIError)
+        .inspect_err(|e| usage.reject(e.0.status, e.0.kind))?;
+    let permit = state
+        .concurrency
+        .acquire(&settings.concurrency, &principal, tenant, &payload.model)
+        .await
+        .map_err(|e| OpenAIError(e.into_app_error(Protocol::OpenAI)))
+        .inspect_err(|e| usage.reject(e.0.status, e.0.kind))?;
+    let bedrock = state
+        .bedrock
+        .lease_for(aws_identity(&principal, tenant))
+        .await;
+    let provider = BedrockChatCompletionsProvider::new(bedrock.client().clone())
+        .with_inference_profiles(settings.inference_profiles_for(tenant))
+        .with_fallback_models(settings.fallbacks_for(&principal, tenant, &payload.model));
+    let ticket = state
+        .throttle
+        .acquire(&settings.throttling, bedrock.name(), &payload.model)
+        .await
+        .map_err(OpenAIError)
+        .inspect_err(|e| usage.reject(e.0.status, e.0.kind))?;
 
-    let stream = BedrockChatCompletionsProvider::new(state.bedrockruntime_client.clone())
-        .chat_completions_stream(payload, log_token_usage)
-        .await?;
+    if payload.stream == Some(true) {
+        let result = provider
+            .chat_completions_stream(payload, Some(requested_model), usage.clone())
+            .await
+            .map_err(AppError::from);
+        bedrock.record(&result);
+        let stream = ticket
+            .record_served(result, &usage.model())
+            .map_err(OpenAIError)
+            .inspect_err(|e| usage.fail(e.0.status, e.0.kind))?;
+        let served_model = [(BEDROCK_MODEL_ID, usage.model())];
+        let stream = state
+            .shutdown
+            .guard(bedrock.hold(permit.hold(stream)), move || {
+                usage.fail_stream(ErrorKind::Overloaded);
+                vec![
+                    openai_error_event(ErrorKind::Overloaded, SHUTDOWN_MESSAGE, usage.request_id()),
+                    Ok(Event::default().data(DONE_MESSAGE)),
+                ]
+            });
+        return Ok((StatusCode::OK, served_model, Sse::new(stream)).into_response());
+    }
 
-    Ok((StatusCode::OK, Sse::new(stream)))
+    let result = provider
+        .chat_completions(payload, Some(requested_model), usage.clone())
+        .await
+        .map_err(AppError::from);
+    bedrock.record(&result);
+    let response = ticket
+        .record_served(result, &usage.model())
+        .map_err(OpenAIError)
+        .inspect_err(|e| usage.fail(e.0.status, e.0.kind))?;
+    drop(permit);
+    let served_model = [(BEDROCK_MODEL_ID, usage.model())];
+    Ok((StatusCode::OK, served_model, Json(response)).into_response())
+}
+
+pub async fn handle_models(
+    State(state): State<Arc<AppState>>,
+    Extension(principal): Extension<Principal>,
+) -> impl IntoResponse {
+    let settings = state.settings.load();
+    let tenant = settings.tenants.of(&principal);
+    Json(ModelList {
+        object: "list".to_string(),
+        data: settings
+            .models_for(&principal, tenant)
+            .models()
+            .iter()
+            .map(CatalogModel::to_openai)
+            .collect(),
+    })
+}
+
+pub async fn handle_model(
+    State(state): State<Arc<AppState>>,
+    Extension(principal): Extension<Principal>,
+    Path(model_id): Path<String>,
+) -> Result<impl IntoResponse, OpenAIError> {
+    let settings = state.settings.load();
+    let tenant = settings.tenants.of(&principal);
+    let models = settings.models_for(&principal, tenant);
+    let model = models.get(&model_id).ok_or_else(|| {
+        AppError::new(
+            ErrorKind::NotFound,
+            format!("The model '{model_id}' does not exist"),
+        )
+    })?;
+    Ok(Json(model.to_openai()))
 }
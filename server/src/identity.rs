@@ -0,0 +1,176 @@
+use anyhow::bail;
+use aws_config::{SdkConfig, profile::ProfileFileCredentialsProvider, sts::AssumeRoleProvider};
+use aws_sdk_bedrockruntime::{Client, config::SharedCredentialsProvider};
+use std::collections::BTreeMap;
+
+/// Longest role session name STS accepts.
+const MAX_SESSION_NAME_LEN: usize = 64;
+
+/// The AWS identity a principal's Bedrock calls are made as, instead of the
+/// proxy's own: a named profile's credentials, a role assumed with the
+/// proxy's (or the profile's) credentials, or both. Assumed-role sessions
+/// carry `session_tags`, e.g. for cost allocation.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AwsIdentity {
+    pub role_arn: Option<String>,
+    pub profile: Option<String>,
+    pub external_id: Option<String>,
+    pub session_tags: BTreeMap<String, String>,
+    /// The role session name, derived from the principal's name.
+    pub session_name: String,
+}
+
+impl AwsIdentity {
+    /// An identity for the principal `name`, or `None` if neither a role nor
+    /// a profile is set.
+    pub fn new(
+        name: &str,
+        role_arn: Option<String>,
+        profile: Option<String>,
+        external_id: Option<String>,
+        session_tags: BTreeMap<String, String>,
+    ) -> Option<Self> {
+        if role_arn.is_none()
+            && profile.is_none()
+            && external_id.is_none()
+            && session_tags.is_empty()
+        {
+            return None;
+        }
+        Some(Self {
+            role_arn,
+            profile,
+            external_id,
+            session_tags,
+            session_name: session_name(name),
+        })
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match (&self.role_arn, &self.profile) {
+            (None, None) => bail!("set role_arn, aws_profile or both"),
+            (None, Some(_)) if self.external_id.is_some() || !self.session_tags.is_empty() => {
+                bail!("external_id and session_tags require role_arn")
+            }
+            (Some(role_arn), _) if !role_arn.starts_with("arn:") => {
+                bail!("role_arn {:?} is not an ARN", role_arn)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The role, or else the profile, e.g. to tell identities apart in logs.
+    pub fn label(&self) -> &str {
+        self.role_arn
+            .as_deref()
+            .or(self.profile.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// A client like `client` that signs with this identity's credentials.
+    /// Roles are assumed with the credentials of `source`, the AWS
+    /// configuration `client` was built from. The client caches the
+    /// credentials and refreshes them before they expire, so it should be
+    /// reused.
+    pub async fn client(&self, client: &Client, source: &SdkConfig) -> Client {
+        let profile = self.profile.as_ref().map(|profile| {
+            ProfileFileCredentialsProvider::builder()
+                .profile_name(profile)
+                .build()
+        });
+        let credentials = match (&self.role_arn, profile) {
+            (None, Some(profile)) => SharedCredentialsProvider::new(profile),
+            (None, None) => unreachable!("validated identities have a role or a profile"),
+            (Some(role_arn), profile) => {
+                let mut builder = AssumeRoleProvider::builder(role_arn)
+                    .session_name(&self.session_name)
+                    .tags(&self.session_tags)
+                    .configure(source);
+                if let Some(external_id) = &self.external_id {
+                    builder = builder.external_id(external_id);
+                }
+                SharedCredentialsProvider::new(match profile {
+                    Some(profile) => builder.build_from_provider(profile).await,
+                    None => builder.build().await,
+                })
+            }
+        };
+        Client::from_conf(
+            client
+                .config()
+                .to_builder()
+                .credentials_provider(credentials)
+                .build(),
+        )
+    }
+}
+
+/// `llm-proxy-<name>`, limited to the characters and length STS accepts.
+fn session_name(name: &str) -> String {
+    format!("llm-proxy-{name}")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "_+=,.@-".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .take(MAX_SESSION_NAME_LEN)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(role_arn: Option<&str>, profile: Option<&str>) -> AwsIdentity {
+        AwsIdentity::new(
+            "search team",
+            role_arn.map(str::to_string),
+            profile.map(str::to_string),
+            None,
+            BTreeMap::new(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn identity_needs_a_role_or_profile() {
+        assert!(AwsIdentity::new("alice", None, None, None, BTreeMap::new()).is_none());
+        assert!(
+            identity(Some("arn:aws:iam::123456789012:role/search"), None)
+                .validate()
+                .is_ok()
+        );
+        assert!(identity(None, Some("search")).validate().is_ok());
+        assert!(identity(Some("search"), None).validate().is_err());
+
+        let tags_only = AwsIdentity::new(
+            "alice",
+            None,
+            None,
+            None,
+            BTreeMap::from([("team".to_string(), "search".to_string())]),
+        )
+        .unwrap();
+        assert!(tags_only.validate().is_err());
+        assert!(
+            AwsIdentity {
+                profile: Some("search".to_string()),
+                ..tags_only
+            }
+            .validate()
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn session_name_is_sanitized_and_bounded() {
+        assert_eq!(
+            identity(None, Some("search")).session_name,
+            "llm-proxy-search-team"
+        );
+        assert_eq!(session_name(&"a".repeat(100)).len(), MAX_SESSION_NAME_LEN);
+    }
+}
@@ -0,0 +1,623 @@
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, params, params_from_iter};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use tracing::error;
+
+use crate::{budgets::BudgetPeriod, utils::glob_match};
+
+/// An entry of the `[[prices]]` table in `config.toml`, in USD per million
+/// tokens. `model` is an exact Bedrock model ID or a glob pattern such as
+/// `*anthropic.claude-opus-4*`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelPrice {
+    pub model: String,
+    #[serde(default)]
+    pub input: f64,
+    #[serde(default)]
+    pub output: f64,
+    #[serde(default)]
+    pub cache_read: f64,
+    #[serde(default)]
+    pub cache_write: f64,
+}
+
+/// Prices requests by model. Exact entries take precedence over patterns;
+/// patterns are tried in config order. Unpriced models cost nothing.
+#[derive(Clone, Debug, Default)]
+pub struct PriceTable {
+    prices: Vec<ModelPrice>,
+}
+
+impl PriceTable {
+    pub fn new(prices: Vec<ModelPrice>) -> anyhow::Result<Self> {
+        for price in &prices {
+            if [
+                price.input,
+                price.output,
+                price.cache_read,
+                price.cache_write,
+            ]
+            .iter()
+            .any(|p| !p.is_finite() || *p < 0.0)
+            {
+                bail!("price for {:?} must be non-negative", price.model);
+            }
+        }
+        Ok(Self { prices })
+    }
+
+    pub fn price(&self, model: &str) -> Option<&ModelPrice> {
+        let (exact, patterns): (Vec<_>, Vec<_>) = self
+            .prices
+            .iter()
+            .partition(|price| !price.model.contains('*'));
+        exact
+            .into_iter()
+            .chain(patterns)
+            .find(|price| glob_match(&price.model, model))
+    }
+
+    pub fn cost(&self, record: &UsageRecord) -> f64 {
+        self.price(&record.model).map_or(0.0, |price| {
+            (record.input_tokens as f64 * price.input
+                + record.output_tokens as f64 * price.output
+                + record.cache_read_input_tokens as f64 * price.cache_read
+                + record.cache_write_input_tokens as f64 * price.cache_write)
+                / 1_000_000.0
+        })
+    }
+}
+
+/// One completed request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UsageRecord {
+    pub timestamp: DateTime<Utc>,
+    pub request_id: String,
+    /// Bedrock's `x-amzn-requestid` for the last upstream call, if any.
+    pub bedrock_request_id: Option<String>,
+    pub principal: String,
+    pub model: String,
+    pub status: u16,
+    /// The error type of a failed request, including streams that failed
+    /// after a 200 was sent.
+    pub error: Option<String>,
+    /// Whether the proxy refused the request rather than Bedrock failing it.
+    pub rejected: bool,
+    pub stop_reason: Option<String>,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cache_read_input_tokens: i64,
+    pub cache_write_input_tokens: i64,
+    pub latency_ms: i64,
+    pub cost_usd: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeBucket {
+    Hour,
+    Day,
+    Month,
+    #[default]
+    None,
+}
+
+impl TimeBucket {
+    fn format(self) -> Option<&'static str> {
+        match self {
+            TimeBucket::Hour => Some("%Y-%m-%dT%H:00:00Z"),
+            TimeBucket::Day => Some("%Y-%m-%dT00:00:00Z"),
+            TimeBucket::Month => Some("%Y-%m-01T00:00:00Z"),
+            TimeBucket::None => None,
+        }
+    }
+}
+
+/// Aggregation parameters of `GET /admin/usage`.
+#[derive(Clone, Debug, Default)]
+pub struct UsageQuery {
+    pub by_key: bool,
+    pub by_model: bool,
+    pub bucket: TimeBucket,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub key: Option<String>,
+    pub model: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct UsageSummary {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bucket_start: Option<String>,
+    pub requests: i64,
+    pub failed_requests: i64,
+    /// Failed requests the proxy refused to send, such as those for a model
+    /// the key may not use or past a rate limit or budget.
+    pub rejected_requests: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cache_read_input_tokens: i64,
+    pub cache_write_input_tokens: i64,
+    pub cost_usd: f64,
+}
+
+/// What a key has spent in a budget period. Tokens count like rate limits:
+/// cache writes as input, cache reads not at all.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Spend {
+    pub tokens: i64,
+    pub cost_usd: f64,
+}
+
+impl Spend {
+    fn add(&mut self, record: &UsageRecord) {
+        self.tokens += record.input_tokens + record.cache_write_input_tokens + record.output_tokens;
+        self.cost_usd += record.cost_usd;
+    }
+}
+
+/// A key's spend since the start of a period or the last reset within it.
+#[derive(Debug)]
+struct PeriodSpend {
+    since: DateTime<Utc>,
+    spend: Spend,
+}
+
+/// Append-only SQLite ledger of completed requests. Writes happen on the
+/// blocking pool so recording never stalls a response. Each key's spend in
+/// the current day and month is kept in memory for budget checks, and
+/// rebuilt from the ledger on open.
+#[derive(Clone)]
+pub struct UsageLedger {
+    conn: Arc<Mutex<Connection>>,
+    spend: Arc<Mutex<HashMap<(String, BudgetPeriod), PeriodSpend>>>,
+}
+
+impl UsageLedger {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> anyhow::Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS usage (
+                id INTEGER PRIMARY KEY,
+                timestamp INTEGER NOT NULL,
+                request_id TEXT NOT NULL,
+                bedrock_request_id TEXT,
+                principal TEXT NOT NULL,
+                model TEXT NOT NULL,
+                status INTEGER NOT NULL,
+                error TEXT,
+                rejected INTEGER NOT NULL DEFAULT 0,
+                stop_reason TEXT,
+                input_tokens INTEGER NOT NULL,
+                output_tokens INTEGER NOT NULL,
+                cache_read_input_tokens INTEGER NOT NULL,
+                cache_write_input_tokens INTEGER NOT NULL,
+                latency_ms INTEGER NOT NULL,
+                cost_usd REAL NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS usage_timestamp ON usage (timestamp);
+            CREATE TABLE IF NOT EXISTS budget_resets (
+                principal TEXT NOT NULL,
+                period TEXT NOT NULL,
+                reset_at INTEGER NOT NULL
+            );",
+        )?;
+        // Ledgers written before rejections were told apart from failures.
+        let has_rejected: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('usage') WHERE name = 'rejected'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_rejected {
+            conn.execute(
+                "ALTER TABLE usage ADD COLUMN rejected INTEGER NOT NULL DEFAULT 0",
+                [],
+            )?;
+        }
+        let spend = load_spend(&conn, Utc::now())?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            spend: Arc::new(Mutex::new(spend)),
+        })
+    }
+
+    fn insert(&self, record: &UsageRecord) -> anyhow::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO usage (
+                timestamp, request_id, bedrock_request_id, principal, model, status,
+                error, rejected, stop_reason, input_tokens, output_tokens,
+                cache_read_input_tokens, cache_write_input_tokens, latency_ms, cost_usd
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            params![
+                record.timestamp.timestamp(),
+                record.request_id,
+                record.bedrock_request_id,
+                record.principal,
+                record.model,
+                record.status,
+                record.error,
+                record.rejected,
+                record.stop_reason,
+                record.input_tokens,
+                record.output_tokens,
+                record.cache_read_input_tokens,
+                record.cache_write_input_tokens,
+                record.latency_ms,
+                record.cost_usd,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Records `record` in the background. Failures are logged, not returned:
+    /// the request has already been served.
+    pub fn record(&self, record: UsageRecord) {
+        let mut spend = self.spend.lock().unwrap();
+        for period in BudgetPeriod::ALL {
+            let period_start = period.start(record.timestamp);
+            let current = spend
+                .entry((record.principal.clone(), period))
+                .or_insert_with(|| PeriodSpend {
+                    since: period_start,
+                    spend: Spend::default(),
+                });
+            if current.since < period_start {
+                *current = PeriodSpend {
+                    since: period_start,
+                    spend: Spend::default(),
+                };
+            }
+            current.spend.add(&record);
+        }
+        drop(spend);
+
+        let ledger = self.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = ledger.insert(&record) {
+                error!("Failed to record usage {:?}: {:?}", record, e);
+            }
+        });
+    }
+
+    /// What `principal` has spent in the `period` containing `now`, or
+    /// since the budget was last reset.
+    pub fn spend(&self, principal: &str, period: BudgetPeriod, now: DateTime<Utc>) -> Spend {
+        self.spend
+            .lock()
+            .unwrap()
+            .get(&(principal.to_string(), period))
+            .filter(|current| current.since >= period.start(now))
+            .map(|current| current.spend)
+            .unwrap_or_default()
+    }
+
+    /// Starts `principal`'s spend in the current `period` over from zero.
+    /// The reset is persisted, so the spend stays reset after a restart.
+    pub async fn reset_spend(&self, principal: &str, period: BudgetPeriod) -> anyhow::Result<()> {
+        let now = Utc::now();
+        self.spend.lock().unwrap().insert(
+            (principal.to_string(), period),
+            PeriodSpend {
+                since: now,
+                spend: Spend::default(),
+            },
+        );
+        let ledger = self.clone();
+        let principal = principal.to_string();
+        tokio::task::spawn_blocking(move || {
+            ledger.conn.lock().unwrap().execute(
+                "INSERT INTO budget_resets (principal, period, reset_at) VALUES (?1, ?2, ?3)",
+                params![principal, period.as_str(), now.timestamp()],
+            )
+        })
+        .await??;
+        Ok(())
+    }
+
+    pub async fn query(&self, query: UsageQuery) -> anyhow::Result<Vec<UsageSummary>> {
+        let ledger = self.clone();
+        tokio::task::spawn_blocking(move || ledger.query_blocking(&query)).await?
+    }
+
+    fn query_blocking(&self, query: &UsageQuery) -> anyhow::Result<Vec<UsageSummary>> {
+        let mut group_by = Vec::new();
+        let key_column = if query.by_key {
+            group_by.push("principal");
+            "principal"
+        } else {
+            "NULL"
+        };
+        let model_column = if query.by_model {
+            group_by.push("model");
+            "model"
+        } else {
+            "NULL"
+        };
+        let bucket_column = match query.bucket.format() {
+            Some(format) => {
+                group_by.push("bucket_start");
+                format!("strftime('{format}', timestamp, 'unixepoch')")
+            }
+            None => "NULL".to_string(),
+        };
+
+        let mut filters = Vec::new();
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+        if let Some(from) = query.from {
+            filters.push("timestamp >= ?");
+            values.push(from.timestamp().into());
+        }
+        if let Some(to) = query.to {
+            filters.push("timestamp < ?");
+            values.push(to.timestamp().into());
+        }
+        if let Some(key) = &query.key {
+            filters.push("principal = ?");
+            values.push(key.clone().into());
+        }
+        if let Some(model) = &query.model {
+            filters.push("model = ?");
+            values.push(model.clone().into());
+        }
+
+        let mut sql = format!(
+            "SELECT {key_column}, {model_column}, {bucket_column} AS bucket_start,
+                COUNT(*), SUM(status >= 400 OR error IS NOT NULL), SUM(rejected),
+                SUM(input_tokens), SUM(output_tokens),
+                SUM(cache_read_input_tokens), SUM(cache_write_input_tokens), SUM(cost_usd)
+            FROM usage"
+        );
+        if !filters.is_empty() {
+            sql.push_str(&format!(" WHERE {}", filters.join(" AND ")));
+        }
+        if !group_by.is_empty() {
+            let group_by = group_by.join(", ");
+            sql.push_str(&format!(" GROUP BY {group_by} ORDER BY {group_by}"));
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(&sql)?;
+        let rows = statement.query_map(params_from_iter(values), |row| {
+            Ok(UsageSummary {
+                key: row.get(0)?,
+                model: row.get(1)?,
+                bucket_start: row.get(2)?,
+                requests: row.get(3)?,
+                failed_requests: row.get::<_, Option<i64>>(4)?.unwrap_or(0),
+                rejected_requests: row.get::<_, Option<i64>>(5)?.unwrap_or(0),
+                input_tokens: row.get::<_, Option<i64>>(6)?.unwrap_or(0),
+                output_tokens: row.get::<_, Option<i64>>(7)?.unwrap_or(0),
+                cache_read_input_tokens: row.get::<_, Option<i64>>(8)?.unwrap_or(0),
+                cache_write_input_tokens: row.get::<_, Option<i64>>(9)?.unwrap_or(0),
+                cost_usd: row.get::<_, Option<f64>>(10)?.unwrap_or(0.0),
+            })
+        })?;
+        let summaries = rows.collect::<Result<Vec<_>, _>>()?;
+        // An ungrouped query over an empty ledger still yields one all-zero row.
+        Ok(summaries
+            .into_iter()
+            .filter(|summary| summary.requests > 0)
+            .collect())
+    }
+}
+
+/// Each key's spend in the current day and month, as of `now`, counted
+/// from its last reset.
+fn load_spend(
+    conn: &Connection,
+    now: DateTime<Utc>,
+) -> anyhow::Result<HashMap<(String, BudgetPeriod), PeriodSpend>> {
+    let mut spend = HashMap::new();
+    let mut statement = conn.prepare(
+        "SELECT principal, since,
+            SUM(CASE WHEN timestamp >= since
+                THEN input_tokens + cache_write_input_tokens + output_tokens ELSE 0 END),
+            SUM(CASE WHEN timestamp >= since THEN cost_usd ELSE 0 END)
+        FROM (
+            SELECT principal, timestamp, input_tokens, cache_write_input_tokens,
+                output_tokens, cost_usd,
+                MAX(?1, COALESCE((SELECT MAX(reset_at) FROM budget_resets r
+                    WHERE r.principal = u.principal AND r.period = ?2), 0)) AS since
+            FROM usage u
+            WHERE timestamp >= ?1
+        )
+        GROUP BY principal",
+    )?;
+    for period in BudgetPeriod::ALL {
+        let period_start = period.start(now);
+        let rows =
+            statement.query_map(params![period_start.timestamp(), period.as_str()], |row| {
+                let since: i64 = row.get(1)?;
+                Ok((
+                    row.get::<_, String>(0)?,
+                    PeriodSpend {
+                        since: DateTime::from_timestamp(since, 0).unwrap_or(period_start),
+                        spend: Spend {
+                            tokens: row.get::<_, Option<i64>>(2)?.unwrap_or(0),
+                            cost_usd: row.get::<_, Option<f64>>(3)?.unwrap_or(0.0),
+                        },
+                    },
+                ))
+            })?;
+        for row in rows {
+            let (principal, period_spend) = row?;
+            spend.insert((principal, period), period_spend);
+        }
+    }
+    Ok(spend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(principal: &str, model: &str, timestamp: &str) -> UsageRecord {
+        UsageRecord {
+            timestamp: timestamp.parse().unwrap(),
+            request_id: "req_test".to_string(),
+            bedrock_request_id: None,
+            principal: principal.to_string(),
+            model: model.to_string(),
+            status: 200,
+            error: None,
+            rejected: false,
+            stop_reason: Some("end_turn".to_string()),
+            input_tokens: 1000,
+            output_tokens: 100,
+            cache_read_input_tokens: 0,
+            cache_write_input_tokens: 0,
+            latency_ms: 50,
+            cost_usd: 0.5,
+        }
+    }
+
+    #[test]
+    fn exact_price_wins_over_pattern() {
+        let prices = PriceTable::new(vec![
+            ModelPrice {
+                model: "*claude-opus*".to_string(),
+                input: 15.0,
+                ..Default::default()
+            },
+            ModelPrice {
+                model: "us.anthropic.claude-opus-4-8".to_string(),
+                input: 5.0,
+                output: 25.0,
+                ..Default::default()
+            },
+        ])
+        .unwrap();
+
+        let mut usage = record(
+            "alice",
+            "us.anthropic.claude-opus-4-8",
+            "2026-10-17T10:00:00Z",
+        );
+        assert_eq!(prices.cost(&usage), (1000.0 * 5.0 + 100.0 * 25.0) / 1e6);
+        usage.model = "global.anthropic.claude-opus-4-6-v1".to_string();
+        assert_eq!(prices.cost(&usage), 1000.0 * 15.0 / 1e6);
+        usage.model = "meta.llama3".to_string();
+        assert_eq!(prices.cost(&usage), 0.0);
+    }
+
+    #[test]
+    fn query_aggregates_by_key_model_and_day() {
+        let ledger = UsageLedger::open_in_memory().unwrap();
+        for record in [
+            record("alice", "opus", "2026-10-16T10:00:00Z"),
+            record("alice", "opus", "2026-10-17T09:00:00Z"),
+            record("alice", "opus", "2026-10-17T11:00:00Z"),
+            record("alice", "sonnet", "2026-10-17T11:00:00Z"),
+            UsageRecord {
+                status: 429,
+                input_tokens: 0,
+                output_tokens: 0,
+                cost_usd: 0.0,
+                ..record("bob", "opus", "2026-10-17T12:00:00Z")
+            },
+        ] {
+            ledger.insert(&record).unwrap();
+        }
+
+        let by_key = ledger
+            .query_blocking(&UsageQuery {
+                by_key: true,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(by_key.len(), 2);
+        assert_eq!(by_key[0].key.as_deref(), Some("alice"));
+        assert_eq!(by_key[0].requests, 4);
+        assert_eq!(by_key[0].input_tokens, 4000);
+        assert_eq!(by_key[0].cost_usd, 2.0);
+        assert_eq!(by_key[1].failed_requests, 1);
+
+        let daily = ledger
+            .query_blocking(&UsageQuery {
+                by_model: true,
+                bucket: TimeBucket::Day,
+                key: Some("alice".to_string()),
+                from: Some("2026-10-17T00:00:00Z".parse().unwrap()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            daily
+                .iter()
+                .map(|s| (s.model.as_deref().unwrap(), s.requests))
+                .collect::<Vec<_>>(),
+            [("opus", 2), ("sonnet", 1)]
+        );
+        assert_eq!(
+            daily[0].bucket_start.as_deref(),
+            Some("2026-10-17T00:00:00Z")
+        );
+        assert!(daily[0].key.is_none());
+    }
+
+    #[test]
+    fn spend_is_rebuilt_from_the_ledger_and_resets() {
+        let ledger = UsageLedger::open_in_memory().unwrap();
+        for record in [
+            record("alice", "opus", "2026-09-30T23:00:00Z"),
+            record("alice", "opus", "2026-10-16T10:00:00Z"),
+            record("alice", "opus", "2026-10-17T09:00:00Z"),
+            record("alice", "opus", "2026-10-17T11:00:00Z"),
+            record("bob", "opus", "2026-10-17T11:00:00Z"),
+        ] {
+            ledger.insert(&record).unwrap();
+        }
+        let reset_at: DateTime<Utc> = "2026-10-17T10:00:00Z".parse().unwrap();
+        ledger
+            .conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO budget_resets (principal, period, reset_at) VALUES ('alice', 'month', ?1)",
+                params![reset_at.timestamp()],
+            )
+            .unwrap();
+
+        let now = "2026-10-17T12:00:00Z".parse().unwrap();
+        let spend = load_spend(&ledger.conn.lock().unwrap(), now).unwrap();
+        let tokens = |principal: &str, period| spend[&(principal.to_string(), period)].spend.tokens;
+        assert_eq!(tokens("alice", BudgetPeriod::Day), 2200);
+        assert_eq!(tokens("alice", BudgetPeriod::Month), 1100);
+        assert_eq!(tokens("bob", BudgetPeriod::Month), 1100);
+        assert_eq!(
+            spend[&("alice".to_string(), BudgetPeriod::Month)]
+                .spend
+                .cost_usd,
+            0.5
+        );
+    }
+
+    #[test]
+    fn empty_ledger_returns_no_rows() {
+        let ledger = UsageLedger::open_in_memory().unwrap();
+        assert!(
+            ledger
+                .query_blocking(&UsageQuery::default())
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
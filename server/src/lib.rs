@@ -1,27 +1,108 @@
-use aws_sdk_bedrockruntime::Client;
-use axum::{Router, routing::post};
+use arc_swap::ArcSwap;
+use axum::{
+    Router, middleware,
+    routing::{get, post},
+};
 use std::sync::Arc;
 
+pub mod aliases;
+pub mod auth;
+pub mod bedrock_pool;
+pub mod budgets;
+pub mod concurrency;
 pub mod error;
+pub mod fallbacks;
 pub mod handlers;
+pub mod identity;
+pub mod ledger;
+pub mod metrics;
+pub mod model_access;
+pub mod models;
+pub mod rate_limit;
+pub mod request_id;
+pub mod response_cache;
+pub mod settings;
+pub mod shutdown;
+pub mod telemetry;
+pub mod tenants;
+pub mod throttle;
+pub mod tls;
 pub mod utils;
 
-use handlers::anthropic::{handle_v1_messages, handle_v1_messages_count_tokens};
-use handlers::openai::handle_chat_completions;
+use bedrock_pool::BedrockPool;
+use budgets::enforce_budgets;
+use concurrency::ConcurrencyLimiter;
+use handlers::admin::{handle_budgets, handle_reset_budget, handle_usage};
+use handlers::anthropic::{
+    handle_v1_messages, handle_v1_messages_count_tokens, handle_v1_model, handle_v1_models,
+};
+use handlers::openai::{handle_chat_completions, handle_model, handle_models};
+use ledger::UsageLedger;
+use metrics::{handle_metrics, track_requests};
+use rate_limit::{RateLimiter, enforce_rate_limits};
+use request_id::assign_request_id;
+use response_cache::ResponseCache;
+use settings::Settings;
+use shutdown::Shutdown;
+use telemetry::trace_requests;
+use throttle::AdaptiveThrottle;
 
 pub struct AppState {
-    pub bedrockruntime_client: Client,
-    pub inference_profile_prefixes: Vec<String>,
-    pub anthropic_beta_whitelist: Vec<String>,
+    pub bedrock: BedrockPool,
+    /// Replaced as a whole when the config file is reloaded.
+    pub settings: ArcSwap<Settings>,
+    pub rate_limiter: RateLimiter,
+    pub concurrency: ConcurrencyLimiter,
+    pub throttle: AdaptiveThrottle,
+    pub response_cache: ResponseCache,
+    pub ledger: UsageLedger,
+    pub shutdown: Shutdown,
 }
 
 pub fn get_app(state: Arc<AppState>) -> Router {
     Router::new()
-        .route("/chat/completions", post(handle_chat_completions))
-        .route("/v1/messages", post(handle_v1_messages))
+        .route(
+            "/chat/completions",
+            post(handle_chat_completions)
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    enforce_rate_limits,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    enforce_budgets,
+                )),
+        )
+        .route("/models", get(handle_models))
+        .route("/models/{model_id}", get(handle_model))
+        .route(
+            "/v1/messages",
+            post(handle_v1_messages)
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    enforce_rate_limits,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    enforce_budgets,
+                )),
+        )
         .route(
             "/v1/messages/count_tokens",
             post(handle_v1_messages_count_tokens),
         )
+        .route("/v1/models", get(handle_v1_models))
+        .route("/v1/models/{model_id}", get(handle_v1_model))
+        .route("/admin/usage", get(handle_usage))
+        .route("/admin/budgets", get(handle_budgets))
+        .route("/admin/budgets/reset", post(handle_reset_budget))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::authenticate,
+        ))
+        .route_layer(middleware::from_fn(track_requests))
+        .route_layer(middleware::from_fn(assign_request_id))
+        .route_layer(middleware::from_fn(trace_requests))
+        .route("/metrics", get(handle_metrics))
         .with_state(state)
 }
@@ -1,82 +1,185 @@
+use arc_swap::ArcSwap;
 use aws_config::BehaviorVersion;
 use aws_config::retry::RetryConfig;
-use aws_sdk_bedrockruntime::Client;
-use config::{Config, File};
-use server::{AppState, get_app};
-use std::sync::Arc;
-use tracing::info;
-
-async fn load_config() -> anyhow::Result<(String, u16, Vec<String>, Vec<String>)> {
-    let settings = Config::builder()
-        .add_source(File::with_name("config"))
-        .build()?;
-
-    let host: String = settings
-        .get("host")
-        .unwrap_or_else(|_| "127.0.0.1".to_string());
-    let port: u16 = settings.get("port").unwrap_or(3000);
-
-    let inference_profile_prefixes: Vec<String> = settings
-        .get("inference_profile_prefixes")
-        .unwrap_or_else(|_| vec!["us.".to_string()]);
-
-    info!(
-        "inference_profile_prefixes: {:?}",
-        inference_profile_prefixes
-    );
-
-    let anthropic_beta_whitelist: Vec<String> = settings
-        .get("anthropic_beta_whitelist")
-        .unwrap_or_else(|_| {
-            vec![
-                "adaptive-thinking-2026-01-28".to_string(),
-                "claude-code-20250219".to_string(),
-                "context-1m-2025-08-07".to_string(),
-                "effort-2025-11-24".to_string(),
-                "interleaved-thinking-2025-05-14".to_string(),
-                "structured-outputs-2025-12-15".to_string(),
-            ]
-        });
-
-    info!("anthropic_beta_whitelist: {:?}", anthropic_beta_whitelist);
-
-    Ok((
-        host,
-        port,
-        inference_profile_prefixes,
-        anthropic_beta_whitelist,
-    ))
+use clap::Parser;
+use futures::FutureExt;
+use server::{
+    AppState,
+    bedrock_pool::BedrockPool,
+    concurrency::ConcurrencyLimiter,
+    get_app,
+    ledger::UsageLedger,
+    rate_limit::RateLimiter,
+    response_cache::ResponseCache,
+    settings::{ConfigSource, Settings, SettingsLoader, SettingsReloader},
+    shutdown::{FLUSH_TIMEOUT, Shutdown},
+    telemetry::init_tracing,
+    throttle::AdaptiveThrottle,
+    tls::{TlsConnectInfo, TlsListener, watch_certificates},
+};
+use std::{future::IntoFuture, path::PathBuf, sync::Arc, time::Duration};
+use tracing::{error, info, warn};
+
+/// OpenAI- and Anthropic-compatible API proxy for Amazon Bedrock.
+///
+/// Every key of the configuration file can also be set through an
+/// `LLM_PROXY_<KEY>` environment variable, e.g. `LLM_PROXY_PORT=8080` or
+/// `LLM_PROXY_INFERENCE_PROFILE_PREFIXES=us.,global.`, which takes
+/// precedence over the file.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Configuration file.
+    #[arg(short, long, env = "LLM_PROXY_CONFIG", default_value = "config.toml")]
+    config: PathBuf,
+
+    /// Address to listen on, overriding `host` from the configuration.
+    #[arg(long)]
+    host: Option<String>,
+
+    /// Port to listen on, overriding `port` from the configuration.
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// Validate the configuration and exit.
+    #[arg(long)]
+    check_config: bool,
+
+    /// Print the configuration with defaults and overrides applied, then exit.
+    #[arg(long)]
+    print_effective_config: bool,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
-    info!("Initializing LLM proxy server");
+    let cli = Cli::parse();
+    let source = ConfigSource {
+        path: cli.config,
+        host: cli.host,
+        port: cli.port,
+    };
+    let config = source.read()?;
+    if cli.check_config || cli.print_effective_config {
+        Settings::from_config(&config)?;
+        if cli.print_effective_config {
+            print!("{}", toml::to_string_pretty(&config)?);
+        } else {
+            if let Some(tls) = &config.tls {
+                tls.server_config()?;
+            }
+            println!("{}: configuration is valid", source.path.display());
+        }
+        return Ok(());
+    }
 
-    let (host, port, inference_profile_prefixes, anthropic_beta_whitelist) = load_config().await?;
+    let tracer_provider = init_tracing(config.otlp_traces_endpoint.as_deref())?;
+    info!("Initializing LLM proxy server");
+    info!("Configuration read from {}", source.path.display());
+    if let Some(endpoint) = &config.otlp_traces_endpoint {
+        info!("Exporting traces to {}", endpoint);
+    }
+    let host = config.host.clone();
+    let port = config.port;
+    let ledger_path = config.ledger_path.clone();
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout_secs);
+    let tls = config.tls.clone();
     info!("Starting server on {}:{}", host, port);
 
     // Retries are owned by the SDK's `standard` strategy: exponential backoff
     // with jitter plus a client-side retry-quota token bucket. We only widen the
-    // attempt budget; the proxy adds no retry loop of its own.
+    // attempt budget; the proxy adds no retry loop of its own, but throttling
+    // that gets through slows later requests down (see `throttle`).
     let aws_config = aws_config::defaults(BehaviorVersion::latest())
         .retry_config(RetryConfig::standard().with_max_attempts(5))
         .load()
         .await;
-    let bedrockruntime_client = Client::new(&aws_config);
-    info!("AWS Bedrock client initialized");
+    let bedrock = BedrockPool::connect(&config.bedrock_pool, &aws_config).await?;
+    info!("AWS Bedrock clients initialized");
+
+    let loader = SettingsLoader::new(source, aws_sdk_bedrock::Client::new(&aws_config));
+    let loaded = loader.build(config).await?;
+
+    let ledger = UsageLedger::open(&ledger_path)?;
+    info!("Usage ledger opened at {}", ledger_path);
 
     let state = Arc::new(AppState {
-        bedrockruntime_client,
-        inference_profile_prefixes,
-        anthropic_beta_whitelist,
+        bedrock,
+        settings: ArcSwap::from_pointee(loaded.settings),
+        rate_limiter: RateLimiter::default(),
+        concurrency: ConcurrencyLimiter::default(),
+        throttle: AdaptiveThrottle::default(),
+        response_cache: ResponseCache::default(),
+        ledger,
+        shutdown: Shutdown::default(),
+    });
+
+    let reloader = SettingsReloader::new(loader, state.clone(), loaded.config);
+    tokio::spawn(async move {
+        if let Err(e) = reloader.watch().await {
+            error!("Config reloading is disabled: {:?}", e);
+        }
     });
 
     info!("Routes configured, binding to {}:{}", host, port);
     let listener = tokio::net::TcpListener::bind(format!("{host}:{port}")).await?;
     info!("Server started successfully, listening for requests");
 
-    axum::serve(listener, get_app(state)).await?;
+    let shutdown = state.shutdown.clone();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            if let Err(e) = shutdown.handle_signals(shutdown_timeout).await {
+                error!("Graceful shutdown is disabled: {:?}", e);
+            }
+        }
+    });
+    let app = get_app(state);
+    let draining = shutdown.clone().draining();
+    let server = match tls {
+        None => axum::serve(listener, app)
+            .with_graceful_shutdown(draining)
+            .into_future()
+            .boxed(),
+        Some(tls) => {
+            let server_config = Arc::new(ArcSwap::from_pointee(tls.server_config()?));
+            info!(
+                "Serving HTTPS with the certificate from {}{}",
+                tls.cert_path.display(),
+                if tls.client_ca_path.is_some() {
+                    ", verifying client certificates"
+                } else {
+                    ""
+                }
+            );
+            let listener = TlsListener::new(listener, server_config.clone())?;
+            tokio::spawn(async move {
+                if let Err(e) = watch_certificates(tls, server_config).await {
+                    error!("TLS certificate reloading is disabled: {:?}", e);
+                }
+            });
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<TlsConnectInfo>(),
+            )
+            .with_graceful_shutdown(draining)
+            .into_future()
+            .boxed()
+        }
+    };
+    // Once the deadline has ended the open streams, their connections close
+    // and `server` completes; the flush timeout bounds clients slow to read.
+    tokio::select! {
+        result = server => result?,
+        () = async {
+            shutdown.expired().await;
+            tokio::time::sleep(FLUSH_TIMEOUT).await;
+        } => warn!("Connections still open {:?} after the drain deadline, exiting", FLUSH_TIMEOUT),
+    }
+    info!("Server stopped");
+
+    if let Some(tracer_provider) = tracer_provider {
+        tracer_provider.shutdown()?;
+    }
 
     Ok(())
 }
@@ -0,0 +1,196 @@
+use aws_sdk_bedrockruntime::types::TokenUsage;
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{StatusCode, header::CONTENT_TYPE},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use prometheus::{
+    Encoder, GaugeVec, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, TextEncoder,
+    register_gauge_vec, register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec,
+};
+use std::{
+    sync::{Arc, LazyLock, OnceLock},
+    time::Instant,
+};
+use tracing::error;
+
+static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "llm_proxy_http_requests_total",
+        "HTTP requests by route, model and response status",
+        &["route", "model", "status"]
+    )
+    .unwrap()
+});
+
+static HTTP_REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "llm_proxy_http_request_duration_seconds",
+        "Time until the response head is sent; streams are timed separately",
+        &["route"]
+    )
+    .unwrap()
+});
+
+static TOKENS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "llm_proxy_tokens_total",
+        "Tokens reported by Bedrock, by model and type",
+        &["model", "type"]
+    )
+    .unwrap()
+});
+
+static CONCURRENT_REQUESTS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "llm_proxy_concurrent_requests",
+        "Inference requests holding a concurrency slot"
+    )
+    .unwrap()
+});
+
+static QUEUED_REQUESTS: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "llm_proxy_queued_requests",
+        "Inference requests waiting for a concurrency slot"
+    )
+    .unwrap()
+});
+
+static SHED_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "llm_proxy_shed_requests_total",
+        "Requests rejected by a concurrency limit, by limit and reason",
+        &["limit", "reason"]
+    )
+    .unwrap()
+});
+
+static THROTTLE_RATE: LazyLock<GaugeVec> = LazyLock::new(|| {
+    register_gauge_vec!(
+        "llm_proxy_throttle_rate",
+        "Requests per second the adaptive throttle allows, for models Bedrock has throttled",
+        &["region", "model"]
+    )
+    .unwrap()
+});
+
+static THROTTLED_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "llm_proxy_throttled_requests_total",
+        "Requests failed with a 429 by Bedrock or for exceeding the maximum queue wait",
+        &["region", "model", "reason"]
+    )
+    .unwrap()
+});
+
+static BEDROCK_CLIENT_OUTSTANDING: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "llm_proxy_bedrock_client_outstanding_requests",
+        "Requests in flight on each Bedrock client of the pool, streams included",
+        &["client"]
+    )
+    .unwrap()
+});
+
+static BEDROCK_CLIENT_HEALTHY: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "llm_proxy_bedrock_client_healthy",
+        "Whether each Bedrock client of the pool is in rotation (1) or cooling down (0)",
+        &["client"]
+    )
+    .unwrap()
+});
+
+/// The Bedrock model a request resolved to, set by the handler once the
+/// body is parsed so [`track_requests`] can label the request with it.
+#[derive(Clone, Debug, Default)]
+pub struct RequestModel(Arc<OnceLock<String>>);
+
+impl RequestModel {
+    pub fn set(&self, model: &str) {
+        let _ = self.0.set(model.to_string());
+    }
+}
+
+pub fn record_tokens(model: &str, usage: &TokenUsage) {
+    for (kind, count) in [
+        ("input", Some(usage.input_tokens)),
+        ("output", Some(usage.output_tokens)),
+        ("cache_read", usage.cache_read_input_tokens),
+        ("cache_write", usage.cache_write_input_tokens),
+    ] {
+        TOKENS
+            .with_label_values(&[model, kind])
+            .inc_by(count.unwrap_or(0).max(0) as u64);
+    }
+}
+
+pub fn record_concurrency(in_flight: usize, queued: usize) {
+    CONCURRENT_REQUESTS.set(in_flight as i64);
+    QUEUED_REQUESTS.set(queued as i64);
+}
+
+pub fn record_shed_request(limit: &str, reason: &str) {
+    SHED_REQUESTS.with_label_values(&[limit, reason]).inc();
+}
+
+pub fn record_throttle_rate(region: &str, model: &str, rate: f64) {
+    THROTTLE_RATE.with_label_values(&[region, model]).set(rate);
+}
+
+pub fn record_throttled_request(region: &str, model: &str, reason: &str) {
+    THROTTLED_REQUESTS
+        .with_label_values(&[region, model, reason])
+        .inc();
+}
+
+pub fn record_bedrock_client(client: &str, outstanding: usize, healthy: bool) {
+    BEDROCK_CLIENT_OUTSTANDING
+        .with_label_values(&[client])
+        .set(outstanding as i64);
+    BEDROCK_CLIENT_HEALTHY
+        .with_label_values(&[client])
+        .set(healthy.into());
+}
+
+/// Counts and times every routed request.
+pub async fn track_requests(mut request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_string(), |path| path.as_str().to_string());
+    let model = RequestModel::default();
+    request.extensions_mut().insert(model.clone());
+
+    let started = Instant::now();
+    let response = next.run(request).await;
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[&route])
+        .observe(started.elapsed().as_secs_f64());
+    // Client errors other than throttling may carry any model name the
+    // client made up; leaving them unlabeled keeps cardinality bounded.
+    let status = response.status();
+    let model = match model.0.get() {
+        Some(model) if !status.is_client_error() || status == StatusCode::TOO_MANY_REQUESTS => {
+            model.as_str()
+        }
+        _ => "",
+    };
+    HTTP_REQUESTS
+        .with_label_values(&[&route, model, status.as_str()])
+        .inc();
+    response
+}
+
+pub async fn handle_metrics() -> Response {
+    let mut body = Vec::new();
+    let encoder = TextEncoder::new();
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut body) {
+        error!("Failed to encode metrics: {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    ([(CONTENT_TYPE, encoder.format_type().to_string())], body).into_response()
+}
@@ -0,0 +1,104 @@
+use anyhow::bail;
+use chat::error::ErrorKind;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{auth::Principal, error::AppError, tenants::Tenant, utils::glob_match};
+
+/// Which models a key or tenant may use, as Bedrock model IDs after alias
+/// resolution, exact or as glob patterns. A denied model is refused even if
+/// it is also allowed; an empty allow list allows every model not denied.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelRules {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_models: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub denied_models: Vec<String>,
+}
+
+impl ModelRules {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self
+            .allowed_models
+            .iter()
+            .chain(&self.denied_models)
+            .any(String::is_empty)
+        {
+            bail!("allowed_models and denied_models must not contain empty patterns");
+        }
+        Ok(())
+    }
+
+    pub fn allows(&self, model: &str) -> bool {
+        let matches = |patterns: &[String]| patterns.iter().any(|p| glob_match(p, model));
+        (self.allowed_models.is_empty() || matches(&self.allowed_models))
+            && !matches(&self.denied_models)
+    }
+}
+
+/// Whether both `principal` and its `tenant` may use `model`.
+pub fn is_model_allowed(principal: &Principal, tenant: Option<&Tenant>, model: &str) -> bool {
+    principal.models.allows(model) && tenant.is_none_or(|tenant| tenant.models.allows(model))
+}
+
+/// A `permission_error` unless both `principal` and its `tenant` may use
+/// `model`.
+pub fn check_model_access(
+    principal: &Principal,
+    tenant: Option<&Tenant>,
+    model: &str,
+) -> Result<(), AppError> {
+    let scope = if !principal.models.allows(model) {
+        "API key"
+    } else if tenant.is_some_and(|tenant| !tenant.models.allows(model)) {
+        "tenant"
+    } else {
+        return Ok(());
+    };
+    warn!(
+        "Rejected request from {} for {}: not allowed for the {}",
+        principal.name, model, scope
+    );
+    Err(AppError::new(
+        ErrorKind::Permission,
+        format!("Your {scope} is not allowed to use the model {model}"),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(allowed: &[&str], denied: &[&str]) -> ModelRules {
+        ModelRules {
+            allowed_models: allowed.iter().map(|model| model.to_string()).collect(),
+            denied_models: denied.iter().map(|model| model.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn denied_models_win_over_allowed_ones() {
+        let ci = rules(
+            &["*claude-haiku*", "us.amazon.nova-lite-v1:0"],
+            &["*preview*"],
+        );
+        assert!(ci.allows("us.anthropic.claude-haiku-4-5-20251001-v1:0"));
+        assert!(ci.allows("us.amazon.nova-lite-v1:0"));
+        assert!(!ci.allows("us.amazon.nova-lite-v1:0:300k"));
+        assert!(!ci.allows("us.anthropic.claude-sonnet-4-5-20250929-v1:0"));
+        assert!(!ci.allows("us.anthropic.claude-haiku-preview"));
+
+        let no_previews = rules(&[], &["*preview*"]);
+        assert!(no_previews.allows("us.anthropic.claude-sonnet-4-5-20250929-v1:0"));
+        assert!(!no_previews.allows("us.anthropic.claude-preview"));
+        assert!(ModelRules::default().allows("anything"));
+    }
+
+    #[test]
+    fn empty_patterns_are_rejected() {
+        assert!(rules(&["*haiku*"], &["*preview*"]).validate().is_ok());
+        assert!(rules(&[""], &[]).validate().is_err());
+        assert!(rules(&[], &[""]).validate().is_err());
+    }
+}
@@ -0,0 +1,402 @@
+use anthropic_response::{ModelInfo, ModelList};
+use aws_sdk_bedrock::{
+    Client,
+    types::{InferenceProfileStatus, InferenceProfileType, InferenceType, ModelModality},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::aliases::ModelAliases;
+
+pub const DEFAULT_PAGE_LIMIT: usize = 20;
+pub const MAX_PAGE_LIMIT: usize = 1000;
+
+/// An entry of the `[[models]]` table in `config.toml`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelConfig {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owned_by: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CatalogModel {
+    pub id: String,
+    pub display_name: String,
+    pub created_at: DateTime<Utc>,
+    pub owned_by: String,
+}
+
+impl CatalogModel {
+    pub fn to_anthropic(&self) -> ModelInfo {
+        ModelInfo {
+            created_at: self
+                .created_at
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            display_name: self.display_name.clone(),
+            id: self.id.clone(),
+            model_type: "model".to_string(),
+        }
+    }
+
+    pub fn to_openai(&self) -> response::Model {
+        response::Model {
+            id: self.id.clone(),
+            object: "model".to_string(),
+            created: self.created_at.timestamp(),
+            owned_by: self.owned_by.clone(),
+        }
+    }
+}
+
+/// The models advertised by `GET /v1/models` and `GET /models`. Configured
+/// models come first, in config order, followed by discovered models newest
+/// first. IDs are unique.
+#[derive(Clone, Debug, Default)]
+pub struct ModelCatalog {
+    models: Vec<CatalogModel>,
+}
+
+impl ModelCatalog {
+    pub fn from_config(models: &[ModelConfig], inference_profile_prefixes: &[String]) -> Self {
+        let mut catalog = Self::default();
+        for model in models {
+            catalog.push(CatalogModel {
+                id: model.id.clone(),
+                display_name: model
+                    .display_name
+                    .clone()
+                    .unwrap_or_else(|| model.id.clone()),
+                created_at: model.created_at.unwrap_or(DateTime::UNIX_EPOCH),
+                owned_by: model.owned_by.clone().unwrap_or_else(|| {
+                    provider_from_model_id(&model.id, inference_profile_prefixes)
+                }),
+            });
+        }
+        catalog
+    }
+
+    /// Adds discovered models, keeping configured entries when IDs collide.
+    pub fn extend_discovered(&mut self, mut discovered: Vec<CatalogModel>) {
+        discovered.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(a.id.cmp(&b.id)));
+        for model in discovered {
+            self.push(model);
+        }
+    }
+
+    /// Lists exact aliases under their client-facing name, borrowing the
+    /// metadata of the target model when the catalog knows it.
+    pub fn extend_aliases(
+        &mut self,
+        aliases: &ModelAliases,
+        inference_profile_prefixes: &[String],
+    ) {
+        for (alias, target) in aliases.exact() {
+            let model = match self.get(target) {
+                Some(model) => CatalogModel {
+                    id: alias.to_string(),
+                    ..model.clone()
+                },
+                None => CatalogModel {
+                    id: alias.to_string(),
+                    display_name: alias.to_string(),
+                    created_at: DateTime::UNIX_EPOCH,
+                    owned_by: provider_from_model_id(target, inference_profile_prefixes),
+                },
+            };
+            self.push(model);
+        }
+    }
+
+    fn push(&mut self, model: CatalogModel) {
+        if !self.models.iter().any(|m| m.id == model.id) {
+            self.models.push(model);
+        }
+    }
+
+    /// The catalog without the models `allows` rejects, in the same order.
+    pub fn filtered(&self, allows: impl Fn(&str) -> bool) -> Self {
+        Self {
+            models: self
+                .models
+                .iter()
+                .filter(|m| allows(&m.id))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn models(&self) -> &[CatalogModel] {
+        &self.models
+    }
+
+    pub fn get(&self, id: &str) -> Option<&CatalogModel> {
+        self.models.iter().find(|m| m.id == id)
+    }
+
+    /// Returns one page of the Anthropic listing. `after_id` pages forward and
+    /// `before_id` pages backward; both are exclusive cursors.
+    pub fn page(
+        &self,
+        before_id: Option<&str>,
+        after_id: Option<&str>,
+        limit: usize,
+    ) -> anyhow::Result<ModelList> {
+        let position = |id: &str| {
+            self.models
+                .iter()
+                .position(|m| m.id == id)
+                .ok_or_else(|| anyhow::anyhow!("unknown model id cursor: {id}"))
+        };
+
+        let (start, end) = match (before_id, after_id) {
+            (Some(_), Some(_)) => {
+                anyhow::bail!("before_id and after_id cannot be used together")
+            }
+            (Some(before_id), None) => {
+                let end = position(before_id)?;
+                (end.saturating_sub(limit), end)
+            }
+            (None, Some(after_id)) => {
+                let start = position(after_id)? + 1;
+                (start, (start + limit).min(self.models.len()))
+            }
+            (None, None) => (0, limit.min(self.models.len())),
+        };
+
+        let data: Vec<ModelInfo> = self.models[start..end]
+            .iter()
+            .map(CatalogModel::to_anthropic)
+            .collect();
+        let has_more = if before_id.is_some() {
+            start > 0
+        } else {
+            end < self.models.len()
+        };
+
+        Ok(ModelList {
+            first_id: data.first().map(|m| m.id.clone()),
+            last_id: data.last().map(|m| m.id.clone()),
+            has_more,
+            data,
+        })
+    }
+}
+
+/// `us.anthropic.claude-…`, `anthropic.claude-…` and inference-profile ARNs
+/// ending in either are all owned by `anthropic`.
+fn provider_from_model_id(id: &str, inference_profile_prefixes: &[String]) -> String {
+    let id = id.rsplit('/').next().unwrap_or(id);
+    let id = inference_profile_prefixes
+        .iter()
+        .find_map(|prefix| id.strip_prefix(prefix.as_str()))
+        .unwrap_or(id);
+    id.split('.').next().unwrap_or(id).to_string()
+}
+
+/// Lists the text models the proxy can actually invoke: foundation models
+/// that support on-demand throughput under their bare ID, plus system-defined
+/// inference profiles whose ID starts with one of `inference_profile_prefixes`.
+pub async fn discover_models(
+    client: &Client,
+    inference_profile_prefixes: &[String],
+) -> anyhow::Result<Vec<CatalogModel>> {
+    let mut discovered = Vec::new();
+
+    let foundation_models = client
+        .list_foundation_models()
+        .by_output_modality(ModelModality::Text)
+        .by_inference_type(InferenceType::OnDemand)
+        .send()
+        .await?;
+    for summary in foundation_models.model_summaries() {
+        discovered.push(CatalogModel {
+            id: summary.model_id().to_string(),
+            display_name: summary
+                .model_name()
+                .unwrap_or(summary.model_id())
+                .to_string(),
+            created_at: DateTime::UNIX_EPOCH,
+            owned_by: provider_from_model_id(summary.model_id(), inference_profile_prefixes),
+        });
+    }
+    info!(
+        "Discovered {} on-demand foundation models",
+        discovered.len()
+    );
+
+    let mut profiles = client
+        .list_inference_profiles()
+        .type_equals(InferenceProfileType::SystemDefined)
+        .into_paginator()
+        .items()
+        .send();
+    let mut profile_count = 0;
+    while let Some(profile) = profiles.next().await {
+        let profile = profile?;
+        let id = profile.inference_profile_id();
+        if profile.status() != &InferenceProfileStatus::Active
+            || !inference_profile_prefixes
+                .iter()
+                .any(|prefix| id.starts_with(prefix.as_str()))
+        {
+            continue;
+        }
+        profile_count += 1;
+        discovered.push(CatalogModel {
+            id: id.to_string(),
+            display_name: profile.inference_profile_name().to_string(),
+            created_at: profile
+                .created_at()
+                .and_then(|t| DateTime::from_timestamp(t.secs(), t.subsec_nanos()))
+                .unwrap_or(DateTime::UNIX_EPOCH),
+            owned_by: provider_from_model_id(id, inference_profile_prefixes),
+        });
+    }
+    info!("Discovered {} inference profiles", profile_count);
+
+    Ok(discovered)
+}
+
+/// Builds the catalog from config and, when enabled, Bedrock discovery. A
+/// failed discovery is logged and the configured models are served alone.
+pub async fn load_model_catalog(
+    client: &Client,
+    models: &[ModelConfig],
+    discover: bool,
+    inference_profile_prefixes: &[String],
+) -> ModelCatalog {
+    let mut catalog = ModelCatalog::from_config(models, inference_profile_prefixes);
+    if discover {
+        match discover_models(client, inference_profile_prefixes).await {
+            Ok(discovered) => catalog.extend_discovered(discovered),
+            Err(e) => warn!("Bedrock model discovery failed: {:?}", e),
+        }
+    }
+    info!("Model catalog contains {} models", catalog.models().len());
+    catalog
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog(ids: &[&str]) -> ModelCatalog {
+        let models: Vec<ModelConfig> = ids
+            .iter()
+            .map(|id| ModelConfig {
+                id: id.to_string(),
+                display_name: None,
+                created_at: None,
+                owned_by: None,
+            })
+            .collect();
+        ModelCatalog::from_config(&models, &["us.".to_string()])
+    }
+
+    fn ids(list: &ModelList) -> Vec<&str> {
+        list.data.iter().map(|m| m.id.as_str()).collect()
+    }
+
+    #[test]
+    fn provider_strips_inference_profile_prefix() {
+        let prefixes = vec!["us.".to_string(), "global.".to_string()];
+        assert_eq!(
+            provider_from_model_id("us.anthropic.claude-sonnet-4-5", &prefixes),
+            "anthropic"
+        );
+        assert_eq!(
+            provider_from_model_id("meta.llama3-70b-instruct-v1:0", &prefixes),
+            "meta"
+        );
+        assert_eq!(
+            provider_from_model_id(
+                "arn:aws:bedrock:us-east-1:123456789012:inference-profile/us.anthropic.claude-opus-4-8",
+                &prefixes
+            ),
+            "anthropic"
+        );
+    }
+
+    #[test]
+    fn exact_aliases_borrow_target_metadata() {
+        let mut catalog = catalog(&["us.anthropic.claude-a"]);
+        let aliases = ModelAliases::new(
+            serde_json::from_value(serde_json::json!([
+                {"pattern": "claude-a", "target": "us.anthropic.claude-a"},
+                {"pattern": "claude-*", "target": "us.anthropic.claude-*"},
+                {"pattern": "gpt-4o", "target": "us.meta.llama3-70b"}
+            ]))
+            .unwrap(),
+        )
+        .unwrap();
+        catalog.extend_aliases(&aliases, &["us.".to_string()]);
+
+        let ids: Vec<&str> = catalog.models().iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["us.anthropic.claude-a", "claude-a", "gpt-4o"]);
+        assert_eq!(
+            catalog.get("claude-a").unwrap().display_name,
+            "us.anthropic.claude-a"
+        );
+        assert_eq!(catalog.get("gpt-4o").unwrap().owned_by, "meta");
+    }
+
+    #[test]
+    fn configured_models_win_over_discovered() {
+        let mut catalog = catalog(&["us.anthropic.claude-a"]);
+        catalog.extend_discovered(vec![
+            CatalogModel {
+                id: "us.anthropic.claude-a".to_string(),
+                display_name: "discovered".to_string(),
+                created_at: DateTime::UNIX_EPOCH,
+                owned_by: "anthropic".to_string(),
+            },
+            CatalogModel {
+                id: "us.anthropic.claude-b".to_string(),
+                display_name: "Claude B".to_string(),
+                created_at: DateTime::UNIX_EPOCH,
+                owned_by: "anthropic".to_string(),
+            },
+        ]);
+        assert_eq!(catalog.models().len(), 2);
+        assert_eq!(
+            catalog.get("us.anthropic.claude-a").unwrap().display_name,
+            "us.anthropic.claude-a"
+        );
+    }
+
+    #[test]
+    fn page_forward_and_backward() {
+        let catalog = catalog(&["a", "b", "c", "d", "e"]);
+
+        let first = catalog.page(None, None, 2).unwrap();
+        assert_eq!(ids(&first), ["a", "b"]);
+        assert!(first.has_more);
+        assert_eq!(first.last_id.as_deref(), Some("b"));
+
+        let last = catalog.page(None, Some("c"), 2).unwrap();
+        assert_eq!(ids(&last), ["d", "e"]);
+        assert!(!last.has_more);
+
+        let back = catalog.page(Some("d"), None, 2).unwrap();
+        assert_eq!(ids(&back), ["b", "c"]);
+        assert!(back.has_more);
+
+        let start = catalog.page(Some("b"), None, 2).unwrap();
+        assert_eq!(ids(&start), ["a"]);
+        assert!(!start.has_more);
+    }
+
+    #[test]
+    fn page_rejects_unknown_cursor() {
+        let catalog = catalog(&["a"]);
+        assert!(catalog.page(None, Some("missing"), 20).is_err());
+        assert!(catalog.page(Some("a"), Some("a"), 20).is_err());
+    }
+}
@@ -0,0 +1,559 @@
+use aws_sdk_bedrockruntime::types::TokenUsage;
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use chat::error::ErrorKind;
+use chrono::{SecondsFormat, Utc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+use crate::{
+    AppState,
+    auth::Principal,
+    error::{AppError, Protocol},
+    tenants::Tenant,
+    utils::{PendingRejection, reject},
+};
+
+/// Per-key or per-tenant limits, set in the `[[api_keys]]` and `[[tenants]]`
+/// entries of `config.toml`. An unset limit is not enforced.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RateLimits {
+    pub requests_per_minute: Option<u32>,
+    pub input_tokens_per_minute: Option<u32>,
+    pub output_tokens_per_minute: Option<u32>,
+    /// Requests in flight at once, enforced by the concurrency limiter.
+    pub max_concurrent_requests: Option<u32>,
+}
+
+/// A bucket holding up to `capacity` tokens, refilled continuously at
+/// `capacity` per minute. Token usage is only known after a response, so
+/// debits may take the balance below zero; the key is then throttled until
+/// the debt is repaid.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    balance: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: u32, now: Instant) -> Self {
+        Self {
+            capacity: capacity as f64,
+            balance: capacity as f64,
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.balance = (self.balance + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.updated_at = now;
+    }
+
+    /// Time until the balance reaches `amount`.
+    fn wait_for(&self, amount: f64) -> Duration {
+        let missing = (amount - self.balance).max(0.0);
+        Duration::from_secs_f64(missing * 60.0 / self.capacity)
+    }
+
+    fn snapshot(&self) -> BucketStatus {
+        BucketStatus {
+            limit: self.capacity as u64,
+            remaining: self.balance.max(0.0).floor() as u64,
+            reset: self.wait_for(self.capacity),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct KeyBuckets {
+    limits: RateLimits,
+    /// The tenant whose buckets the key's token usage is also debited from.
+    tenant: Option<String>,
+    requests: Option<TokenBucket>,
+    input_tokens: Option<TokenBucket>,
+    output_tokens: Option<TokenBucket>,
+}
+
+impl KeyBuckets {
+    fn new(limits: RateLimits, now: Instant) -> Self {
+        let bucket = |limit: Option<u32>| limit.map(|limit| TokenBucket::new(limit, now));
+        Self {
+            limits,
+            tenant: None,
+            requests: bucket(limits.requests_per_minute),
+            input_tokens: bucket(limits.input_tokens_per_minute),
+            output_tokens: bucket(limits.output_tokens_per_minute),
+        }
+    }
+
+    fn buckets_mut(&mut self) -> impl Iterator<Item = &mut TokenBucket> {
+        [
+            self.requests.as_mut(),
+            self.input_tokens.as_mut(),
+            self.output_tokens.as_mut(),
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    /// The exhausted limit that takes longest to refill, and how long.
+    fn exhausted(&self) -> Option<(&'static str, Duration)> {
+        [
+            ("requests", self.requests.as_ref(), 1.0),
+            (
+                "input_tokens",
+                self.input_tokens.as_ref(),
+                f64::MIN_POSITIVE,
+            ),
+            (
+                "output_tokens",
+                self.output_tokens.as_ref(),
+                f64::MIN_POSITIVE,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(limit, bucket, needed)| {
+            bucket
+                .filter(|bucket| bucket.balance < needed)
+                .map(|bucket| (limit, bucket.wait_for(needed)))
+        })
+        .max_by_key(|(_, wait)| *wait)
+    }
+
+    fn debit_tokens(&mut self, usage: &TokenUsage, now: Instant) {
+        let input_tokens = usage.input_tokens + usage.cache_write_input_tokens.unwrap_or(0);
+        if let Some(bucket) = self.input_tokens.as_mut() {
+            bucket.refill(now);
+            bucket.balance -= input_tokens as f64;
+        }
+        if let Some(bucket) = self.output_tokens.as_mut() {
+            bucket.refill(now);
+            bucket.balance -= usage.output_tokens as f64;
+        }
+    }
+
+    fn status(&self) -> RateLimitStatus {
+        RateLimitStatus {
+            requests: self.requests.as_ref().map(TokenBucket::snapshot),
+            input_tokens: self.input_tokens.as_ref().map(TokenBucket::snapshot),
+            output_tokens: self.output_tokens.as_ref().map(TokenBucket::snapshot),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BucketStatus {
+    pub limit: u64,
+    pub remaining: u64,
+    /// Time until the bucket is full again.
+    pub reset: Duration,
+}
+
+/// The remaining budget of a key, rendered as rate-limit response headers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    pub requests: Option<BucketStatus>,
+    pub input_tokens: Option<BucketStatus>,
+    pub output_tokens: Option<BucketStatus>,
+}
+
+impl RateLimitStatus {
+    /// Per limit, the bucket with the least remaining of `self` and `other`.
+    fn tightest(self, other: Self) -> Self {
+        let tightest = |a: Option<BucketStatus>, b: Option<BucketStatus>| match (a, b) {
+            (Some(a), Some(b)) => Some(if b.remaining < a.remaining { b } else { a }),
+            (a, b) => a.or(b),
+        };
+        Self {
+            requests: tightest(self.requests, other.requests),
+            input_tokens: tightest(self.input_tokens, other.input_tokens),
+            output_tokens: tightest(self.output_tokens, other.output_tokens),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimitExceeded {
+    pub status: RateLimitStatus,
+    pub retry_after: Duration,
+    pub limit: &'static str,
+}
+
+/// Whose buckets a request draws from: its key's, and its tenant's, which
+/// all of the tenant's keys share.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Scope {
+    Key(String),
+    Tenant(String),
+}
+
+/// Per-key and per-tenant token buckets for requests and input/output
+/// tokens per minute.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    keys: Mutex<HashMap<Scope, KeyBuckets>>,
+}
+
+impl RateLimiter {
+    /// Admits one request for `principal` of `tenant`, or reports which limit
+    /// is exhausted and when to retry. The reported status is the tightest
+    /// of the key's and the tenant's.
+    pub fn check(
+        &self,
+        principal: &Principal,
+        tenant: Option<&Tenant>,
+    ) -> Result<RateLimitStatus, Box<RateLimitExceeded>> {
+        self.check_at(principal, tenant, Instant::now())
+    }
+
+    fn check_at(
+        &self,
+        principal: &Principal,
+        tenant: Option<&Tenant>,
+        now: Instant,
+    ) -> Result<RateLimitStatus, Box<RateLimitExceeded>> {
+        let mut keys = self.keys.lock().unwrap();
+        let scopes: Vec<_> = [(Scope::Key(principal.name.clone()), principal.limits)]
+            .into_iter()
+            .chain(tenant.map(|tenant| (Scope::Tenant(tenant.name.clone()), tenant.limits)))
+            .collect();
+        for (scope, limits) in &scopes {
+            let buckets = keys
+                .entry(scope.clone())
+                .or_insert_with(|| KeyBuckets::new(*limits, now));
+            if buckets.limits != *limits {
+                *buckets = KeyBuckets::new(*limits, now);
+            }
+            buckets.buckets_mut().for_each(|bucket| bucket.refill(now));
+            if let Scope::Key(_) = scope {
+                buckets.tenant = tenant.map(|tenant| tenant.name.clone());
+            }
+        }
+
+        let status = |keys: &HashMap<Scope, KeyBuckets>| {
+            scopes
+                .iter()
+                .map(|(scope, _)| keys[scope].status())
+                .reduce(RateLimitStatus::tightest)
+                .unwrap_or_default()
+        };
+        let exhausted = scopes
+            .iter()
+            .filter_map(|(scope, _)| keys[scope].exhausted())
+            .max_by_key(|(_, wait)| *wait);
+        if let Some((limit, retry_after)) = exhausted {
+            return Err(Box::new(RateLimitExceeded {
+                status: status(&keys),
+                retry_after,
+                limit,
+            }));
+        }
+
+        for (scope, _) in &scopes {
+            if let Some(requests) = keys.get_mut(scope).and_then(|b| b.requests.as_mut()) {
+                requests.balance -= 1.0;
+            }
+        }
+        Ok(status(&keys))
+    }
+
+    /// Debits the tokens a finished request consumed from the key and its
+    /// tenant. Cache writes count as input; cache reads do not.
+    pub fn record_usage(&self, principal: &str, usage: &TokenUsage) {
+        self.record_usage_at(principal, usage, Instant::now());
+    }
+
+    fn record_usage_at(&self, principal: &str, usage: &TokenUsage, now: Instant) {
+        let mut keys = self.keys.lock().unwrap();
+        let Some(buckets) = keys.get_mut(&Scope::Key(principal.to_string())) else {
+            return;
+        };
+        buckets.debit_tokens(usage, now);
+        if let Some(tenant) = buckets.tenant.clone()
+            && let Some(buckets) = keys.get_mut(&Scope::Tenant(tenant))
+        {
+            buckets.debit_tokens(usage, now);
+        }
+    }
+}
+
+fn insert_header(headers: &mut HeaderMap, name: String, value: String) {
+    if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+        headers.insert(name, value);
+    }
+}
+
+/// `anthropic-ratelimit-{requests,input-tokens,output-tokens}-{limit,remaining,reset}`
+/// with `reset` as an RFC 3339 timestamp.
+fn insert_anthropic_headers(headers: &mut HeaderMap, status: &RateLimitStatus) {
+    for (name, bucket) in [
+        ("requests", status.requests),
+        ("input-tokens", status.input_tokens),
+        ("output-tokens", status.output_tokens),
+    ] {
+        let Some(bucket) = bucket else { continue };
+        let reset = Utc::now()
+            + chrono::Duration::from_std(bucket.reset).unwrap_or(chrono::Duration::zero());
+        insert_header(
+            headers,
+            format!("anthropic-ratelimit-{name}-limit"),
+            bucket.limit.to_string(),
+        );
+        insert_header(
+            headers,
+            format!("anthropic-ratelimit-{name}-remaining"),
+            bucket.remaining.to_string(),
+        );
+        insert_header(
+            headers,
+            format!("anthropic-ratelimit-{name}-reset"),
+            reset.to_rfc3339_opts(SecondsFormat::Secs, true),
+        );
+    }
+}
+
+/// OpenAI reports a single token budget; it is mapped to the input-token
+/// bucket, which is what prompt-heavy traffic exhausts first.
+fn insert_openai_headers(headers: &mut HeaderMap, status: &RateLimitStatus) {
+    for (name, bucket) in [
+        ("requests", status.requests),
+        ("tokens", status.input_tokens),
+    ] {
+        let Some(bucket) = bucket else { continue };
+        insert_header(
+            headers,
+            format!("x-ratelimit-limit-{name}"),
+            bucket.limit.to_string(),
+        );
+        insert_header(
+            headers,
+            format!("x-ratelimit-remaining-{name}"),
+            bucket.remaining.to_string(),
+        );
+        insert_header(
+            headers,
+            format!("x-ratelimit-reset-{name}"),
+            format_reset_duration(bucket.reset),
+        );
+    }
+}
+
+/// Formats like OpenAI's reset headers: `20ms`, `6s`, `1m30s`.
+fn format_reset_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    if millis < 1000 {
+        return format!("{millis}ms");
+    }
+    let secs = duration.as_secs();
+    match (secs / 60, secs % 60) {
+        (0, secs) => format!("{secs}s"),
+        (mins, secs) => format!("{mins}m{secs}s"),
+    }
+}
+
+fn insert_rate_limit_headers(
+    headers: &mut HeaderMap,
+    protocol: Protocol,
+    status: &RateLimitStatus,
+) {
+    match protocol {
+        Protocol::Anthropic => insert_anthropic_headers(headers, status),
+        Protocol::OpenAI => insert_openai_headers(headers, status),
+    }
+}
+
+/// Enforces the caller's and its tenant's [`RateLimits`], recording each
+/// refusal in the usage ledger, and reports the remaining budget in
+/// protocol-specific response headers. Must run after authentication and
+/// inside [`enforce_budgets`](crate::budgets::enforce_budgets), whose
+/// refusals it lets through unchecked.
+pub async fn enforce_rate_limits(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let protocol = Protocol::from_path(request.uri().path());
+    let principal = request
+        .extensions()
+        .get::<Principal>()
+        .cloned()
+        .unwrap_or_else(Principal::anonymous);
+
+    // A request its budget refused must not count against the rate limits.
+    if request.extensions().get::<PendingRejection>().is_some() {
+        return next.run(request).await;
+    }
+
+    let settings = state.settings.load();
+    let checked = state
+        .rate_limiter
+        .check(&principal, settings.tenants.of(&principal));
+    drop(settings);
+    match checked {
+        Ok(status) => {
+            let mut response = next.run(request).await;
+            insert_rate_limit_headers(response.headers_mut(), protocol, &status);
+            response
+        }
+        Err(exceeded) => {
+            warn!(
+                "Rate limit exceeded for {}: {} (retry after {:?})",
+                principal.name, exceeded.limit, exceeded.retry_after
+            );
+            let retry_after = exceeded.retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            let error = AppError::new(
+                ErrorKind::RateLimit,
+                format!(
+                    "This request would exceed your {} per minute rate limit. Please retry after {} seconds.",
+                    exceeded.limit.replace('_', " "),
+                    retry_after
+                ),
+            );
+            let mut response = reject(&state, request, next, &principal, error).await;
+            let headers = response.headers_mut();
+            insert_rate_limit_headers(headers, protocol, &exceeded.status);
+            insert_header(headers, "retry-after".to_string(), retry_after.to_string());
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model_access::ModelRules;
+    use crate::tenants::{TenantConfig, Tenants};
+
+    fn principal(limits: RateLimits) -> Principal {
+        Principal {
+            name: "alice".to_string(),
+            admin: false,
+            limits,
+            identity: None,
+            tenant: None,
+            models: ModelRules::default(),
+        }
+    }
+
+    fn usage(input_tokens: i32, output_tokens: i32) -> TokenUsage {
+        TokenUsage::builder()
+            .input_tokens(input_tokens)
+            .output_tokens(output_tokens)
+            .total_tokens(input_tokens + output_tokens)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn requests_per_minute_refills_continuously() {
+        let limiter = RateLimiter::default();
+        let alice = principal(RateLimits {
+            requests_per_minute: Some(2),
+            ..Default::default()
+        });
+        let start = Instant::now();
+
+        assert_eq!(
+            limiter
+                .check_at(&alice, None, start)
+                .unwrap()
+                .requests
+                .unwrap()
+                .remaining,
+            1
+        );
+        assert!(limiter.check_at(&alice, None, start).is_ok());
+        let exceeded = limiter.check_at(&alice, None, start).unwrap_err();
+        assert_eq!(exceeded.limit, "requests");
+        assert_eq!(exceeded.retry_after, Duration::from_secs(30));
+
+        assert!(
+            limiter
+                .check_at(&alice, None, start + Duration::from_secs(30))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn token_usage_debt_blocks_until_repaid() {
+        let limiter = RateLimiter::default();
+        let alice = principal(RateLimits {
+            output_tokens_per_minute: Some(600),
+            ..Default::default()
+        });
+        let start = Instant::now();
+
+        assert!(limiter.check_at(&alice, None, start).is_ok());
+        limiter.record_usage_at("alice", &usage(10, 900), start);
+
+        let exceeded = limiter.check_at(&alice, None, start).unwrap_err();
+        assert_eq!(exceeded.limit, "output_tokens");
+        assert_eq!(exceeded.retry_after, Duration::from_secs(30));
+        assert_eq!(exceeded.status.output_tokens.unwrap().remaining, 0);
+
+        assert!(
+            limiter
+                .check_at(&alice, None, start + Duration::from_secs(31))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn tenant_buckets_are_shared_and_debited_with_the_key() {
+        let limiter = RateLimiter::default();
+        let tenants = Tenants::new(vec![TenantConfig {
+            name: "search".to_string(),
+            output_tokens_per_minute: Some(600),
+            ..Default::default()
+        }])
+        .unwrap();
+        let search = tenants.get("search");
+        let alice = principal(RateLimits {
+            requests_per_minute: Some(10),
+            ..Default::default()
+        });
+        let bob = Principal {
+            name: "bob".to_string(),
+            ..principal(RateLimits::default())
+        };
+        let start = Instant::now();
+
+        let status = limiter.check_at(&alice, search, start).unwrap();
+        assert_eq!(status.requests.unwrap().remaining, 9);
+        assert_eq!(status.output_tokens.unwrap().remaining, 600);
+        limiter.record_usage_at("alice", &usage(10, 900), start);
+
+        let exceeded = limiter.check_at(&bob, search, start).unwrap_err();
+        assert_eq!(exceeded.limit, "output_tokens");
+        assert!(limiter.check_at(&bob, None, start).is_ok());
+    }
+
+    #[test]
+    fn unlimited_keys_are_always_admitted() {
+        let limiter = RateLimiter::default();
+        let anonymous = Principal::anonymous();
+        for _ in 0..100 {
+            assert_eq!(
+                limiter.check(&anonymous, None).unwrap(),
+                RateLimitStatus::default()
+            );
+        }
+    }
+
+    #[test]
+    fn reset_durations_use_openai_format() {
+        assert_eq!(format_reset_duration(Duration::from_millis(20)), "20ms");
+        assert_eq!(format_reset_duration(Duration::from_secs(6)), "6s");
+        assert_eq!(format_reset_duration(Duration::from_secs(90)), "1m30s");
+    }
+}
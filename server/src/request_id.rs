@@ -0,0 +1,191 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    http::{
+        HeaderMap, HeaderName, HeaderValue,
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+    },
+    middleware::Next,
+    response::Response,
+};
+use futures::{StreamExt, stream};
+use std::{fmt, sync::Arc};
+use tracing::{Span, warn};
+use uuid::Uuid;
+
+use crate::error::Protocol;
+
+/// Response header carrying the request ID on the Anthropic front-end.
+pub const ANTHROPIC_REQUEST_ID: HeaderName = HeaderName::from_static("request-id");
+/// Response header carrying the request ID on the OpenAI front-end, also
+/// accepted from clients on either front-end.
+pub const OPENAI_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Client-supplied IDs longer than this are replaced with a generated one.
+const MAX_CLIENT_ID_LEN: usize = 128;
+/// Error bodies larger than this are passed through without the ID.
+const MAX_ERROR_BODY_LEN: usize = 64 * 1024;
+
+/// The ID of one proxied request, shared by its response header, error
+/// body, log lines and usage record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(Arc<str>);
+
+impl RequestId {
+    pub fn generate() -> Self {
+        Self(format!("req_{}", Uuid::new_v4().simple()).into())
+    }
+
+    /// The ID the client sent in `x-request-id` or `request-id`, if it is
+    /// short and made only of visible ASCII characters.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        [&OPENAI_REQUEST_ID, &ANTHROPIC_REQUEST_ID]
+            .into_iter()
+            .filter_map(|name| headers.get(name)?.to_str().ok())
+            .find(|id| {
+                !id.is_empty()
+                    && id.len() <= MAX_CLIENT_ID_LEN
+                    && id.bytes().all(|b| b.is_ascii_graphic())
+            })
+            .map(|id| Self(id.into()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Assigns every routed request a [`RequestId`], records it on the request
+/// span so it prefixes every log line, and returns it in the protocol's
+/// response header and in the body of JSON error responses.
+pub async fn assign_request_id(mut request: Request, next: Next) -> Response {
+    let protocol = Protocol::from_path(request.uri().path());
+    let request_id = RequestId::from_headers(request.headers()).unwrap_or_else(RequestId::generate);
+    Span::current().record("request_id", request_id.as_str());
+    request.extensions_mut().insert(request_id.clone());
+
+    let mut response = next.run(request).await;
+    if response.status().is_client_error() || response.status().is_server_error() {
+        response = add_to_error_body(response, &request_id).await;
+    }
+    let header = match protocol {
+        Protocol::Anthropic => ANTHROPIC_REQUEST_ID,
+        Protocol::OpenAI => OPENAI_REQUEST_ID,
+    };
+    if let Ok(value) = HeaderValue::from_str(request_id.as_str()) {
+        response.headers_mut().insert(header, value);
+    }
+    response
+}
+
+/// Adds a top-level `request_id` to a JSON object error body, as the
+/// Anthropic API does.
+async fn add_to_error_body(response: Response, request_id: &RequestId) -> Response {
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let mut chunks = body.into_data_stream();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = chunks.next().await {
+        match chunk {
+            Ok(chunk) => bytes.extend_from_slice(&chunk),
+            Err(e) => {
+                warn!("Failed to read error body for request {request_id}: {e}");
+                parts.headers.remove(CONTENT_LENGTH);
+                return Response::from_parts(parts, Body::from(bytes));
+            }
+        }
+        if bytes.len() > MAX_ERROR_BODY_LEN {
+            let read = stream::once(async move { Ok(Bytes::from(bytes)) });
+            return Response::from_parts(parts, Body::from_stream(read.chain(chunks)));
+        }
+    }
+    let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Object(mut object)) => {
+            object.insert("request_id".to_string(), request_id.as_str().into());
+            parts.headers.remove(CONTENT_LENGTH);
+            Body::from(serde_json::Value::Object(object).to_string())
+        }
+        _ => Body::from(bytes),
+    };
+    Response::from_parts(parts, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_well_formed_client_ids_only() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(RequestId::from_headers(&headers), None);
+
+        headers.insert(&ANTHROPIC_REQUEST_ID, "trace-42".parse().unwrap());
+        assert_eq!(
+            RequestId::from_headers(&headers).unwrap().as_str(),
+            "trace-42"
+        );
+
+        headers.insert(&OPENAI_REQUEST_ID, "has space".parse().unwrap());
+        assert_eq!(
+            RequestId::from_headers(&headers).unwrap().as_str(),
+            "trace-42"
+        );
+
+        headers.insert(&OPENAI_REQUEST_ID, "x".repeat(129).parse().unwrap());
+        headers.remove(&ANTHROPIC_REQUEST_ID);
+        assert_eq!(RequestId::from_headers(&headers), None);
+    }
+
+    #[test]
+    fn generated_ids_are_unique() {
+        let id = RequestId::generate();
+        assert!(id.as_str().starts_with("req_"));
+        assert_ne!(id, RequestId::generate());
+    }
+
+    #[tokio::test]
+    async fn large_error_bodies_are_passed_through() {
+        let error = serde_json::json!({"message": "x".repeat(MAX_ERROR_BODY_LEN)}).to_string();
+        let response = Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from_stream(stream::iter(
+                error
+                    .as_bytes()
+                    .chunks(1024)
+                    .map(|chunk| Ok::<_, std::io::Error>(Bytes::copy_from_slice(chunk)))
+                    .collect::<Vec<_>>(),
+            )))
+            .unwrap();
+        let response = add_to_error_body(response, &RequestId::generate()).await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, error.as_bytes());
+
+        let response = Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"error": "small"}"#))
+            .unwrap();
+        let request_id = RequestId::generate();
+        let response = add_to_error_body(response, &request_id).await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["request_id"], request_id.as_str());
+    }
+}
@@ -0,0 +1,573 @@
+use anthropic_request::V1MessagesRequest;
+use anthropic_response::{Message, message_to_events};
+use anyhow::bail;
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header::CONTENT_TYPE},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, Sse},
+    },
+};
+use chat::error::ErrorKind;
+use futures::stream;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+use crate::{auth::Principal, error::AppError, fallbacks::BEDROCK_MODEL_ID};
+
+/// Set by clients so that a retried request is answered with the response
+/// to the first one instead of being sent to Bedrock again.
+pub const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+/// `hit` on responses served from the cache.
+pub const CACHE_STATUS: HeaderName = HeaderName::from_static("x-cache");
+/// `true` on responses replayed for a repeated [`IDEMPOTENCY_KEY`].
+pub const IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+/// The `[response_cache]` table of `config.toml`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ResponseCacheConfig {
+    /// Serve repeated identical requests from the cache. Idempotency keys
+    /// are honored either way.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Who may be served a response cached for someone else.
+    #[serde(default)]
+    pub scope: CacheScope,
+    /// How long a response is kept after it was stored.
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+    /// Total size of the cached response bodies; the least recently used
+    /// are evicted past it.
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: usize,
+}
+
+impl Default for ResponseCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            scope: CacheScope::default(),
+            ttl_secs: default_ttl_secs(),
+            max_entries: default_max_entries(),
+            max_bytes: default_max_bytes(),
+        }
+    }
+}
+
+/// Which callers share cached responses. Idempotency keys always belong to
+/// the API key that sent them.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheScope {
+    /// Each API key has its own entries.
+    #[default]
+    Key,
+    /// Keys of the same tenant share entries; keys without a tenant have
+    /// their own.
+    Tenant,
+    /// All keys share entries.
+    Global,
+}
+
+impl CacheScope {
+    /// The name entries are kept under for `principal`, if not shared by all.
+    fn owner(self, principal: &Principal) -> Option<&str> {
+        match self {
+            CacheScope::Key => Some(&principal.name),
+            CacheScope::Tenant => Some(principal.tenant.as_deref().unwrap_or(&principal.name)),
+            CacheScope::Global => None,
+        }
+    }
+}
+
+fn default_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_max_entries() -> usize {
+    1000
+}
+
+fn default_max_bytes() -> usize {
+    64 * 1024 * 1024
+}
+
+impl ResponseCacheConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.ttl_secs == 0 || self.max_entries == 0 || self.max_bytes == 0 {
+            bail!("response_cache: ttl_secs, max_entries and max_bytes must be positive");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum CacheKey {
+    Request(String),
+    /// An idempotency key and the principal that sent it.
+    Idempotency(String, String),
+}
+
+/// A stored `/v1/messages` response, as the JSON of its message.
+#[derive(Debug)]
+struct CachedResponse {
+    request_hash: String,
+    served_model: String,
+    message: Bytes,
+}
+
+#[derive(Debug)]
+struct Entry {
+    response: Arc<CachedResponse>,
+    expires_at: Instant,
+    last_used: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    entries: HashMap<CacheKey, Entry>,
+    bytes: usize,
+    /// Idempotency keys, by principal, of requests still being served.
+    in_flight: HashSet<(String, String)>,
+}
+
+impl Entries {
+    fn get(&mut self, key: &CacheKey, now: Instant) -> Option<Arc<CachedResponse>> {
+        let entry = self.entries.get_mut(key)?;
+        if entry.expires_at <= now {
+            self.remove(key);
+            return None;
+        }
+        entry.last_used = now;
+        Some(entry.response.clone())
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.bytes -= entry.response.message.len();
+        }
+    }
+
+    fn insert(
+        &mut self,
+        config: &ResponseCacheConfig,
+        key: CacheKey,
+        response: Arc<CachedResponse>,
+        now: Instant,
+    ) {
+        let size = response.message.len();
+        if size > config.max_bytes {
+            return;
+        }
+        self.remove(&key);
+        let expired: Vec<CacheKey> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.expires_at <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            self.remove(key);
+        }
+        while self.entries.len() >= config.max_entries || self.bytes + size > config.max_bytes {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            self.remove(&oldest);
+        }
+        self.bytes += size;
+        self.entries.insert(
+            key,
+            Entry {
+                response,
+                expires_at: now + Duration::from_secs(config.ttl_secs),
+                last_used: now,
+            },
+        );
+    }
+}
+
+/// Complete `/v1/messages` responses kept in memory, found again by a hash
+/// of the request or by the `Idempotency-Key` it was sent with. Streamed
+/// responses are stored once their stream ends cleanly, and either kind is
+/// served to streaming requests as SSE events.
+#[derive(Clone, Debug, Default)]
+pub struct ResponseCache {
+    entries: Arc<Mutex<Entries>>,
+}
+
+/// The outcome of [`ResponseCache::lookup`].
+pub enum CacheLookup {
+    Hit(CacheHit),
+    Miss(CacheTicket),
+}
+
+/// What a `/v1/messages` response is cached under, besides its
+/// [`CacheScope`] owner.
+pub struct CacheRequest<'a> {
+    /// The model the client asked for, which the response echoes.
+    pub requested_model: &'a str,
+    /// The request after alias resolution and tenant defaults.
+    pub payload: &'a V1MessagesRequest,
+    pub anthropic_beta: Option<&'a [String]>,
+    /// The prefixes `payload.model` is resolved to an inference profile
+    /// with, which pin the region it is served from.
+    pub inference_profile_prefixes: &'a [String],
+    /// The models tried after `payload.model`.
+    pub fallback_models: &'a [String],
+}
+
+impl ResponseCache {
+    /// Looks up the response to `request`, as sent by `principal`, among
+    /// those cached in its [`CacheScope`]. A request with an idempotency
+    /// key that was already used for a different request is refused, as is
+    /// one whose key is still in use by a request in flight.
+    pub fn lookup(
+        &self,
+        config: &ResponseCacheConfig,
+        principal: &Principal,
+        idempotency_key: Option<&str>,
+        request: &CacheRequest,
+    ) -> Result<CacheLookup, AppError> {
+        let mut ticket = CacheTicket {
+            cache: self.clone(),
+            config: config.clone(),
+            request_hash: None,
+            idempotency_key: None,
+        };
+        if !config.enabled && idempotency_key.is_none() {
+            return Ok(CacheLookup::Miss(ticket));
+        }
+        if let Some(key) = idempotency_key
+            && (key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LEN)
+        {
+            return Err(AppError::new(
+                ErrorKind::InvalidRequest,
+                format!("Idempotency-Key must be 1 to {MAX_IDEMPOTENCY_KEY_LEN} characters long"),
+            ));
+        }
+        let request_hash = request_hash(config.scope.owner(principal), request)?;
+
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(key) = idempotency_key {
+            let idempotency_key = (principal.name.clone(), key.to_string());
+            let cache_key = CacheKey::Idempotency(principal.name.clone(), key.to_string());
+            if let Some(response) = entries.get(&cache_key, now) {
+                if response.request_hash != request_hash {
+                    return Err(AppError::new(
+                        ErrorKind::InvalidRequest,
+                        format!("Idempotency-Key {key:?} was already used for a different request"),
+                    ));
+                }
+                info!("Replaying the response to Idempotency-Key {key:?}");
+                return Ok(CacheLookup::Hit(CacheHit {
+                    response,
+                    header: (IDEMPOTENT_REPLAYED, "true"),
+                }));
+            }
+            if !entries.in_flight.insert(idempotency_key.clone()) {
+                return Err(AppError {
+                    status: StatusCode::CONFLICT,
+                    ..AppError::new(
+                        ErrorKind::InvalidRequest,
+                        format!("A request with Idempotency-Key {key:?} is still in progress"),
+                    )
+                }
+                .with_retry_after(Duration::from_secs(1)));
+            }
+            ticket.idempotency_key = Some(idempotency_key);
+        }
+        if config.enabled
+            && let Some(response) = entries.get(&CacheKey::Request(request_hash.clone()), now)
+        {
+            info!("Serving a cached response for {}", request.requested_model);
+            if let Some((principal, key)) = ticket.idempotency_key.take() {
+                entries.in_flight.remove(&(principal.clone(), key.clone()));
+                entries.insert(
+                    config,
+                    CacheKey::Idempotency(principal, key),
+                    response.clone(),
+                    now,
+                );
+            }
+            return Ok(CacheLookup::Hit(CacheHit {
+                response,
+                header: (CACHE_STATUS, "hit"),
+            }));
+        }
+        ticket.request_hash = Some(request_hash);
+        Ok(CacheLookup::Miss(ticket))
+    }
+}
+
+/// A hex SHA-256 of the Messages request after alias resolution and tenant
+/// defaults, with object keys sorted: the resolved model, messages, system
+/// prompt, tools and inference parameters. `stream` and `metadata` are left
+/// out since Bedrock never sees them. The model the client asked for, which
+/// the response echoes, the enabled beta features, the inference profile
+/// prefixes and fallback models that decide which Bedrock model serves it,
+/// and `owner`, the [`CacheScope`] the entry belongs to, are included.
+fn request_hash(owner: Option<&str>, request: &CacheRequest) -> anyhow::Result<String> {
+    let mut payload = serde_json::to_value(request.payload)?;
+    if let Some(payload) = payload.as_object_mut() {
+        payload.remove("stream");
+        payload.remove("metadata");
+    }
+    sort_keys(&mut payload);
+    let mut anthropic_beta = request.anthropic_beta.unwrap_or_default().to_vec();
+    anthropic_beta.sort();
+    let canonical = serde_json::json!([
+        owner,
+        request.requested_model,
+        anthropic_beta,
+        request.inference_profile_prefixes,
+        request.fallback_models,
+        payload
+    ]);
+    Ok(hex::encode(Sha256::digest(canonical.to_string())))
+}
+
+fn sort_keys(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(object) => {
+            object.sort_keys();
+            object.values_mut().for_each(sort_keys);
+        }
+        serde_json::Value::Array(array) => array.iter_mut().for_each(sort_keys),
+        _ => {}
+    }
+}
+
+/// A response found by [`ResponseCache::lookup`].
+pub struct CacheHit {
+    response: Arc<CachedResponse>,
+    header: (HeaderName, &'static str),
+}
+
+impl CacheHit {
+    /// The response as JSON, or replayed as SSE events if `stream`.
+    pub fn into_response(self, stream: bool) -> Result<Response, AppError> {
+        let mut headers = HeaderMap::new();
+        if let Ok(served_model) = HeaderValue::try_from(&self.response.served_model) {
+            headers.insert(BEDROCK_MODEL_ID, served_model);
+        }
+        headers.insert(self.header.0, HeaderValue::from_static(self.header.1));
+        if !stream {
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            return Ok((StatusCode::OK, headers, self.response.message.clone()).into_response());
+        }
+
+        let message: Message = serde_json::from_slice(&self.response.message)?;
+        let events = message_to_events(message)?
+            .into_iter()
+            .map(|(name, event)| {
+                Ok(Event::default()
+                    .event(name)
+                    .data(serde_json::to_string(&event)?))
+            })
+            .collect::<Result<Vec<_>, serde_json::Error>>()?;
+        let events = stream::iter(events.into_iter().map(Ok::<_, Infallible>));
+        Ok((StatusCode::OK, headers, Sse::new(events)).into_response())
+    }
+}
+
+/// Stores the response to a request [`ResponseCache::lookup`] missed. Its
+/// idempotency key, if any, stays reserved until the ticket is dropped.
+pub struct CacheTicket {
+    cache: ResponseCache,
+    config: ResponseCacheConfig,
+    request_hash: Option<String>,
+    /// The principal and the idempotency key it reserved.
+    idempotency_key: Option<(String, String)>,
+}
+
+impl CacheTicket {
+    /// Whether [`CacheTicket::store`] keeps the response, so callers can skip
+    /// collecting a streamed response nothing will read.
+    pub fn will_store(&self) -> bool {
+        self.request_hash.is_some()
+    }
+
+    pub fn store(&self, served_model: &str, message: &Message) {
+        let Some(request_hash) = &self.request_hash else {
+            return;
+        };
+        let message = match serde_json::to_vec(message) {
+            Ok(message) => Bytes::from(message),
+            Err(e) => {
+                warn!("Failed to cache response: {e}");
+                return;
+            }
+        };
+        let response = Arc::new(CachedResponse {
+            request_hash: request_hash.clone(),
+            served_model: served_model.to_string(),
+            message,
+        });
+        let now = Instant::now();
+        let mut entries = self.cache.entries.lock().unwrap();
+        if self.config.enabled {
+            entries.insert(
+                &self.config,
+                CacheKey::Request(request_hash.clone()),
+                response.clone(),
+                now,
+            );
+        }
+        if let Some((principal, key)) = &self.idempotency_key {
+            entries.insert(
+                &self.config,
+                CacheKey::Idempotency(principal.clone(), key.clone()),
+                response,
+                now,
+            );
+        }
+    }
+}
+
+impl Drop for CacheTicket {
+    fn drop(&mut self) {
+        if let Some(key) = &self.idempotency_key {
+            self.cache.entries.lock().unwrap().in_flight.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(json: &str) -> V1MessagesRequest {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn response(request_hash: &str, size: usize) -> Arc<CachedResponse> {
+        Arc::new(CachedResponse {
+            request_hash: request_hash.to_string(),
+            served_model: "model".to_string(),
+            message: Bytes::from(vec![b'x'; size]),
+        })
+    }
+
+    fn cache_request(payload: &V1MessagesRequest) -> CacheRequest<'_> {
+        CacheRequest {
+            requested_model: "sonnet",
+            payload,
+            anthropic_beta: None,
+            inference_profile_prefixes: &[],
+            fallback_models: &[],
+        }
+    }
+
+    #[test]
+    fn request_hash_ignores_key_order_stream_and_metadata() {
+        let hash = |json: &str| request_hash(Some("ci"), &cache_request(&request(json))).unwrap();
+        let a = hash(
+            r#"{"model": "m", "max_tokens": 16, "messages": [{"role": "user", "content": "hi"}]}"#,
+        );
+        let b = hash(
+            r#"{"messages": [{"content": "hi", "role": "user"}], "stream": true, "metadata": {"user_id": "u"}, "max_tokens": 16, "model": "m"}"#,
+        );
+        let c = hash(
+            r#"{"model": "m", "max_tokens": 17, "messages": [{"role": "user", "content": "hi"}]}"#,
+        );
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        let payload = request(
+            r#"{"model": "m", "max_tokens": 16, "messages": [{"role": "user", "content": "hi"}]}"#,
+        );
+        assert_ne!(
+            a,
+            request_hash(Some("bob"), &cache_request(&payload)).unwrap()
+        );
+        assert_ne!(a, request_hash(None, &cache_request(&payload)).unwrap());
+    }
+
+    #[test]
+    fn request_hash_includes_the_bedrock_routing() {
+        let payload = request(
+            r#"{"model": "m", "max_tokens": 16, "messages": [{"role": "user", "content": "hi"}]}"#,
+        );
+        let hash = |request: CacheRequest| request_hash(None, &request).unwrap();
+        let us = hash(CacheRequest {
+            inference_profile_prefixes: &["us.".to_string()],
+            ..cache_request(&payload)
+        });
+        let eu = hash(CacheRequest {
+            inference_profile_prefixes: &["eu.".to_string()],
+            ..cache_request(&payload)
+        });
+        let with_fallback = hash(CacheRequest {
+            inference_profile_prefixes: &["us.".to_string()],
+            fallback_models: &["m2".to_string()],
+            ..cache_request(&payload)
+        });
+        assert_ne!(us, eu);
+        assert_ne!(us, with_fallback);
+    }
+
+    #[test]
+    fn entries_expire_and_are_evicted_least_recently_used() {
+        let config = ResponseCacheConfig {
+            enabled: true,
+            scope: CacheScope::Key,
+            ttl_secs: 60,
+            max_entries: 2,
+            max_bytes: 100,
+        };
+        let key = |name: &str| CacheKey::Request(name.to_string());
+        let now = Instant::now();
+        let mut entries = Entries::default();
+        entries.insert(&config, key("a"), response("a", 10), now);
+        entries.insert(
+            &config,
+            key("b"),
+            response("b", 10),
+            now + Duration::from_secs(1),
+        );
+        assert!(
+            entries
+                .get(&key("a"), now + Duration::from_secs(2))
+                .is_some()
+        );
+        entries.insert(
+            &config,
+            key("c"),
+            response("c", 10),
+            now + Duration::from_secs(3),
+        );
+        assert!(entries.get(&key("b"), now).is_none());
+        assert!(entries.get(&key("a"), now).is_some());
+
+        entries.insert(&config, key("d"), response("d", 95), now);
+        assert_eq!(entries.entries.len(), 1);
+        assert_eq!(entries.bytes, 95);
+        entries.insert(&config, key("e"), response("e", 101), now);
+        assert!(entries.get(&key("e"), now).is_none());
+        assert!(
+            entries
+                .get(&key("d"), now + Duration::from_secs(60))
+                .is_none()
+        );
+        assert_eq!(entries.bytes, 0);
+    }
+}
@@ -0,0 +1,653 @@
+use anyhow::{Context, bail};
+use aws_sdk_bedrock::Client as BedrockClient;
+use chat::inference_profile::InferenceProfileResolver;
+use config::{Config, Environment, File, Map};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::signal::unix::{SignalKind, signal};
+use tracing::{error, info, warn};
+
+use crate::{
+    AppState,
+    aliases::{ModelAlias, ModelAliases},
+    auth::{ApiKeyConfig, ApiKeys, Principal},
+    bedrock_pool::BedrockPoolConfig,
+    budgets::{BudgetConfig, Budgets},
+    concurrency::{ConcurrencyConfig, ConcurrencyLimits},
+    fallbacks::{FallbackChain, FallbackChains},
+    ledger::{ModelPrice, PriceTable},
+    model_access::is_model_allowed,
+    models::{ModelCatalog, ModelConfig, load_model_catalog},
+    response_cache::ResponseCacheConfig,
+    tenants::{Tenant, TenantConfig, Tenants},
+    throttle::ThrottlingConfig,
+    tls::TlsConfig,
+};
+
+/// How often the config file's modification time is checked.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Keys only read at startup; a reload logs that they changed but keeps
+/// serving with the old values.
+const RESTART_KEYS: &[&str] = &[
+    "host",
+    "port",
+    "ledger_path",
+    "otlp_traces_endpoint",
+    "shutdown_timeout_secs",
+    "tls",
+    "bedrock_pool",
+];
+
+const ENV_PREFIX: &str = "LLM_PROXY";
+/// `LLM_PROXY_*` variables read by the command line rather than the config.
+const CLI_ENV_VARS: &[&str] = &["LLM_PROXY_CONFIG"];
+/// Keys whose `LLM_PROXY_*` variable is a comma-separated list.
+const LIST_KEYS: &[&str] = &["inference_profile_prefixes", "anthropic_beta_whitelist"];
+
+/// The configuration file after `LLM_PROXY_*` environment variables and
+/// command-line flags are applied. Absent keys take the defaults below;
+/// unknown keys and values of the wrong type are errors.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(default = "default_host")]
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default = "default_inference_profile_prefixes")]
+    pub inference_profile_prefixes: Vec<String>,
+    #[serde(default = "default_anthropic_beta_whitelist")]
+    pub anthropic_beta_whitelist: Vec<String>,
+    #[serde(default)]
+    pub discover_models: bool,
+    #[serde(default = "default_ledger_path")]
+    pub ledger_path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub otlp_traces_endpoint: Option<String>,
+    /// How long open streams may run after SIGTERM before they are ended.
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+    #[serde(default)]
+    pub bedrock_pool: BedrockPoolConfig,
+    #[serde(default)]
+    pub concurrency: ConcurrencyConfig,
+    #[serde(default)]
+    pub throttling: ThrottlingConfig,
+    #[serde(default)]
+    pub response_cache: ResponseCacheConfig,
+    #[serde(default)]
+    pub model_aliases: Vec<ModelAlias>,
+    #[serde(default)]
+    pub models: Vec<ModelConfig>,
+    #[serde(default)]
+    pub api_keys: Vec<ApiKeyConfig>,
+    #[serde(default)]
+    pub prices: Vec<ModelPrice>,
+    #[serde(default)]
+    pub fallbacks: Vec<FallbackChain>,
+    #[serde(default)]
+    pub tenants: Vec<TenantConfig>,
+    #[serde(default)]
+    pub budgets: Vec<BudgetConfig>,
+}
+
+fn default_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_port() -> u16 {
+    3000
+}
+
+fn default_inference_profile_prefixes() -> Vec<String> {
+    vec!["us.".to_string()]
+}
+
+fn default_anthropic_beta_whitelist() -> Vec<String> {
+    vec![
+        "adaptive-thinking-2026-01-28".to_string(),
+        "claude-code-20250219".to_string(),
+        "context-1m-2025-08-07".to_string(),
+        "effort-2025-11-24".to_string(),
+        "interleaved-thinking-2025-05-14".to_string(),
+        "structured-outputs-2025-12-15".to_string(),
+    ]
+}
+
+fn default_ledger_path() -> String {
+    "usage.db".to_string()
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+/// Where the configuration is read from: a TOML file, overridden by
+/// `LLM_PROXY_<KEY>` environment variables, overridden by `host` and `port`
+/// from the command line.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigSource {
+    pub path: PathBuf,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+}
+
+impl ConfigSource {
+    pub fn read(&self) -> anyhow::Result<ConfigFile> {
+        let env = std::env::vars()
+            .filter(|(name, _)| {
+                name.starts_with(ENV_PREFIX) && !CLI_ENV_VARS.contains(&name.as_str())
+            })
+            .collect();
+        self.read_with_env(env)
+    }
+
+    fn read_with_env(&self, env: Map<String, String>) -> anyhow::Result<ConfigFile> {
+        let environment = LIST_KEYS.iter().fold(
+            Environment::with_prefix(ENV_PREFIX)
+                .prefix_separator("_")
+                .try_parsing(true)
+                .list_separator(",")
+                .ignore_empty(true)
+                .source(Some(env)),
+            |environment, key| environment.with_list_parse_key(key),
+        );
+        Config::builder()
+            .add_source(File::from(self.path.as_path()))
+            .add_source(environment)
+            .set_override_option("host", self.host.clone())?
+            .set_override_option("port", self.port)?
+            .build()
+            .and_then(Config::try_deserialize)
+            .with_context(|| format!("invalid configuration in {}", self.path.display()))
+    }
+}
+
+/// The part of the configuration that can change while the server runs,
+/// held in `AppState::settings` and swapped as a whole on reload. A request
+/// keeps the snapshot it started with, so in-flight streams are unaffected.
+#[derive(Default)]
+pub struct Settings {
+    pub inference_profiles: Arc<InferenceProfileResolver>,
+    pub anthropic_beta_whitelist: Vec<String>,
+    pub api_keys: ApiKeys,
+    pub model_aliases: ModelAliases,
+    pub models: ModelCatalog,
+    pub prices: PriceTable,
+    pub concurrency: ConcurrencyLimits,
+    pub throttling: ThrottlingConfig,
+    pub fallbacks: FallbackChains,
+    pub tenants: Tenants,
+    pub budgets: Budgets,
+    pub response_cache: ResponseCacheConfig,
+}
+
+impl Settings {
+    /// Builds settings from `config` with the catalog limited to configured
+    /// models and aliases; [`SettingsLoader`] adds discovered models. Fails
+    /// on the first invalid value.
+    pub fn from_config(config: &ConfigFile) -> anyhow::Result<Self> {
+        info!(
+            "inference_profile_prefixes: {:?}",
+            config.inference_profile_prefixes
+        );
+        info!(
+            "anthropic_beta_whitelist: {:?}",
+            config.anthropic_beta_whitelist
+        );
+
+        let api_keys = ApiKeys::new(config.api_keys.clone())?;
+        if !api_keys.is_enabled() {
+            warn!("No api_keys configured: authentication is disabled");
+        }
+
+        info!("model_aliases: {} rules", config.model_aliases.len());
+        let model_aliases = ModelAliases::new(config.model_aliases.clone())?;
+
+        info!(
+            "models: {} configured, discovery {}",
+            config.models.len(),
+            if config.discover_models {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+        let mut models =
+            ModelCatalog::from_config(&config.models, &config.inference_profile_prefixes);
+        models.extend_aliases(&model_aliases, &config.inference_profile_prefixes);
+
+        info!("prices: {} models", config.prices.len());
+        let prices = PriceTable::new(config.prices.clone())?;
+        let concurrency = ConcurrencyLimits::new(config.concurrency.clone())?;
+        config.throttling.validate()?;
+        config.response_cache.validate()?;
+        config.bedrock_pool.validate()?;
+        info!("fallbacks: {} chains", config.fallbacks.len());
+        let fallbacks = FallbackChains::new(config.fallbacks.clone())?;
+        info!("tenants: {}", config.tenants.len());
+        let tenants = Tenants::new(config.tenants.clone())?;
+        if let Some(key) = config.api_keys.iter().find(|key| {
+            key.tenant
+                .as_deref()
+                .is_some_and(|tenant| tenants.get(tenant).is_none())
+        }) {
+            bail!(
+                "api key {:?}: unknown tenant {:?}",
+                key.name,
+                key.tenant.as_deref().unwrap_or_default()
+            );
+        }
+        info!("budgets: {}", config.budgets.len());
+        let budgets = Budgets::new(config.budgets.clone())?;
+        for budget in budgets.iter() {
+            if api_keys.is_enabled() && !config.api_keys.iter().any(|key| key.name == budget.key) {
+                warn!("Budget for {:?} matches no api key", budget.key);
+            }
+        }
+
+        Ok(Self {
+            inference_profiles: Arc::new(InferenceProfileResolver::new(
+                config.inference_profile_prefixes.clone(),
+            )),
+            anthropic_beta_whitelist: config.anthropic_beta_whitelist.clone(),
+            api_keys,
+            model_aliases,
+            models,
+            prices,
+            concurrency,
+            throttling: config.throttling.clone(),
+            fallbacks,
+            tenants,
+            budgets,
+            response_cache: config.response_cache.clone(),
+        })
+    }
+
+    /// The beta features `tenant` may use: its own whitelist, or else the
+    /// global one.
+    pub fn anthropic_beta_whitelist_for<'a>(&'a self, tenant: Option<&'a Tenant>) -> &'a [String] {
+        tenant
+            .and_then(|tenant| tenant.anthropic_beta_whitelist.as_deref())
+            .unwrap_or(&self.anthropic_beta_whitelist)
+    }
+
+    /// The catalog entries `principal` may use, judged by the model each one
+    /// resolves to after aliases, so listings match what requests accept.
+    pub fn models_for(&self, principal: &Principal, tenant: Option<&Tenant>) -> ModelCatalog {
+        self.models.filtered(|id| {
+            let model = self.model_aliases.resolve(id);
+            is_model_allowed(principal, tenant, model.as_deref().unwrap_or(id))
+        })
+    }
+
+    /// The fallbacks of `model` that `principal` may use, in order, so a
+    /// throttled model never falls back to one the caller is denied.
+    pub fn fallbacks_for(
+        &self,
+        principal: &Principal,
+        tenant: Option<&Tenant>,
+        model: &str,
+    ) -> Vec<String> {
+        self.fallbacks
+            .chain(model)
+            .into_iter()
+            .filter(|fallback| is_model_allowed(principal, tenant, fallback))
+            .collect()
+    }
+
+    pub fn inference_profile_prefixes_for<'a>(
+        &'a self,
+        tenant: Option<&'a Tenant>,
+    ) -> &'a [String] {
+        tenant
+            .and_then(Tenant::inference_profile_prefixes)
+            .unwrap_or(self.inference_profiles.prefixes())
+    }
+
+    pub fn inference_profiles_for(&self, tenant: Option<&Tenant>) -> Arc<InferenceProfileResolver> {
+        tenant
+            .and_then(|tenant| tenant.inference_profiles.clone())
+            .unwrap_or_else(|| self.inference_profiles.clone())
+    }
+}
+
+/// A configuration that passed validation, and the settings built from it.
+pub struct LoadedSettings {
+    pub config: ConfigFile,
+    pub settings: Settings,
+}
+
+/// Reads the configuration and builds [`Settings`] from it, including
+/// Bedrock model discovery when `discover_models` is set.
+pub struct SettingsLoader {
+    source: ConfigSource,
+    bedrock_client: BedrockClient,
+}
+
+impl SettingsLoader {
+    pub fn new(source: ConfigSource, bedrock_client: BedrockClient) -> Self {
+        Self {
+            source,
+            bedrock_client,
+        }
+    }
+
+    pub fn source(&self) -> &ConfigSource {
+        &self.source
+    }
+
+    pub async fn load(&self) -> anyhow::Result<LoadedSettings> {
+        self.build(self.source.read()?).await
+    }
+
+    pub async fn build(&self, config: ConfigFile) -> anyhow::Result<LoadedSettings> {
+        let mut settings = Settings::from_config(&config)?;
+        if config.discover_models {
+            settings.models = load_model_catalog(
+                &self.bedrock_client,
+                &config.models,
+                true,
+                &config.inference_profile_prefixes,
+            )
+            .await;
+            settings
+                .models
+                .extend_aliases(&settings.model_aliases, &config.inference_profile_prefixes);
+        }
+        Ok(LoadedSettings { config, settings })
+    }
+}
+
+/// Re-reads the config file into `AppState::settings`, on demand or when
+/// watched. An invalid file is logged and the running settings are kept.
+pub struct SettingsReloader {
+    loader: SettingsLoader,
+    state: Arc<AppState>,
+    config: ConfigFile,
+}
+
+impl SettingsReloader {
+    /// `config` is the configuration `state` was built from.
+    pub fn new(loader: SettingsLoader, state: Arc<AppState>, config: ConfigFile) -> Self {
+        Self {
+            loader,
+            state,
+            config,
+        }
+    }
+
+    /// Loads the config file and activates it if it is valid, logging each
+    /// changed key. Returns whether the new settings were activated.
+    pub async fn reload(&mut self) -> bool {
+        let loaded = match self.loader.load().await {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                error!("Config reload failed, keeping the current settings: {e:#}");
+                return false;
+            }
+        };
+
+        let changes = describe_changes(
+            &serde_json::to_value(&self.config).unwrap_or_default(),
+            &serde_json::to_value(&loaded.config).unwrap_or_default(),
+        );
+        if changes.is_empty() {
+            info!("Config reloaded without changes");
+        }
+        for (key, change) in &changes {
+            if RESTART_KEYS.contains(&key.as_str()) {
+                warn!("Config changed {change}; restart to apply it");
+            } else {
+                info!("Config changed {change}");
+            }
+        }
+
+        let mut settings = loaded.settings;
+        let current = self.state.settings.load();
+        if settings.inference_profiles.prefixes() == current.inference_profiles.prefixes() {
+            // Keep what the resolver learned about which profiles work.
+            settings.inference_profiles = current.inference_profiles.clone();
+        }
+        settings.tenants.keep_inference_profiles(&current.tenants);
+        self.state.settings.store(Arc::new(settings));
+        self.config = loaded.config;
+        true
+    }
+
+    /// Reloads on SIGHUP and whenever the config file's modification time
+    /// changes. Runs until the process exits.
+    pub async fn watch(mut self) -> anyhow::Result<()> {
+        let mut hangup = signal(SignalKind::hangup())?;
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+        let path = self.loader.source().path.clone();
+        let mut last_modified = modified(&path);
+        info!(
+            "Watching {} for changes; send SIGHUP to reload",
+            path.display()
+        );
+        loop {
+            tokio::select! {
+                _ = hangup.recv() => info!("Received SIGHUP, reloading config"),
+                _ = interval.tick() => {
+                    if modified(&path) == last_modified {
+                        continue;
+                    }
+                    info!("{} changed, reloading config", path.display());
+                }
+            }
+            last_modified = modified(&path);
+            self.reload().await;
+        }
+    }
+}
+
+pub(crate) fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// One `(key, description)` per top-level key whose value differs. Array
+/// values list the entries added and removed; API key hashes are redacted.
+fn describe_changes(old: &Value, new: &Value) -> Vec<(String, String)> {
+    let empty = serde_json::Map::new();
+    let old = old.as_object().unwrap_or(&empty);
+    let new = new.as_object().unwrap_or(&empty);
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+
+    keys.into_iter()
+        .filter_map(|key| {
+            let (before, after) = (old.get(key), new.get(key));
+            if before == after {
+                return None;
+            }
+            let change = match (before, after) {
+                (Some(Value::Array(before)), Some(Value::Array(after))) => {
+                    let difference = |a: &[Value], b: &[Value]| {
+                        a.iter()
+                            .filter(|value| !b.contains(value))
+                            .map(|value| render(key, value))
+                            .collect::<Vec<_>>()
+                    };
+                    let added = difference(after, before);
+                    let removed = difference(before, after);
+                    if added.is_empty() && removed.is_empty() {
+                        format!("{key}: reordered")
+                    } else {
+                        format!(
+                            "{key}: added [{}], removed [{}]",
+                            added.join(", "),
+                            removed.join(", ")
+                        )
+                    }
+                }
+                (before, after) => {
+                    let render = |value: Option<&Value>| {
+                        value.map_or_else(|| "unset".to_string(), |value| render(key, value))
+                    };
+                    format!("{key}: {} -> {}", render(before), render(after))
+                }
+            };
+            Some((key.clone(), change))
+        })
+        .collect()
+}
+
+fn render(key: &str, value: &Value) -> String {
+    match value {
+        Value::Object(object) if key == "api_keys" && object.contains_key("key_hash") => {
+            let mut object = object.clone();
+            object.insert("key_hash".to_string(), "<redacted>".into());
+            Value::Object(object).to_string()
+        }
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn describes_scalar_and_array_changes() {
+        let old = json!({
+            "port": 3000,
+            "discover_models": false,
+            "anthropic_beta_whitelist": ["a", "b"],
+            "inference_profile_prefixes": ["us.", "global."],
+        });
+        let new = json!({
+            "port": 3001,
+            "anthropic_beta_whitelist": ["b", "c"],
+            "inference_profile_prefixes": ["global.", "us."],
+            "ledger_path": "usage.db",
+        });
+        assert_eq!(
+            describe_changes(&old, &new),
+            vec![
+                (
+                    "anthropic_beta_whitelist".to_string(),
+                    r#"anthropic_beta_whitelist: added ["c"], removed ["a"]"#.to_string()
+                ),
+                (
+                    "discover_models".to_string(),
+                    "discover_models: false -> unset".to_string()
+                ),
+                (
+                    "inference_profile_prefixes".to_string(),
+                    "inference_profile_prefixes: reordered".to_string()
+                ),
+                (
+                    "ledger_path".to_string(),
+                    r#"ledger_path: unset -> "usage.db""#.to_string()
+                ),
+                ("port".to_string(), "port: 3000 -> 3001".to_string()),
+            ]
+        );
+    }
+
+    fn read(
+        name: &str,
+        toml: &str,
+        source: ConfigSource,
+        env: &[(&str, &str)],
+    ) -> anyhow::Result<ConfigFile> {
+        let path = std::env::temp_dir().join(format!(
+            "llm-proxy-settings-{}-{}.toml",
+            name,
+            std::process::id()
+        ));
+        std::fs::write(&path, toml).unwrap();
+        let env = env
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let config = ConfigSource {
+            path: path.clone(),
+            ..source
+        }
+        .read_with_env(env);
+        let _ = std::fs::remove_file(&path);
+        config
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_wrong_types() {
+        let error = read("unknown", "prot = 3001\n", ConfigSource::default(), &[]).unwrap_err();
+        assert!(format!("{error:#}").contains("unknown field `prot`"));
+
+        let error = read(
+            "unknown-nested",
+            "[[api_keys]]\nname = \"alice\"\nkey_hash = \"sha256:aa\"\nrpm = 10\n",
+            ConfigSource::default(),
+            &[],
+        )
+        .unwrap_err();
+        assert!(format!("{error:#}").contains("unknown field `rpm`"));
+
+        let error = read("type", "port = \"http\"\n", ConfigSource::default(), &[]).unwrap_err();
+        assert!(format!("{error:#}").contains("port"));
+    }
+
+    #[test]
+    fn environment_overrides_file_and_command_line_overrides_both() {
+        let config = read(
+            "overrides",
+            "host = \"0.0.0.0\"\nport = 3001\ndiscover_models = true\n",
+            ConfigSource {
+                port: Some(4000),
+                ..Default::default()
+            },
+            &[
+                ("LLM_PROXY_HOST", "10.0.0.1"),
+                ("LLM_PROXY_PORT", "8080"),
+                ("LLM_PROXY_DISCOVER_MODELS", "false"),
+                ("LLM_PROXY_INFERENCE_PROFILE_PREFIXES", "eu.,global."),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.host, "10.0.0.1");
+        assert_eq!(config.port, 4000);
+        assert!(!config.discover_models);
+        assert_eq!(config.inference_profile_prefixes, vec!["eu.", "global."]);
+        assert_eq!(config.ledger_path, "usage.db");
+    }
+
+    #[test]
+    fn effective_config_round_trips() {
+        let config = ConfigSource {
+            path: Path::new(env!("CARGO_MANIFEST_DIR")).join("../config.toml"),
+            ..Default::default()
+        }
+        .read_with_env(Map::new())
+        .unwrap();
+        Settings::from_config(&config).unwrap();
+        let printed = toml::to_string_pretty(&config).unwrap();
+        let reparsed = read("round-trip", &printed, ConfigSource::default(), &[]).unwrap();
+        assert_eq!(
+            serde_json::to_value(&reparsed).unwrap(),
+            serde_json::to_value(&config).unwrap()
+        );
+    }
+
+    #[test]
+    fn redacts_api_key_hashes() {
+        let old = json!({"api_keys": [{"name": "alice", "key_hash": "sha256:aa"}]});
+        let new = json!({"api_keys": [{"name": "alice", "key_hash": "sha256:bb"}]});
+        let changes = describe_changes(&old, &new);
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].1.contains("alice"));
+        assert!(!changes[0].1.contains("sha256"));
+    }
+}
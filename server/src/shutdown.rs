@@ -0,0 +1,191 @@
+use axum::response::sse::Event;
+use chat::metrics::streams_in_flight;
+use futures::stream::{self, Stream, StreamExt};
+use std::time::Duration;
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    sync::watch,
+};
+use tracing::{info, warn};
+
+/// Message of the error event sent to streams still open at the deadline.
+pub const SHUTDOWN_MESSAGE: &str = "The server is shutting down; retry the request.";
+
+/// How long the error events sent at the deadline get to reach clients
+/// before the process exits regardless.
+pub const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Phase {
+    Serving,
+    /// No new connections; open requests and streams run to completion.
+    Draining,
+    /// The drain deadline passed; open streams are ended.
+    Expired,
+}
+
+/// Coordinates a graceful shutdown between the listener, which stops
+/// accepting connections once draining starts, and SSE responses, which are
+/// ended with an error event if they are still open when it expires.
+#[derive(Clone)]
+pub struct Shutdown {
+    phase: watch::Sender<Phase>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            phase: watch::Sender::new(Phase::Serving),
+        }
+    }
+}
+
+impl Shutdown {
+    fn advance(&self, phase: Phase) {
+        self.phase.send_if_modified(|current| {
+            let advanced = *current < phase;
+            if advanced {
+                *current = phase;
+            }
+            advanced
+        });
+    }
+
+    async fn reached(&self, phase: Phase) {
+        // The sender lives in `self`, so the channel cannot close while waiting.
+        let _ = self
+            .phase
+            .subscribe()
+            .wait_for(|current| *current >= phase)
+            .await;
+    }
+
+    /// Stops accepting connections and lets open requests finish.
+    pub fn drain(&self) {
+        self.advance(Phase::Draining);
+    }
+
+    /// Ends the streams still open.
+    pub fn expire(&self) {
+        self.advance(Phase::Expired);
+    }
+
+    /// Resolves once draining has started.
+    pub async fn draining(self) {
+        self.reached(Phase::Draining).await
+    }
+
+    /// Resolves once the drain deadline has passed.
+    pub async fn expired(self) {
+        self.reached(Phase::Expired).await
+    }
+
+    /// Waits for SIGTERM or Ctrl-C, then drains for up to `timeout` before
+    /// expiring the streams still open.
+    pub async fn handle_signals(self, timeout: Duration) -> anyhow::Result<()> {
+        let mut sigterm = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = sigterm.recv() => info!("Received SIGTERM"),
+            result = tokio::signal::ctrl_c() => {
+                result?;
+                info!("Received Ctrl-C");
+            }
+        }
+        info!(
+            "Shutting down: no longer accepting connections, draining {} open streams for up to {:?}",
+            streams_in_flight(),
+            timeout
+        );
+        self.drain();
+        tokio::time::sleep(timeout).await;
+        warn!(
+            "Drain deadline passed, ending {} open streams",
+            streams_in_flight()
+        );
+        self.expire();
+        Ok(())
+    }
+
+    /// Relays `events` until they end or the drain deadline passes, in which
+    /// case the stream is ended with the events `on_expiry` builds.
+    pub fn guard<S, F>(
+        &self,
+        events: S,
+        on_expiry: F,
+    ) -> impl Stream<Item = anyhow::Result<Event>> + Send + 'static
+    where
+        S: Stream<Item = anyhow::Result<Event>> + Send + Unpin + 'static,
+        F: FnOnce() -> Vec<anyhow::Result<Event>> + Send + 'static,
+    {
+        let shutdown = self.clone();
+        stream::unfold(Guarded::Open(events, on_expiry), move |state| {
+            let shutdown = shutdown.clone();
+            async move {
+                match state {
+                    Guarded::Open(mut events, on_expiry) => tokio::select! {
+                        biased;
+                        () = shutdown.expired() => {
+                            let mut last = on_expiry().into_iter();
+                            last.next().map(|event| (event, Guarded::Expired(last)))
+                        }
+                        event = events.next() => {
+                            event.map(|event| (event, Guarded::Open(events, on_expiry)))
+                        }
+                    },
+                    Guarded::Expired(mut last) => {
+                        last.next().map(|event| (event, Guarded::Expired(last)))
+                    }
+                }
+            }
+        })
+    }
+}
+
+enum Guarded<S, F> {
+    Open(S, F),
+    Expired(std::vec::IntoIter<anyhow::Result<Event>>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(data: &str) -> anyhow::Result<Event> {
+        Ok(Event::default().data(data))
+    }
+
+    #[tokio::test]
+    async fn guarded_stream_runs_to_completion_while_draining() {
+        let shutdown = Shutdown::default();
+        shutdown.drain();
+        let events = shutdown
+            .guard(stream::iter(vec![event("a"), event("b")]), || {
+                vec![event("error")]
+            })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(events.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn guarded_stream_ends_with_error_event_on_expiry() {
+        let shutdown = Shutdown::default();
+        let mut events = Box::pin(shutdown.guard(
+            stream::iter(vec![event("a")]).chain(stream::pending()),
+            || vec![event("error"), event("done")],
+        ));
+        assert!(events.next().await.is_some());
+
+        shutdown.expire();
+        let last = events.next().await.unwrap().unwrap();
+        assert_eq!(
+            format!("{last:?}"),
+            format!("{:?}", event("error").unwrap())
+        );
+        assert!(events.next().await.is_some());
+        assert!(events.next().await.is_none());
+
+        shutdown.drain();
+        shutdown.clone().draining().await;
+    }
+}
@@ -0,0 +1,134 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use chat::usage::UsageEvent;
+use opentelemetry::{
+    Context, StringValue, Value,
+    propagation::{Extractor, TextMapPropagator},
+    trace::TracerProvider,
+};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{Resource, propagation::TraceContextPropagator, trace::SdkTracerProvider};
+use tracing::{Instrument, Span, field::Empty, info_span, level_filters::LevelFilter};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+const SERVICE_NAME: &str = "llm-proxy";
+
+/// Installs the global `tracing` subscriber: formatted logs at INFO, plus
+/// OTLP/HTTP trace export when `otlp_traces_endpoint` is set (a full URL such
+/// as `http://localhost:4318/v1/traces`). The returned provider must be shut
+/// down on exit to flush buffered spans.
+pub fn init_tracing(
+    otlp_traces_endpoint: Option<&str>,
+) -> anyhow::Result<Option<SdkTracerProvider>> {
+    let provider = otlp_traces_endpoint
+        .map(|endpoint| -> anyhow::Result<_> {
+            let exporter = SpanExporter::builder()
+                .with_http()
+                .with_endpoint(endpoint)
+                .build()?;
+            Ok(SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+                .build())
+        })
+        .transpose()?;
+
+    tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(tracing_subscriber::fmt::layer())
+        .with(provider.as_ref().map(|provider| {
+            tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME))
+        }))
+        .init();
+    Ok(provider)
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// The caller's trace context from the W3C `traceparent` and `tracestate`
+/// headers; empty when they are absent or malformed.
+pub fn extract_trace_context(headers: &HeaderMap) -> Context {
+    TraceContextPropagator::new().extract(&HeaderExtractor(headers))
+}
+
+/// Runs every routed request in a `request` span, a child of the caller's
+/// trace when it sent `traceparent`. The request ID, principal, model and
+/// usage are recorded on it as the request progresses.
+pub async fn trace_requests(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let route = request.extensions().get::<MatchedPath>().map_or_else(
+        || request.uri().path().to_string(),
+        |path| path.as_str().to_string(),
+    );
+    let span = info_span!(
+        "request",
+        otel.name = %format!("{method} {route}"),
+        otel.kind = "server",
+        http.request.method = %method,
+        http.route = %route,
+        http.response.status_code = Empty,
+        request_id = Empty,
+        principal = Empty,
+        gen_ai.operation.name = Empty,
+        gen_ai.provider.name = Empty,
+        gen_ai.request.model = Empty,
+    );
+    let _ = span.set_parent(extract_trace_context(request.headers()));
+
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("http.response.status_code", response.status().as_u16());
+    response
+}
+
+/// Marks the current request span as a Bedrock chat call for `model`.
+pub fn record_chat_request(model: &str) {
+    let span = Span::current();
+    span.record("gen_ai.operation.name", "chat");
+    span.record("gen_ai.provider.name", "aws.bedrock");
+    span.record("gen_ai.request.model", model);
+}
+
+/// Records the outcome of a chat call on its request span.
+pub fn record_chat_usage(span: &Span, event: &UsageEvent) {
+    span.set_attribute("gen_ai.response.model", event.model.clone());
+    if let Some(id) = &event.bedrock_request_id {
+        span.set_attribute("aws.request_id", id.clone());
+    }
+    if let Some(usage) = &event.usage {
+        span.set_attribute("gen_ai.usage.input_tokens", i64::from(usage.input_tokens));
+        span.set_attribute("gen_ai.usage.output_tokens", i64::from(usage.output_tokens));
+        if let Some(tokens) = usage.cache_read_input_tokens {
+            span.set_attribute("gen_ai.usage.cache_read.input_tokens", i64::from(tokens));
+        }
+        if let Some(tokens) = usage.cache_write_input_tokens {
+            span.set_attribute(
+                "gen_ai.usage.cache_creation.input_tokens",
+                i64::from(tokens),
+            );
+        }
+    }
+    if let Some(stop_reason) = &event.stop_reason {
+        span.set_attribute(
+            "gen_ai.response.finish_reasons",
+            Value::Array(vec![StringValue::from(stop_reason.as_str().to_string())].into()),
+        );
+    }
+    if let Some(kind) = event.error {
+        span.set_attribute("error.type", kind.anthropic_type());
+    }
+}
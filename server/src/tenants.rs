@@ -0,0 +1,280 @@
+use anthropic_request::V1MessagesRequest;
+use anyhow::{Context, bail};
+use chat::inference_profile::InferenceProfileResolver;
+use request::ChatCompletionsRequest;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use crate::{
+    auth::Principal, identity::AwsIdentity, model_access::ModelRules, rate_limit::RateLimits,
+};
+
+/// An entry of the `[[tenants]]` table in `config.toml`. API keys join a
+/// tenant with `tenant = "<name>"`. Unset settings fall back to the global
+/// ones; the limits are shared by all of the tenant's keys, on top of each
+/// key's own, and the identity applies to keys without one.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TenantConfig {
+    pub name: String,
+    /// See [`ModelRules`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_models: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub denied_models: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anthropic_beta_whitelist: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inference_profile_prefixes: Option<Vec<String>>,
+    #[serde(default)]
+    pub defaults: DefaultParams,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requests_per_minute: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_tokens_per_minute: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_tokens_per_minute: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_requests: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role_arn: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aws_profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub session_tags: BTreeMap<String, String>,
+}
+
+/// Sampling parameters filled in when a request leaves them unset.
+/// `max_tokens` only applies to Chat Completions, where it is optional, and
+/// `top_k` only to Messages, which is the only API that has it.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DefaultParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<i32>,
+}
+
+impl DefaultParams {
+    pub fn apply_to_v1_messages(&self, payload: &mut V1MessagesRequest) {
+        payload.temperature = payload.temperature.or(self.temperature);
+        payload.top_p = payload.top_p.or(self.top_p);
+        payload.top_k = payload.top_k.or(self.top_k);
+    }
+
+    pub fn apply_to_chat_completions(&self, payload: &mut ChatCompletionsRequest) {
+        payload.max_tokens = payload.max_tokens.or(self.max_tokens);
+        payload.temperature = payload.temperature.or(self.temperature);
+        payload.top_p = payload.top_p.or(self.top_p);
+    }
+}
+
+/// A validated [`TenantConfig`].
+#[derive(Debug)]
+pub struct Tenant {
+    pub name: String,
+    pub models: ModelRules,
+    pub anthropic_beta_whitelist: Option<Vec<String>>,
+    /// Set when the tenant has its own prefixes, so what it learns about
+    /// unavailable profiles does not leak into the global resolver.
+    pub inference_profiles: Option<Arc<InferenceProfileResolver>>,
+    pub defaults: DefaultParams,
+    pub limits: RateLimits,
+    pub identity: Option<AwsIdentity>,
+}
+
+impl Tenant {
+    /// The tenant's own inference profile prefixes, if it has any.
+    pub fn inference_profile_prefixes(&self) -> Option<&[String]> {
+        self.inference_profiles
+            .as_deref()
+            .map(InferenceProfileResolver::prefixes)
+    }
+}
+
+/// The configured tenants by name.
+#[derive(Debug, Default)]
+pub struct Tenants {
+    tenants: HashMap<String, Tenant>,
+}
+
+impl Tenants {
+    pub fn new(configs: Vec<TenantConfig>) -> anyhow::Result<Self> {
+        let mut tenants = HashMap::new();
+        for config in configs {
+            if config.name.is_empty() {
+                bail!("tenant name must not be empty");
+            }
+            let limits = RateLimits {
+                requests_per_minute: config.requests_per_minute,
+                input_tokens_per_minute: config.input_tokens_per_minute,
+                output_tokens_per_minute: config.output_tokens_per_minute,
+                max_concurrent_requests: config.max_concurrent_requests,
+            };
+            if [
+                limits.requests_per_minute,
+                limits.input_tokens_per_minute,
+                limits.output_tokens_per_minute,
+                limits.max_concurrent_requests,
+            ]
+            .contains(&Some(0))
+            {
+                bail!("tenant {:?}: limits must be positive", config.name);
+            }
+            let identity = AwsIdentity::new(
+                &config.name,
+                config.role_arn,
+                config.aws_profile,
+                config.external_id,
+                config.session_tags,
+            );
+            if let Some(identity) = &identity {
+                identity
+                    .validate()
+                    .with_context(|| format!("tenant {:?}", config.name))?;
+            }
+            let models = ModelRules {
+                allowed_models: config.allowed_models,
+                denied_models: config.denied_models,
+            };
+            models
+                .validate()
+                .with_context(|| format!("tenant {:?}", config.name))?;
+            let tenant = Tenant {
+                name: config.name.clone(),
+                models,
+                inference_profiles: config
+                    .inference_profile_prefixes
+                    .map(|prefixes| Arc::new(InferenceProfileResolver::new(prefixes))),
+                anthropic_beta_whitelist: config.anthropic_beta_whitelist,
+                defaults: config.defaults,
+                limits,
+                identity,
+            };
+            if tenants.insert(config.name.clone(), tenant).is_some() {
+                bail!("duplicate tenant {:?}", config.name);
+            }
+        }
+        Ok(Self { tenants })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Tenant> {
+        self.tenants.get(name)
+    }
+
+    /// The tenant `principal` belongs to, if any.
+    pub fn of(&self, principal: &Principal) -> Option<&Tenant> {
+        principal
+            .tenant
+            .as_deref()
+            .and_then(|name| self.tenants.get(name))
+    }
+
+    /// Takes over the resolvers of `current`'s tenants whose prefixes are
+    /// unchanged, keeping what they learned about which profiles work.
+    pub fn keep_inference_profiles(&mut self, current: &Tenants) {
+        for (name, tenant) in &mut self.tenants {
+            if let Some(current) = current.tenants.get(name)
+                && current.inference_profile_prefixes() == tenant.inference_profile_prefixes()
+            {
+                tenant.inference_profiles = current.inference_profiles.clone();
+            }
+        }
+    }
+}
+
+/// The AWS identity `principal`'s Bedrock calls are made as: its own, or
+/// else its tenant's.
+pub fn aws_identity<'a>(
+    principal: &'a Principal,
+    tenant: Option<&'a Tenant>,
+) -> Option<&'a AwsIdentity> {
+    principal
+        .identity
+        .as_ref()
+        .or(tenant.and_then(|tenant| tenant.identity.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tenant(name: &str) -> TenantConfig {
+        TenantConfig {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn tenant_is_read_and_validated() {
+        let config: TenantConfig = toml::from_str(
+            r#"
+            name = "search"
+            allowed_models = ["*claude-sonnet-4*"]
+            anthropic_beta_whitelist = []
+            requests_per_minute = 600
+            role_arn = "arn:aws:iam::123456789012:role/search"
+            defaults = { temperature = 0.2, max_tokens = 1024 }
+            "#,
+        )
+        .unwrap();
+        let tenants = Tenants::new(vec![config.clone()]).unwrap();
+        let search = tenants.get("search").unwrap();
+        assert!(search.models.allows("us.anthropic.claude-sonnet-4-5-v1:0"));
+        assert!(!search.models.allows("us.anthropic.claude-opus-4-1-v1:0"));
+        assert_eq!(search.limits.requests_per_minute, Some(600));
+        assert_eq!(
+            search.identity.as_ref().unwrap().session_name,
+            "llm-proxy-search"
+        );
+        assert!(search.inference_profiles.is_none());
+
+        assert!(Tenants::new(vec![tenant("a"), tenant("a")]).is_err());
+        assert!(Tenants::new(vec![tenant("")]).is_err());
+        assert!(
+            Tenants::new(vec![TenantConfig {
+                max_concurrent_requests: Some(0),
+                ..tenant("a")
+            }])
+            .is_err()
+        );
+        assert!(
+            Tenants::new(vec![TenantConfig {
+                role_arn: Some("search".to_string()),
+                ..tenant("a")
+            }])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn defaults_only_fill_unset_parameters() {
+        let defaults = DefaultParams {
+            max_tokens: Some(1024),
+            temperature: Some(0.2),
+            top_p: None,
+            top_k: Some(5),
+        };
+        let mut payload: ChatCompletionsRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude",
+            "messages": [],
+            "temperature": 1.0,
+        }))
+        .unwrap();
+        defaults.apply_to_chat_completions(&mut payload);
+        assert_eq!(payload.max_tokens, Some(1024));
+        assert_eq!(payload.temperature, Some(1.0));
+        assert_eq!(payload.top_p, None);
+    }
+}
@@ -0,0 +1,427 @@
+use anyhow::bail;
+use chat::error::ErrorKind;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::{
+    error::AppError,
+    metrics::{record_throttle_rate, record_throttled_request},
+};
+
+/// Weight of the newest gap between requests in the observed request rate.
+const OBSERVED_RATE_WEIGHT: f64 = 0.2;
+/// Requests per second assumed when Bedrock throttles a model before its
+/// request rate is known.
+const INITIAL_RATE: f64 = 1.0;
+
+/// The `[throttling]` table of `config.toml`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ThrottlingConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Longest a request is held back; later slots are refused with a 429.
+    #[serde(default = "default_max_queue_wait_secs")]
+    pub max_queue_wait_secs: u64,
+    /// The rate is multiplied by this on every throttling error.
+    #[serde(default = "default_decrease_factor")]
+    pub decrease_factor: f64,
+    /// Requests per second the rate grows by per second of successful
+    /// requests.
+    #[serde(default = "default_additive_increase")]
+    pub additive_increase: f64,
+    /// The rate never drops below this many requests per second.
+    #[serde(default = "default_min_requests_per_second")]
+    pub min_requests_per_second: f64,
+}
+
+impl Default for ThrottlingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            max_queue_wait_secs: default_max_queue_wait_secs(),
+            decrease_factor: default_decrease_factor(),
+            additive_increase: default_additive_increase(),
+            min_requests_per_second: default_min_requests_per_second(),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_max_queue_wait_secs() -> u64 {
+    30
+}
+
+fn default_decrease_factor() -> f64 {
+    0.5
+}
+
+fn default_additive_increase() -> f64 {
+    0.1
+}
+
+fn default_min_requests_per_second() -> f64 {
+    0.05
+}
+
+impl ThrottlingConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(self.decrease_factor > 0.0 && self.decrease_factor < 1.0) {
+            bail!("throttling.decrease_factor must be between 0 and 1");
+        }
+        if !(self.additive_increase.is_finite() && self.additive_increase > 0.0) {
+            bail!("throttling.additive_increase must be positive");
+        }
+        if !(self.min_requests_per_second.is_finite() && self.min_requests_per_second > 0.0) {
+            bail!("throttling.min_requests_per_second must be positive");
+        }
+        Ok(())
+    }
+
+    fn max_queue_wait(&self) -> Duration {
+        Duration::from_secs(self.max_queue_wait_secs)
+    }
+}
+
+/// What the limiter has learned about one model in one region.
+#[derive(Debug)]
+struct ModelRate {
+    /// Requests per second sent to Bedrock; unlimited until the first
+    /// throttling error.
+    rate: Option<f64>,
+    /// The earliest time the next request may be sent.
+    next_slot: Instant,
+    /// Moving average of the time between requests.
+    mean_gap: Option<Duration>,
+    last_sent: Instant,
+    /// Requests sent before the last decrease do not decrease it again.
+    decreased_at: Option<Instant>,
+}
+
+impl ModelRate {
+    fn new(now: Instant) -> Self {
+        Self {
+            rate: None,
+            next_slot: now,
+            mean_gap: None,
+            last_sent: now,
+            decreased_at: None,
+        }
+    }
+
+    fn observe(&mut self, sent_at: Instant) {
+        if sent_at <= self.last_sent {
+            return;
+        }
+        let gap = sent_at - self.last_sent;
+        self.last_sent = sent_at;
+        self.mean_gap = Some(match self.mean_gap {
+            None => gap,
+            Some(mean) => {
+                mean.mul_f64(1.0 - OBSERVED_RATE_WEIGHT) + gap.mul_f64(OBSERVED_RATE_WEIGHT)
+            }
+        });
+    }
+
+    fn observed_rate(&self) -> Option<f64> {
+        self.mean_gap
+            .filter(|gap| !gap.is_zero())
+            .map(|gap| 1.0 / gap.as_secs_f64())
+    }
+
+    fn interval(&self) -> Duration {
+        self.rate
+            .map_or(Duration::ZERO, |rate| Duration::from_secs_f64(1.0 / rate))
+    }
+}
+
+/// A request's place in the schedule, reported back with its outcome.
+#[derive(Debug)]
+pub struct ThrottleTicket {
+    throttle: Option<AdaptiveThrottle>,
+    config: ThrottlingConfig,
+    region: String,
+    model: String,
+    sent_at: Instant,
+}
+
+impl ThrottleTicket {
+    /// Feeds the outcome of the Bedrock call back into the limiter. A
+    /// throttling error that got past the SDK's retries lowers the rate and
+    /// gets a `retry-after` for the next free slot.
+    pub fn record<T>(self, result: Result<T, AppError>) -> Result<T, AppError> {
+        let Some(throttle) = &self.throttle else {
+            return result;
+        };
+        match result {
+            Ok(value) => {
+                throttle.succeeded(&self);
+                Ok(value)
+            }
+            Err(error) if error.kind == ErrorKind::RateLimit => {
+                let retry_after = throttle.throttled(&self, Instant::now());
+                Err(error.with_retry_after(retry_after))
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Like [`record`](Self::record), for a request that may have fallen
+    /// back to another model. A response served by a model other than this
+    /// one means Bedrock throttled it or could not serve it, so its rate is
+    /// lowered all the same.
+    pub fn record_served<T>(
+        self,
+        result: Result<T, AppError>,
+        served_model: &str,
+    ) -> Result<T, AppError> {
+        match &self.throttle {
+            Some(throttle) if result.is_ok() && !served_model.ends_with(&self.model) => {
+                throttle.throttled(&self, Instant::now());
+                result
+            }
+            _ => self.record(result),
+        }
+    }
+}
+
+/// An additive-increase/multiplicative-decrease limiter on the rate of
+/// requests sent to each model in each region. It stays out of the way
+/// until Bedrock throttles a model, then paces that model's requests:
+/// each throttling error cuts the rate by `decrease_factor` and successful
+/// requests grow it by `additive_increase` per second. Requests wait for
+/// their slot instead of being throttled by Bedrock, for up to
+/// `max_queue_wait_secs`.
+#[derive(Clone, Debug, Default)]
+pub struct AdaptiveThrottle {
+    /// Keyed by region and model. The region is the name of the pool client
+    /// the request went to, which is the region unless configured otherwise.
+    models: Arc<Mutex<HashMap<(String, String), ModelRate>>>,
+}
+
+impl AdaptiveThrottle {
+    /// Waits for the next slot to send a request for `model` in `region`.
+    /// If that is further away than `max_queue_wait_secs`, fails at once
+    /// with a rate-limit error telling the client when to retry.
+    pub async fn acquire(
+        &self,
+        config: &ThrottlingConfig,
+        region: &str,
+        model: &str,
+    ) -> Result<ThrottleTicket, AppError> {
+        let now = Instant::now();
+        let slot = if config.enabled {
+            self.reserve(config, region, model, now)?
+        } else {
+            now
+        };
+        tokio::time::sleep_until(slot).await;
+        Ok(ThrottleTicket {
+            throttle: config.enabled.then(|| self.clone()),
+            config: config.clone(),
+            region: region.to_string(),
+            model: model.to_string(),
+            sent_at: slot,
+        })
+    }
+
+    fn reserve(
+        &self,
+        config: &ThrottlingConfig,
+        region: &str,
+        model: &str,
+        now: Instant,
+    ) -> Result<Instant, AppError> {
+        let mut models = self.models.lock().unwrap();
+        let Some(state) = models
+            .get_mut(&(region.to_string(), model.to_string()))
+            .filter(|state| state.rate.is_some())
+        else {
+            return Ok(now);
+        };
+        let slot = state.next_slot.max(now);
+        let wait = slot - now;
+        if wait > config.max_queue_wait() {
+            drop(models);
+            warn!(
+                "Throttling {} in {}: the next slot is {:?} away, beyond the maximum queue wait",
+                model, region, wait
+            );
+            record_throttled_request(region, model, "queue_wait");
+            return Err(AppError::new(
+                ErrorKind::RateLimit,
+                format!(
+                    "Bedrock is throttling requests for this model. Please retry after {} seconds.",
+                    wait.as_secs_f64().ceil()
+                ),
+            )
+            .with_retry_after(wait));
+        }
+        state.next_slot = slot + state.interval();
+        Ok(slot)
+    }
+
+    /// The model's state, created on the first answer from Bedrock so names
+    /// no model answers to are never tracked.
+    fn with_model<R>(&self, ticket: &ThrottleTicket, f: impl FnOnce(&mut ModelRate) -> R) -> R {
+        let mut models = self.models.lock().unwrap();
+        let model = models
+            .entry((ticket.region.clone(), ticket.model.clone()))
+            .or_insert_with(|| ModelRate::new(ticket.sent_at));
+        model.observe(ticket.sent_at);
+        f(model)
+    }
+
+    fn succeeded(&self, ticket: &ThrottleTicket) {
+        self.with_model(ticket, |model| {
+            if let Some(rate) = model.rate.as_mut() {
+                // One success arrives every 1 / rate seconds at full pace.
+                *rate += ticket.config.additive_increase / *rate;
+                record_throttle_rate(&ticket.region, &ticket.model, *rate);
+            }
+        });
+    }
+
+    /// Lowers the rate, unless the request was sent before the last
+    /// decrease, and returns the time until the next free slot.
+    fn throttled(&self, ticket: &ThrottleTicket, now: Instant) -> Duration {
+        record_throttled_request(&ticket.region, &ticket.model, "bedrock");
+        self.with_model(ticket, |model| {
+            let stale = model
+                .decreased_at
+                .is_some_and(|decreased_at| ticket.sent_at < decreased_at);
+            if !stale {
+                let current = [model.rate, model.observed_rate()]
+                    .into_iter()
+                    .flatten()
+                    .reduce(f64::min)
+                    .unwrap_or(INITIAL_RATE);
+                let rate = (current * ticket.config.decrease_factor)
+                    .max(ticket.config.min_requests_per_second);
+                info!(
+                    "Bedrock throttled {} in {}; pacing it at {:.3} requests per second",
+                    ticket.model, ticket.region, rate
+                );
+                model.rate = Some(rate);
+                model.decreased_at = Some(now);
+                model.next_slot = model.next_slot.max(now + model.interval());
+                record_throttle_rate(&ticket.region, &ticket.model, rate);
+            }
+            model
+                .next_slot
+                .saturating_duration_since(now)
+                .max(model.interval())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGION: &str = "us-east-1";
+    const MODEL: &str = "us.anthropic.claude";
+
+    fn ticket(throttle: &AdaptiveThrottle, sent_at: Instant) -> ThrottleTicket {
+        ThrottleTicket {
+            throttle: Some(throttle.clone()),
+            config: ThrottlingConfig::default(),
+            region: REGION.to_string(),
+            model: MODEL.to_string(),
+            sent_at,
+        }
+    }
+
+    fn rate(throttle: &AdaptiveThrottle) -> Option<f64> {
+        throttle.models.lock().unwrap()[&(REGION.to_string(), MODEL.to_string())].rate
+    }
+
+    #[test]
+    fn throttling_halves_observed_rate_once_per_wave() {
+        let throttle = AdaptiveThrottle::default();
+        let start = Instant::now();
+        // Four requests a second succeed without pacing.
+        for i in 0..20 {
+            throttle.succeeded(&ticket(&throttle, start + Duration::from_millis(250 * i)));
+        }
+        assert_eq!(rate(&throttle), None);
+
+        let now = start + Duration::from_secs(5);
+        let retry_after = throttle.throttled(&ticket(&throttle, now), now);
+        assert!((rate(&throttle).unwrap() - 2.0).abs() < 0.01);
+        assert_eq!(retry_after, Duration::from_millis(500));
+
+        // Requests sent before the decrease do not lower the rate again.
+        throttle.throttled(&ticket(&throttle, now - Duration::from_millis(1)), now);
+        assert!((rate(&throttle).unwrap() - 2.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn slots_are_spaced_by_the_rate_and_capped_by_max_wait() {
+        let throttle = AdaptiveThrottle::default();
+        let config = ThrottlingConfig {
+            max_queue_wait_secs: 4,
+            ..Default::default()
+        };
+        let now = Instant::now();
+        assert_eq!(throttle.reserve(&config, REGION, MODEL, now).unwrap(), now);
+
+        throttle.throttled(&ticket(&throttle, now), now);
+        assert_eq!(rate(&throttle), Some(INITIAL_RATE * 0.5));
+        let first = throttle.reserve(&config, REGION, MODEL, now).unwrap();
+        let second = throttle.reserve(&config, REGION, MODEL, now).unwrap();
+        assert_eq!(first, now + Duration::from_secs(2));
+        assert_eq!(second - first, Duration::from_secs(2));
+        let error = throttle.reserve(&config, REGION, MODEL, now).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::RateLimit);
+        assert_eq!(error.retry_after, Some(Duration::from_secs(6)));
+
+        // Other models are unaffected.
+        assert_eq!(
+            throttle
+                .reserve(&config, REGION, "us.anthropic.other", now)
+                .unwrap(),
+            now
+        );
+    }
+
+    #[test]
+    fn successes_grow_the_rate_additively() {
+        let throttle = AdaptiveThrottle::default();
+        let now = Instant::now();
+        throttle.throttled(&ticket(&throttle, now), now);
+        throttle.succeeded(&ticket(&throttle, now + Duration::from_secs(2)));
+        // 0.1 requests per second per second, at one success every 2s.
+        assert!((rate(&throttle).unwrap() - 0.7).abs() < 1e-9);
+    }
+
+    #[test]
+    fn invalid_factors_are_rejected() {
+        for config in [
+            ThrottlingConfig {
+                decrease_factor: 1.0,
+                ..Default::default()
+            },
+            ThrottlingConfig {
+                additive_increase: 0.0,
+                ..Default::default()
+            },
+            ThrottlingConfig {
+                min_requests_per_second: f64::NAN,
+                ..Default::default()
+            },
+        ] {
+            assert!(config.validate().is_err());
+        }
+        assert!(ThrottlingConfig::default().validate().is_ok());
+    }
+}
@@ -0,0 +1,260 @@
+use anyhow::Context;
+use arc_swap::ArcSwap;
+use axum::{
+    extract::connect_info::Connected,
+    serve::{IncomingStream, Listener},
+};
+use rustls::{
+    RootCertStore, ServerConfig,
+    crypto::{CryptoProvider, aws_lc_rs},
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    server::WebPkiClientVerifier,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    signal::unix::{SignalKind, signal},
+    sync::mpsc,
+};
+use tokio_rustls::{TlsAcceptor, server::TlsStream};
+use tracing::{error, info, warn};
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use crate::settings::modified;
+
+/// Connections that have not completed the handshake by then are dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Handshaken connections waiting to be served.
+const ACCEPT_BACKLOG: usize = 128;
+/// How often the certificate files' modification times are checked.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The `[tls]` table of `config.toml`. When present the server speaks HTTPS
+/// only. With `client_ca_path`, clients are asked for a certificate issued by
+/// one of those CAs; `require_client_cert = false` still admits clients that
+/// present none, which then authenticate with an API key.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first.
+    pub cert_path: PathBuf,
+    /// PEM private key (PKCS#8, PKCS#1 or SEC1).
+    pub key_path: PathBuf,
+    /// PEM CA certificates client certificates must chain to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ca_path: Option<PathBuf>,
+    #[serde(default = "default_require_client_cert")]
+    pub require_client_cert: bool,
+}
+
+fn default_require_client_cert() -> bool {
+    true
+}
+
+impl TlsConfig {
+    fn paths(&self) -> impl Iterator<Item = &Path> {
+        [Some(&self.cert_path), Some(&self.key_path)]
+            .into_iter()
+            .chain([self.client_ca_path.as_ref()])
+            .flatten()
+            .map(PathBuf::as_path)
+    }
+
+    /// Reads the certificate, key and client CAs into a rustls config.
+    pub fn server_config(&self) -> anyhow::Result<ServerConfig> {
+        let provider = Arc::new(aws_lc_rs::default_provider());
+        let certs = read_certs(&self.cert_path)?;
+        let key = PrivateKeyDer::from_pem_file(&self.key_path).with_context(|| {
+            format!(
+                "failed to read a private key from {}",
+                self.key_path.display()
+            )
+        })?;
+
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+        let builder = match &self.client_ca_path {
+            None => builder.with_no_client_auth(),
+            Some(path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in read_certs(path)? {
+                    roots
+                        .add(cert)
+                        .with_context(|| format!("invalid CA certificate in {}", path.display()))?;
+                }
+                let verifier = client_verifier(roots, provider, self.require_client_cert)?;
+                builder.with_client_cert_verifier(verifier)
+            }
+        };
+        let mut config = builder.with_single_cert(certs, key).with_context(|| {
+            format!(
+                "{} does not match {}",
+                self.key_path.display(),
+                self.cert_path.display()
+            )
+        })?;
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(config)
+    }
+}
+
+fn read_certs(path: &Path) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+        .with_context(|| format!("failed to read certificates from {}", path.display()))?;
+    anyhow::ensure!(!certs.is_empty(), "no certificates in {}", path.display());
+    Ok(certs)
+}
+
+fn client_verifier(
+    roots: RootCertStore,
+    provider: Arc<CryptoProvider>,
+    required: bool,
+) -> anyhow::Result<Arc<dyn rustls::server::danger::ClientCertVerifier>> {
+    let builder = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+    let builder = if required {
+        builder
+    } else {
+        builder.allow_unauthenticated()
+    };
+    Ok(builder.build()?)
+}
+
+/// Connection details of an HTTPS client, available to handlers and
+/// middleware as `ConnectInfo<TlsConnectInfo>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TlsConnectInfo {
+    pub remote_addr: SocketAddr,
+    /// The verified client certificate's subject, e.g. `O=Acme, CN=ci`.
+    pub client_subject: Option<String>,
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for TlsConnectInfo {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        let (_, connection) = stream.io().get_ref();
+        Self {
+            remote_addr: *stream.remote_addr(),
+            client_subject: connection
+                .peer_certificates()
+                .and_then(<[_]>::first)
+                .and_then(certificate_subject),
+        }
+    }
+}
+
+fn certificate_subject(cert: &CertificateDer) -> Option<String> {
+    X509Certificate::from_der(cert)
+        .ok()
+        .map(|(_, cert)| cert.subject().to_string())
+}
+
+/// A listener that terminates TLS. Handshakes run concurrently, so a slow
+/// client cannot hold up the others; each uses the config current when the
+/// connection was accepted.
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    connections: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    pub fn new(listener: TcpListener, config: Arc<ArcSwap<ServerConfig>>) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (connections_tx, connections) = mpsc::channel(ACCEPT_BACKLOG);
+        tokio::spawn(accept_connections(listener, config, connections_tx));
+        Ok(Self {
+            local_addr,
+            connections,
+        })
+    }
+}
+
+/// Accepts and handshakes connections until the [`TlsListener`] is dropped.
+async fn accept_connections(
+    listener: TcpListener,
+    config: Arc<ArcSwap<ServerConfig>>,
+    connections_tx: mpsc::Sender<(TlsStream<TcpStream>, SocketAddr)>,
+) {
+    loop {
+        let (stream, remote_addr) = tokio::select! {
+            () = connections_tx.closed() => return,
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // Usually out of file descriptors; give connections time to close.
+                    error!("Failed to accept a connection: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+        };
+        let acceptor = TlsAcceptor::from(config.load_full());
+        let connections_tx = connections_tx.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => {
+                    let _ = connections_tx.send((stream, remote_addr)).await;
+                }
+                Ok(Err(e)) => warn!("TLS handshake with {} failed: {}", remote_addr, e),
+                Err(_) => warn!("TLS handshake with {} timed out", remote_addr),
+            }
+        });
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        // The accept task outlives the receiver, so this only fails if it
+        // panicked.
+        self.connections
+            .recv()
+            .await
+            .expect("TLS accept task stopped")
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// Reloads the certificate, key and client CAs on SIGHUP and whenever one of
+/// the files changes; new connections use the new config. A config that
+/// fails to load is logged and the current one kept. Runs until the process
+/// exits.
+pub async fn watch_certificates(
+    tls: TlsConfig,
+    config: Arc<ArcSwap<ServerConfig>>,
+) -> anyhow::Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    let modified = || tls.paths().map(modified).collect::<Vec<_>>();
+    let mut last_modified = modified();
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => info!("Received SIGHUP, reloading TLS certificates"),
+            _ = interval.tick() => {
+                if modified() == last_modified {
+                    continue;
+                }
+                info!("TLS certificate files changed, reloading");
+            }
+        }
+        last_modified = modified();
+        match tls.server_config() {
+            Ok(server_config) => {
+                config.store(Arc::new(server_config));
+                info!("Reloaded TLS certificate from {}", tls.cert_path.display());
+            }
+            Err(e) => error!("Keeping the current TLS certificate: {:?}", e),
+        }
+    }
+}
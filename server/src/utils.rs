@@ -1,5 +1,58 @@
-use aws_sdk_bedrockruntime::types::TokenUsage;
-use tracing::info;
+use aws_sdk_bedrockruntime::types::{StopReason, TokenUsage};
+use axum::{extract::Request, middleware::Next, response::Response};
+use chat::usage::{UsageEvent, UsageReporter};
+use chrono::Utc;
+use std::sync::{Arc, Mutex};
+use tracing::{Span, info};
+
+use crate::{
+    AppState,
+    auth::Principal,
+    error::{AppError, Protocol},
+    ledger::UsageRecord,
+    metrics::record_tokens,
+    request_id::RequestId,
+    telemetry::record_chat_usage,
+};
+
+/// Matches `value` against `pattern`, which is either exact or contains a
+/// single `*` wildcard, and returns the text the wildcard matched (empty for
+/// an exact match).
+pub fn wildcard_capture<'a>(pattern: &str, value: &'a str) -> Option<&'a str> {
+    match pattern.split_once('*') {
+        None => (pattern == value).then_some(""),
+        Some((prefix, suffix)) => value.strip_prefix(prefix)?.strip_suffix(suffix),
+    }
+}
+
+/// Matches `value` against a glob `pattern` in which each `*` matches any
+/// run of characters, e.g. `*anthropic.claude-opus-*`.
+pub fn glob_match(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// The AWS region `client` sends requests to.
+pub fn client_region(client: &aws_sdk_bedrockruntime::Client) -> &str {
+    client
+        .config()
+        .region()
+        .map_or("", |region| region.as_ref())
+}
 
 pub fn log_token_usage(usage: &TokenUsage) {
     let mut usage_message = format!(
@@ -14,3 +67,129 @@ pub fn log_token_usage(usage: &TokenUsage) {
     }
     info!("{}", usage_message);
 }
+
+/// Accounts for a finished request: debits its token usage from the
+/// caller's rate limits and writes it, priced, to the usage ledger.
+pub fn record_usage(state: &AppState, event: &UsageEvent) {
+    info!(
+        "Request {} finished: bedrock_request_id: {:?}, principal: {}, model: {}, status: {}, latency: {:?}, stop_reason: {:?}",
+        event.request_id,
+        event.bedrock_request_id,
+        event.principal,
+        event.model,
+        event.status.as_u16(),
+        event.latency,
+        event.stop_reason.as_ref().map(StopReason::as_str)
+    );
+    if let Some(usage) = &event.usage {
+        log_token_usage(usage);
+        record_tokens(&event.model, usage);
+        state.rate_limiter.record_usage(&event.principal, usage);
+    }
+
+    let usage = event.usage.as_ref();
+    let tokens = |count: Option<i32>| i64::from(count.unwrap_or(0));
+    let mut record = UsageRecord {
+        timestamp: Utc::now(),
+        request_id: event.request_id.clone(),
+        bedrock_request_id: event.bedrock_request_id.clone(),
+        principal: event.principal.clone(),
+        model: event.model.clone(),
+        status: event.status.as_u16(),
+        error: event.error.map(|kind| kind.anthropic_type().to_string()),
+        rejected: event.rejected,
+        stop_reason: event.stop_reason.as_ref().map(|r| r.as_str().to_string()),
+        input_tokens: tokens(usage.map(|u| u.input_tokens)),
+        output_tokens: tokens(usage.map(|u| u.output_tokens)),
+        cache_read_input_tokens: tokens(usage.and_then(|u| u.cache_read_input_tokens)),
+        cache_write_input_tokens: tokens(usage.and_then(|u| u.cache_write_input_tokens)),
+        latency_ms: event.latency.as_millis() as i64,
+        cost_usd: 0.0,
+    };
+    record.cost_usd = state.settings.load().prices.cost(&record);
+    state.ledger.record(record);
+}
+
+/// The [`UsageReporter`] for request `request_id` from `principal`,
+/// reporting to [`record_usage`]. `model` is the Bedrock model ID
+/// `requested_model` resolved to.
+pub fn usage_reporter(
+    state: &Arc<AppState>,
+    request_id: &RequestId,
+    principal: &Principal,
+    requested_model: &str,
+    model: &str,
+) -> UsageReporter {
+    let state = state.clone();
+    let span = Span::current();
+    let reporter = UsageReporter::new(
+        Arc::new(move |event: &UsageEvent| {
+            record_usage(&state, event);
+            record_chat_usage(&span, event);
+        }),
+        request_id.to_string(),
+        principal.name.clone(),
+        requested_model.to_string(),
+    );
+    reporter.set_model(model);
+    reporter
+}
+
+/// A refusal by a middleware that runs before the body is read, such as for
+/// a rate limit or budget. The handler takes it once it has resolved the
+/// model, so the usage ledger records the refusal against that model.
+#[derive(Clone, Debug)]
+pub struct PendingRejection(Arc<Mutex<Option<AppError>>>);
+
+impl PendingRejection {
+    pub fn take(&self) -> Option<AppError> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+/// Refuses `request` from `principal` with `error` through its handler (see
+/// [`PendingRejection`]). A handler that gives up before taking the refusal,
+/// such as on a malformed body, leaves it to be recorded without a model.
+pub async fn reject(
+    state: &Arc<AppState>,
+    mut request: Request,
+    next: Next,
+    principal: &Principal,
+    error: AppError,
+) -> Response {
+    let protocol = Protocol::from_path(request.uri().path());
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .cloned()
+        .unwrap_or_else(RequestId::generate);
+    let rejection = PendingRejection(Arc::new(Mutex::new(Some(error))));
+    request.extensions_mut().insert(rejection.clone());
+    let response = next.run(request).await;
+    match rejection.take() {
+        Some(error) => {
+            usage_reporter(state, &request_id, principal, "", "").reject(error.status, error.kind);
+            error.into_response_for(protocol)
+        }
+        None => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_matches_any_number_of_wildcards() {
+        assert!(glob_match(
+            "*claude-opus*",
+            "global.anthropic.claude-opus-4-6-v1"
+        ));
+        assert!(glob_match("us.*", "us.anthropic.claude"));
+        assert!(glob_match("exact", "exact"));
+        assert!(glob_match("a*b*c", "abc"));
+        assert!(!glob_match("a*b*c", "acb"));
+        assert!(!glob_match("ab*ba", "aba"));
+        assert!(!glob_match("exact", "exactly"));
+    }
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use aws_smithy_mocks::{RuleMode, mock_client};
+use axum::body::Body;
+use http_body_util::BodyExt;
+use server::{auth::ApiKeys, get_app, settings::Settings};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn build_app() -> axum::Router {
+    let client = mock_client!(aws_sdk_bedrockruntime, RuleMode::Sequential, []);
+    let state = Arc::new(common::app_state(
+        client,
+        Settings {
+            api_keys: ApiKeys::new(vec![common::api_key("alice")]).unwrap(),
+            ..Default::default()
+        },
+    ));
+    get_app(state)
+}
+
+async fn get(uri: &str, header: Option<(&str, &str)>) -> (u16, serde_json::Value) {
+    let mut request = axum::http::Request::builder().uri(uri);
+    if let Some((name, value)) = header {
+        request = request.header(name, value);
+    }
+    let response = build_app()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status().as_u16();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn missing_key_is_rejected_with_anthropic_envelope() {
+    let (status, json) = get("/v1/models", None).await;
+    assert_eq!(status, 401);
+    assert_eq!(json["type"], "error");
+    assert_eq!(json["error"]["type"], "authentication_error");
+}
+
+#[tokio::test]
+async fn invalid_bearer_token_is_rejected_with_openai_envelope() {
+    let (status, json) = get("/models", Some(("authorization", "Bearer sk-mallory"))).await;
+    assert_eq!(status, 401);
+    assert_eq!(json["error"]["type"], "authentication_error");
+    assert_eq!(json["error"]["code"], "invalid_api_key");
+}
+
+#[tokio::test]
+async fn valid_key_is_accepted_in_either_header() {
+    let (status, _) = get("/v1/models", Some(("x-api-key", "sk-alice"))).await;
+    assert_eq!(status, 200);
+
+    let (status, _) = get("/models", Some(("authorization", "Bearer sk-alice"))).await;
+    assert_eq!(status, 200);
+}
@@ -0,0 +1,123 @@
+mod common;
+
+use aws_sdk_bedrockruntime::config::retry::RetryConfig;
+use aws_smithy_mocks::{RuleMode, mock, mock_client};
+use aws_smithy_runtime_api::http::{Response as HttpResponse, StatusCode as HttpStatusCode};
+use aws_smithy_types::body::SdkBody;
+use axum::body::Body;
+use server::{
+    bedrock_pool::{BedrockPool, BedrockPoolConfig, RoutingStrategy},
+    get_app,
+    settings::Settings,
+};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const MODEL: &str = "us.anthropic.claude-pool-test";
+
+fn bedrock_error(status: u16, error_type: &str) -> HttpResponse {
+    let mut response = HttpResponse::new(
+        HttpStatusCode::try_from(status).unwrap(),
+        SdkBody::from(r#"{"message":"Try again later."}"#),
+    );
+    response
+        .headers_mut()
+        .insert("x-amzn-errortype", error_type.to_string());
+    response
+}
+
+fn build_app_with_pool(bedrock: BedrockPool) -> axum::Router {
+    let state = Arc::new(common::app_state(bedrock, Settings::default()));
+    get_app(state)
+}
+
+fn post(uri: &str) -> axum::http::Request<Body> {
+    let body = serde_json::json!({
+        "model": MODEL,
+        "max_tokens": 16,
+        "messages": [{"role": "user", "content": "hi"}]
+    });
+    axum::http::Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn requests_are_spread_across_clients() {
+    let east = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .then_output(|| common::converse_output(30, 7));
+    let west = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .then_output(|| common::converse_output(30, 7));
+    let pool = BedrockPool::with_clients(
+        &BedrockPoolConfig {
+            routing: RoutingStrategy::RoundRobin,
+            ..Default::default()
+        },
+        vec![
+            (
+                "us-east-1".to_string(),
+                mock_client!(aws_sdk_bedrockruntime, RuleMode::MatchAny, [&east]),
+                1,
+            ),
+            (
+                "us-west-2".to_string(),
+                mock_client!(aws_sdk_bedrockruntime, RuleMode::MatchAny, [&west]),
+                1,
+            ),
+        ],
+    );
+    let app = build_app_with_pool(pool);
+
+    for uri in ["/v1/messages", "/chat/completions", "/v1/messages"] {
+        let response = app.clone().oneshot(post(uri)).await.unwrap();
+        assert_eq!(response.status(), 200);
+    }
+    assert_eq!(east.num_calls(), 2);
+    assert_eq!(west.num_calls(), 1);
+}
+
+#[tokio::test]
+async fn failing_client_is_taken_out_of_rotation() {
+    let east = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .then_http_response(|| bedrock_error(503, "ServiceUnavailableException"));
+    let west = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .then_output(|| common::converse_output(30, 7));
+    let pool = BedrockPool::with_clients(
+        &BedrockPoolConfig {
+            routing: RoutingStrategy::LeastOutstanding,
+            unhealthy_after: 1,
+            ..Default::default()
+        },
+        vec![
+            (
+                "us-east-1".to_string(),
+                mock_client!(
+                    aws_sdk_bedrockruntime,
+                    RuleMode::MatchAny,
+                    [&east],
+                    |builder| builder.retry_config(RetryConfig::disabled())
+                ),
+                1,
+            ),
+            (
+                "us-west-2".to_string(),
+                mock_client!(aws_sdk_bedrockruntime, RuleMode::MatchAny, [&west]),
+                1,
+            ),
+        ],
+    );
+    let app = build_app_with_pool(pool);
+
+    let response = app.clone().oneshot(post("/v1/messages")).await.unwrap();
+    assert_eq!(response.status(), 503);
+
+    for _ in 0..3 {
+        let response = app.clone().oneshot(post("/v1/messages")).await.unwrap();
+        assert_eq!(response.status(), 200);
+    }
+    assert_eq!(east.num_calls(), 1);
+    assert_eq!(west.num_calls(), 3);
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use aws_sdk_bedrockruntime::{Client, config::retry::RetryConfig};
+use aws_smithy_mocks::{RuleMode, mock, mock_client};
+use axum::body::Body;
+use http_body_util::BodyExt;
+use server::{
+    auth::{ApiKeyConfig, ApiKeys},
+    budgets::{BUDGET_WARNING, BudgetConfig, BudgetPeriod, BudgetUnit, Budgets},
+    get_app,
+    settings::Settings,
+};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const HAIKU: &str = "us.anthropic.claude-haiku-budget-test";
+
+/// Every request uses 37 tokens; `ci` may use 30 a day before a warning
+/// and 70 before it is cut off.
+fn build_app_with_client(client: Client) -> axum::Router {
+    let state = Arc::new(common::app_state(
+        client,
+        Settings {
+            api_keys: ApiKeys::new(vec![
+                common::api_key("ci"),
+                ApiKeyConfig {
+                    admin: true,
+                    ..common::api_key("ops")
+                },
+            ])
+            .unwrap(),
+            budgets: Budgets::new(vec![BudgetConfig {
+                key: "ci".to_string(),
+                period: BudgetPeriod::Day,
+                unit: BudgetUnit::Tokens,
+                soft_limit: Some(30.0),
+                hard_limit: Some(70.0),
+            }])
+            .unwrap(),
+            ..Default::default()
+        },
+    ));
+    get_app(state)
+}
+
+fn post(uri: &str, key: &str) -> axum::http::Request<Body> {
+    let body = serde_json::json!({
+        "model": HAIKU,
+        "max_tokens": 16,
+        "messages": [{"role": "user", "content": "hi"}]
+    });
+    axum::http::Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "application/json")
+        .header("x-api-key", key)
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap()
+}
+
+async fn response_json(response: axum::response::Response) -> serde_json::Value {
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn budgets_warn_then_reject_until_reset() {
+    let converse = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .then_output(|| common::converse_output(30, 7));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::MatchAny,
+        [&converse],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let app = build_app_with_client(client);
+
+    let response = app
+        .clone()
+        .oneshot(post("/v1/messages", "sk-ci"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().get(BUDGET_WARNING).is_none());
+
+    let response = app
+        .clone()
+        .oneshot(post("/chat/completions", "sk-ci"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers()[BUDGET_WARNING],
+        "37 tokens of the daily 30 tokens budget used"
+    );
+
+    for uri in ["/v1/messages", "/chat/completions"] {
+        let response = app.clone().oneshot(post(uri, "sk-ci")).await.unwrap();
+        assert_eq!(response.status(), 429, "{uri}");
+        assert!(response.headers().contains_key("retry-after"), "{uri}");
+        let json = response_json(response).await;
+        assert_eq!(json["error"]["type"], "rate_limit_error", "{uri}");
+        assert_eq!(json.get("type").is_some(), uri == "/v1/messages", "{uri}");
+    }
+    // Other keys have no budget.
+    let response = app
+        .clone()
+        .oneshot(post("/v1/messages", "sk-ops"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(converse.num_calls(), 3);
+
+    // The refusals are in the ledger against their model, without adding
+    // to the spend.
+    let usage = common::usage_after(&app, "?key=ci&group_by=model", 4).await;
+    let row = &usage["data"][0];
+    assert_eq!(row["model"], HAIKU);
+    assert_eq!(row["requests"], 4);
+    assert_eq!(row["failed_requests"], 2);
+    assert_eq!(row["rejected_requests"], 2);
+
+    let request = axum::http::Request::builder()
+        .uri("/admin/budgets?key=ci")
+        .header("x-api-key", "sk-ops")
+        .body(Body::empty())
+        .unwrap();
+    let json = response_json(app.clone().oneshot(request).await.unwrap()).await;
+    assert_eq!(json["data"][0]["key"], "ci");
+    assert_eq!(json["data"][0]["period"], "day");
+    assert_eq!(json["data"][0]["spent"], 74.0);
+    assert_eq!(json["data"][0]["status"], "hard_limit");
+
+    let reset = |key: &str| {
+        axum::http::Request::builder()
+            .method("POST")
+            .uri("/admin/budgets/reset")
+            .header("content-type", "application/json")
+            .header("x-api-key", key)
+            .body(Body::from(r#"{"key": "ci", "period": "day"}"#))
+            .unwrap()
+    };
+    let response = app.clone().oneshot(reset("sk-ci")).await.unwrap();
+    assert_eq!(response.status(), 403);
+    let response = app.clone().oneshot(reset("sk-ops")).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let json = response_json(response).await;
+    assert_eq!(json["data"][0]["spent"], 0.0);
+    assert_eq!(json["data"][0]["status"], "ok");
+
+    let response = app.oneshot(post("/v1/messages", "sk-ci")).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(converse.num_calls(), 4);
+}
@@ -0,0 +1,184 @@
+mod common;
+
+use aws_sdk_bedrockruntime::{
+    Client,
+    config::retry::RetryConfig,
+    types::{ContentBlock, StopReason, ToolUseBlock},
+};
+use aws_smithy_mocks::{RuleMode, mock, mock_client};
+use aws_smithy_runtime_api::http::{Response as HttpResponse, StatusCode as HttpStatusCode};
+use aws_smithy_types::body::SdkBody;
+use axum::body::Body;
+use chat::inference_profile::InferenceProfileResolver;
+use http_body_util::BodyExt;
+use server::{get_app, settings::Settings};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn build_app_with_client(client: Client) -> axum::Router {
+    let prefixes = vec!["us.".to_string(), "global.".to_string()];
+    let state = Arc::new(common::app_state(
+        client,
+        Settings {
+            inference_profiles: Arc::new(InferenceProfileResolver::new(prefixes)),
+            ..Default::default()
+        },
+    ));
+    get_app(state)
+}
+
+fn post_chat_completions(body: serde_json::Value) -> axum::http::Request<Body> {
+    axum::http::Request::builder()
+        .method("POST")
+        .uri("/chat/completions")
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap()
+}
+
+async fn response_json(response: axum::response::Response) -> serde_json::Value {
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .expect("failed to collect response body")
+        .to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn chat_completions_non_stream_returns_chat_completion_object() {
+    let converse_rule = mock!(aws_sdk_bedrockruntime::Client::converse).then_output(|| {
+        common::converse_reply(
+            vec![ContentBlock::Text("hi".to_string())],
+            StopReason::EndTurn,
+            common::token_usage(12, 3),
+        )
+    });
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        [&converse_rule]
+    );
+
+    let response = build_app_with_client(client)
+        .oneshot(post_chat_completions(serde_json::json!({
+            "model": "global.anthropic.claude-opus-4-8",
+            "stream": false,
+            "messages": [{"role": "user", "content": "hi"}]
+        })))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let json = response_json(response).await;
+    assert_eq!(json["object"], "chat.completion");
+    assert_eq!(json["model"], "global.anthropic.claude-opus-4-8");
+    assert!(json["id"].as_str().unwrap().starts_with("chatcmpl-"));
+    assert_eq!(json["choices"][0]["message"]["role"], "assistant");
+    assert_eq!(json["choices"][0]["message"]["content"], "hi");
+    assert_eq!(json["choices"][0]["finish_reason"], "stop");
+    assert_eq!(json["usage"]["prompt_tokens"], 12);
+    assert_eq!(json["usage"]["completion_tokens"], 3);
+    assert_eq!(json["usage"]["total_tokens"], 15);
+    assert_eq!(converse_rule.num_calls(), 1);
+}
+
+/// OpenAI's default is non-streaming, so a request without `stream` must not
+/// receive an SSE body.
+#[tokio::test]
+async fn chat_completions_without_stream_field_returns_tool_calls() {
+    let converse_rule = mock!(aws_sdk_bedrockruntime::Client::converse).then_output(|| {
+        common::converse_reply(
+            vec![ContentBlock::ToolUse(
+                ToolUseBlock::builder()
+                    .tool_use_id("call_1")
+                    .name("get_weather")
+                    .input(::common::value_to_document(
+                        &serde_json::json!({"city": "NYC"}),
+                    ))
+                    .build()
+                    .expect("tool use"),
+            )],
+            StopReason::ToolUse,
+            common::token_usage(12, 3),
+        )
+    });
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        [&converse_rule]
+    );
+
+    let response = build_app_with_client(client)
+        .oneshot(post_chat_completions(serde_json::json!({
+            "model": "global.anthropic.claude-opus-4-8",
+            "tools": [{"type": "function", "function": {"name": "get_weather", "parameters": {"type": "object"}}}],
+            "messages": [{"role": "user", "content": "Weather in NYC?"}]
+        })))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let json = response_json(response).await;
+    let tool_call = &json["choices"][0]["message"]["tool_calls"][0];
+    assert_eq!(json["choices"][0]["finish_reason"], "tool_calls");
+    assert_eq!(tool_call["id"], "call_1");
+    assert_eq!(tool_call["function"]["name"], "get_weather");
+    assert_eq!(tool_call["function"]["arguments"], r#"{"city":"NYC"}"#);
+}
+
+/// Bedrock throttling surfaces as a 429 with an OpenAI error envelope so
+/// OpenAI SDKs classify it as a `RateLimitError`.
+#[tokio::test]
+async fn chat_completions_throttling_returns_openai_error_envelope() {
+    let converse_rule = mock!(aws_sdk_bedrockruntime::Client::converse).then_http_response(|| {
+        let mut response = HttpResponse::new(
+            HttpStatusCode::try_from(429).unwrap(),
+            SdkBody::from(r#"{"message":"Too many requests, please wait before trying again."}"#),
+        );
+        response
+            .headers_mut()
+            .insert("x-amzn-errortype", "ThrottlingException");
+        response
+    });
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        [&converse_rule],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+
+    let response = build_app_with_client(client)
+        .oneshot(post_chat_completions(serde_json::json!({
+            "model": "global.anthropic.claude-opus-4-8",
+            "messages": [{"role": "user", "content": "hi"}]
+        })))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 429);
+
+    let json = response_json(response).await;
+    assert_eq!(json["error"]["type"], "rate_limit_error");
+    assert_eq!(json["error"]["code"], "rate_limit_exceeded");
+    assert_eq!(
+        json["error"]["message"],
+        "Too many requests, please wait before trying again."
+    );
+}
+
+#[tokio::test]
+async fn chat_completions_malformed_body_returns_openai_error_envelope() {
+    let client = mock_client!(aws_sdk_bedrockruntime, RuleMode::Sequential, []);
+
+    let response = build_app_with_client(client)
+        .oneshot(post_chat_completions(serde_json::json!({
+            "messages": [{"role": "user", "content": "hi"}]
+        })))
+        .await
+        .unwrap();
+    assert!(response.status().is_client_error());
+
+    let json = response_json(response).await;
+    assert_eq!(json["error"]["type"], "invalid_request_error");
+}
@@ -0,0 +1,129 @@
+//! Fixtures shared by the integration tests.
+
+// Each test binary compiles this module but only uses some of it.
+#![allow(dead_code)]
+
+use arc_swap::ArcSwap;
+use aws_sdk_bedrockruntime::{
+    operation::converse::ConverseOutput as ConverseSendOutput,
+    types::{
+        ContentBlock, ConversationRole, ConverseOutput as ConverseOutputVariant,
+        Message as BedrockMessage, StopReason, TokenUsage,
+    },
+};
+use axum::body::Body;
+use http_body_util::BodyExt;
+use server::{
+    AppState,
+    auth::{ApiKeyConfig, hash_api_key},
+    bedrock_pool::BedrockPool,
+    concurrency::ConcurrencyLimiter,
+    ledger::UsageLedger,
+    rate_limit::RateLimiter,
+    response_cache::ResponseCache,
+    settings::Settings,
+    shutdown::Shutdown,
+    throttle::AdaptiveThrottle,
+};
+use std::time::Duration;
+use tower::ServiceExt;
+
+/// Builds an [`AppState`] serving `settings` through `bedrock`, with fresh
+/// limiters, an empty response cache and an in-memory usage ledger.
+pub fn app_state(bedrock: impl Into<BedrockPool>, settings: Settings) -> AppState {
+    AppState {
+        bedrock: bedrock.into(),
+        settings: ArcSwap::from_pointee(settings),
+        rate_limiter: RateLimiter::default(),
+        concurrency: ConcurrencyLimiter::default(),
+        throttle: AdaptiveThrottle::default(),
+        response_cache: ResponseCache::default(),
+        ledger: UsageLedger::open_in_memory().unwrap(),
+        shutdown: Shutdown::default(),
+    }
+}
+
+/// An API key entry named `name` whose secret is `sk-<name>`, with no limits.
+pub fn api_key(name: &str) -> ApiKeyConfig {
+    ApiKeyConfig {
+        name: name.to_string(),
+        key_hash: Some(hash_api_key(&format!("sk-{name}"))),
+        ..Default::default()
+    }
+}
+
+/// A Converse reply answering "hi" with the given token usage.
+pub fn converse_output(input_tokens: i32, output_tokens: i32) -> ConverseSendOutput {
+    converse_reply(
+        vec![ContentBlock::Text("hi".to_string())],
+        StopReason::EndTurn,
+        token_usage(input_tokens, output_tokens),
+    )
+}
+
+/// A Converse reply carrying `content` and stopping with `stop_reason`.
+pub fn converse_reply(
+    content: Vec<ContentBlock>,
+    stop_reason: StopReason,
+    usage: TokenUsage,
+) -> ConverseSendOutput {
+    ConverseSendOutput::builder()
+        .output(ConverseOutputVariant::Message(
+            BedrockMessage::builder()
+                .role(ConversationRole::Assistant)
+                .set_content(Some(content))
+                .build()
+                .expect("message"),
+        ))
+        .stop_reason(stop_reason)
+        .usage(usage)
+        .build()
+        .expect("converse output")
+}
+
+pub fn token_usage(input_tokens: i32, output_tokens: i32) -> TokenUsage {
+    TokenUsage::builder()
+        .input_tokens(input_tokens)
+        .output_tokens(output_tokens)
+        .total_tokens(input_tokens + output_tokens)
+        .build()
+        .expect("usage")
+}
+
+/// Ledger writes are asynchronous; poll `check` until it yields a value.
+pub async fn poll_ledger<T, F>(mut check: impl FnMut() -> F) -> T
+where
+    F: Future<Output = Option<T>>,
+{
+    for _ in 0..50 {
+        if let Some(value) = check().await {
+            return value;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("ledger write did not land");
+}
+
+/// Polls `/admin/usage{query}` as the `ops` key until its rows add up to
+/// `expected` requests.
+pub async fn usage_after(app: &axum::Router, query: &str, expected: i64) -> serde_json::Value {
+    poll_ledger(|| async {
+        let request = axum::http::Request::builder()
+            .uri(format!("/admin/usage{query}"))
+            .header("x-api-key", "sk-ops")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let requests: i64 = json["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| row["requests"].as_i64().unwrap())
+            .sum();
+        (requests >= expected).then_some(json)
+    })
+    .await
+}
@@ -0,0 +1,187 @@
+mod common;
+
+use aws_sdk_bedrockruntime::{Client, config::retry::RetryConfig};
+use aws_smithy_eventstream::frame::write_message_to;
+use aws_smithy_mocks::{RuleMode, mock, mock_client};
+use aws_smithy_runtime_api::http::{Response as HttpResponse, StatusCode as HttpStatusCode};
+use aws_smithy_types::{
+    body::SdkBody,
+    event_stream::{Header, HeaderValue, Message},
+};
+use axum::body::{Body, Bytes};
+use futures::StreamExt;
+use http_body::Frame;
+use http_body_util::{BodyExt, StreamBody};
+use server::{
+    concurrency::{ConcurrencyConfig, ConcurrencyLimits, ModelConcurrencyLimit},
+    get_app,
+    settings::Settings,
+};
+use std::{convert::Infallible, sync::Arc, time::Duration};
+use tower::ServiceExt;
+
+/// A `converse_stream` response that starts the message, then stays open
+/// without sending anything more, like a model still thinking.
+fn open_stream() -> HttpResponse {
+    let message_start = Message::new(&br#"{"role":"assistant"}"#[..])
+        .add_header(Header::new(
+            ":message-type",
+            HeaderValue::String("event".into()),
+        ))
+        .add_header(Header::new(
+            ":event-type",
+            HeaderValue::String("messageStart".into()),
+        ))
+        .add_header(Header::new(
+            ":content-type",
+            HeaderValue::String("application/json".into()),
+        ));
+    let mut frame = Vec::new();
+    write_message_to(&message_start, &mut frame).unwrap();
+    let body = StreamBody::new(
+        futures::stream::iter([Ok::<_, Infallible>(Frame::data(Bytes::from(frame)))])
+            .chain(futures::stream::pending()),
+    );
+    let mut response = HttpResponse::new(
+        HttpStatusCode::try_from(200).unwrap(),
+        SdkBody::from_body_1_x(body),
+    );
+    response
+        .headers_mut()
+        .insert("content-type", "application/vnd.amazon.eventstream");
+    response
+}
+
+fn build_app(concurrency: ConcurrencyConfig) -> axum::Router {
+    let converse_stream_rule =
+        mock!(aws_sdk_bedrockruntime::Client::converse_stream).then_http_response(open_stream);
+    let client: Client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::MatchAny,
+        [&converse_stream_rule],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    get_app(Arc::new(common::app_state(
+        client,
+        Settings {
+            concurrency: ConcurrencyLimits::new(concurrency).unwrap(),
+            ..Default::default()
+        },
+    )))
+}
+
+fn stream_request(uri: &str, model: &str) -> axum::http::Request<Body> {
+    axum::http::Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(
+            serde_json::to_vec(&serde_json::json!({
+                "model": model,
+                "max_tokens": 16,
+                "stream": true,
+                "messages": [{"role": "user", "content": "hi"}]
+            }))
+            .unwrap(),
+        ))
+        .unwrap()
+}
+
+async fn response_json(response: axum::response::Response) -> serde_json::Value {
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+const OPUS: &str = "anthropic.claude-opus-4-8";
+const SONNET: &str = "anthropic.claude-sonnet-4-5";
+
+#[tokio::test]
+async fn model_limit_sheds_excess_requests_with_overloaded_errors() {
+    let app = build_app(ConcurrencyConfig {
+        max_queued_requests: 0,
+        models: vec![ModelConcurrencyLimit {
+            model: "*claude-opus*".to_string(),
+            max_concurrent_requests: 1,
+        }],
+        ..Default::default()
+    });
+
+    let open = app
+        .clone()
+        .oneshot(stream_request("/v1/messages", OPUS))
+        .await
+        .unwrap();
+    assert_eq!(open.status(), 200);
+
+    let response = app
+        .clone()
+        .oneshot(stream_request("/v1/messages", OPUS))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 529);
+    let json = response_json(response).await;
+    assert_eq!(json["error"]["type"], "overloaded_error");
+
+    let response = app
+        .clone()
+        .oneshot(stream_request("/chat/completions", OPUS))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 503);
+    let json = response_json(response).await;
+    assert_eq!(json["error"]["code"], "overloaded");
+
+    // Other models are not held back.
+    let response = app
+        .clone()
+        .oneshot(stream_request("/v1/messages", SONNET))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    // The slot is held until the stream is dropped.
+    drop(open);
+    let response = app
+        .oneshot(stream_request("/v1/messages", OPUS))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn queued_request_proceeds_when_a_stream_ends() {
+    let app = build_app(ConcurrencyConfig {
+        max_concurrent_requests: Some(1),
+        max_queued_requests: 1,
+        ..Default::default()
+    });
+
+    let open = app
+        .clone()
+        .oneshot(stream_request("/v1/messages", OPUS))
+        .await
+        .unwrap();
+    assert_eq!(open.status(), 200);
+
+    let queued = tokio::spawn(
+        app.clone()
+            .oneshot(stream_request("/chat/completions", SONNET)),
+    );
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!queued.is_finished());
+
+    // The queue holds one request; the next is rejected right away.
+    let response = app
+        .oneshot(stream_request("/v1/messages", SONNET))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 529);
+
+    drop(open);
+    let response = tokio::time::timeout(Duration::from_secs(5), queued)
+        .await
+        .expect("queued request was not admitted")
+        .unwrap()
+        .unwrap();
+    assert_eq!(response.status(), 200);
+}
@@ -1,8 +1,11 @@
+mod common;
+
 use aws_config::BehaviorVersion;
 use aws_sdk_bedrockruntime::Client;
 use axum::body::Body;
 use axum::extract::DefaultBodyLimit;
-use server::{AppState, get_app};
+use chat::inference_profile::InferenceProfileResolver;
+use server::{get_app, settings::Settings};
 use std::sync::Arc;
 use tower::ServiceExt;
 
@@ -13,15 +16,19 @@ async fn build_app() -> axum::Router {
     let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
     let client = Client::new(&config);
 
-    let state = Arc::new(AppState {
-        bedrockruntime_client: client,
-        inference_profile_prefixes: vec!["us.".to_string(), "global.".to_string()],
-        anthropic_beta_whitelist: vec![
-            "context-1m-2025-08-07".to_string(),
-            "context-management-2025-06-27".to_string(),
-            "effort-2025-11-24".to_string(),
-        ],
-    });
+    let prefixes = vec!["us.".to_string(), "global.".to_string()];
+    let state = Arc::new(common::app_state(
+        client,
+        Settings {
+            inference_profiles: Arc::new(InferenceProfileResolver::new(prefixes)),
+            anthropic_beta_whitelist: vec![
+                "context-1m-2025-08-07".to_string(),
+                "context-management-2025-06-27".to_string(),
+                "effort-2025-11-24".to_string(),
+            ],
+            ..Default::default()
+        },
+    ));
 
     get_app(state)
 }
@@ -42,10 +49,10 @@ fn parse_sse_events(body: &str) -> Vec<(String, String)> {
     let mut current_data = String::new();
 
     for line in body.lines() {
-        if line.starts_with("event:") {
-            current_event = line["event:".len()..].trim().to_string();
-        } else if line.starts_with("data:") {
-            current_data = line["data:".len()..].trim().to_string();
+        if let Some(event) = line.strip_prefix("event:") {
+            current_event = event.trim().to_string();
+        } else if let Some(data) = line.strip_prefix("data:") {
+            current_data = data.trim().to_string();
         } else if line.is_empty() && (!current_event.is_empty() || !current_data.is_empty()) {
             events.push((current_event.clone(), current_data.clone()));
             current_event.clear();
@@ -1629,6 +1636,56 @@ async fn v1_messages_non_stream_returns_json_message() {
     );
 }
 
+#[tokio::test]
+#[ignore]
+async fn chat_completions_non_stream_returns_chat_completion() {
+    let app = build_app().await;
+
+    let body = serde_json::json!({
+        "model": OPUS_4_8,
+        "max_tokens": 64,
+        "stream": false,
+        "messages": [
+            {"role": "user", "content": "Say hi in exactly one word."}
+        ]
+    });
+
+    let request = axum::http::Request::builder()
+        .method("POST")
+        .uri("/chat/completions")
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), 200);
+
+    let body_str = String::from_utf8(collect_body(response.into_body()).await).unwrap();
+    let completion: serde_json::Value = serde_json::from_str(&body_str)
+        .unwrap_or_else(|e| panic!("expected JSON completion, got {body_str}: {e}"));
+
+    assert_eq!(
+        completion["object"], "chat.completion",
+        "body: {completion}"
+    );
+    assert_eq!(
+        completion["choices"][0]["message"]["role"], "assistant",
+        "body: {completion}"
+    );
+    assert!(
+        completion["choices"][0]["message"]["content"].is_string(),
+        "expected message content, got: {completion}"
+    );
+    assert_eq!(
+        completion["choices"][0]["finish_reason"], "stop",
+        "body: {completion}"
+    );
+    assert!(
+        completion["usage"]["completion_tokens"].as_i64().is_some(),
+        "expected usage.completion_tokens, got: {completion}"
+    );
+}
+
 #[tokio::test]
 #[ignore]
 async fn v1_messages_non_stream_echoes_matched_stop_sequence() {
@@ -1757,3 +1814,27 @@ async fn v1_messages_two_cache_points_in_one_array_streams() {
     );
     assert_eq!(event_types.last(), Some(&"message_stop"));
 }
+
+#[tokio::test]
+#[ignore]
+async fn discover_models_lists_prefixed_inference_profiles() {
+    let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
+    let prefixes = vec!["us.".to_string(), "global.".to_string()];
+
+    let discovered =
+        server::models::discover_models(&aws_sdk_bedrock::Client::new(&config), &prefixes)
+            .await
+            .expect("discovery should succeed");
+
+    assert!(
+        discovered.iter().any(|m| m.id == OPUS_4_8),
+        "expected {OPUS_4_8} among discovered models"
+    );
+    assert!(
+        discovered
+            .iter()
+            .filter(|m| m.id.contains("anthropic.claude-opus-4"))
+            .all(|m| prefixes.iter().any(|p| m.id.starts_with(p.as_str()))),
+        "inference-profile-only models must be listed under a configured prefix"
+    );
+}
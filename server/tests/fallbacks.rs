@@ -0,0 +1,129 @@
+mod common;
+
+use aws_sdk_bedrockruntime::{Client, config::retry::RetryConfig};
+use aws_smithy_mocks::{RuleMode, mock, mock_client};
+use aws_smithy_runtime_api::http::{Response as HttpResponse, StatusCode as HttpStatusCode};
+use aws_smithy_types::body::SdkBody;
+use axum::body::Body;
+use http_body_util::BodyExt;
+use server::{
+    auth::{ApiKeyConfig, ApiKeys},
+    fallbacks::{FallbackChain, FallbackChains},
+    get_app,
+    settings::Settings,
+};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const OPUS: &str = "us.anthropic.claude-opus-fallback-test";
+const SONNET: &str = "us.anthropic.claude-sonnet-fallback-test";
+const OPUS_EU: &str = "eu.anthropic.claude-opus-fallback-test";
+
+fn bedrock_error(status: u16, error_type: &str) -> HttpResponse {
+    let mut response = HttpResponse::new(
+        HttpStatusCode::try_from(status).unwrap(),
+        SdkBody::from(r#"{"message":"Try again later."}"#),
+    );
+    response
+        .headers_mut()
+        .insert("x-amzn-errortype", error_type.to_string());
+    response
+}
+
+fn build_app_with_client(client: Client) -> axum::Router {
+    let state = Arc::new(common::app_state(
+        client,
+        Settings {
+            api_keys: ApiKeys::new(vec![
+                common::api_key("alice"),
+                ApiKeyConfig {
+                    admin: true,
+                    ..common::api_key("ops")
+                },
+            ])
+            .unwrap(),
+            fallbacks: FallbackChains::new(vec![FallbackChain {
+                model: "*claude-opus*".to_string(),
+                fallbacks: vec![SONNET.to_string(), OPUS_EU.to_string()],
+            }])
+            .unwrap(),
+            ..Default::default()
+        },
+    ));
+    get_app(state)
+}
+
+fn post(uri: &str) -> axum::http::Request<Body> {
+    let body = serde_json::json!({
+        "model": OPUS,
+        "max_tokens": 16,
+        "messages": [{"role": "user", "content": "hi"}]
+    });
+    axum::http::Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "application/json")
+        .header("x-api-key", "sk-alice")
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap()
+}
+
+async fn response_json(response: axum::response::Response) -> serde_json::Value {
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn throttled_model_falls_back_and_reports_the_model_used() {
+    let opus = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(|req| req.model_id() == Some(OPUS))
+        .then_http_response(|| bedrock_error(429, "ThrottlingException"));
+    let sonnet = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(|req| req.model_id() == Some(SONNET))
+        .then_output(|| common::converse_output(30, 7));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::MatchAny,
+        [&opus, &sonnet],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let app = build_app_with_client(client);
+
+    let response = app.clone().oneshot(post("/v1/messages")).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-bedrock-model-id"], SONNET);
+    // The client still sees the model it asked for.
+    let json = response_json(response).await;
+    assert_eq!(json["model"], OPUS);
+    assert_eq!(opus.num_calls(), 1);
+    assert_eq!(sonnet.num_calls(), 1);
+
+    let usage = common::usage_after(&app, "?group_by=model", 1).await;
+    assert_eq!(usage["data"][0]["model"], SONNET);
+    assert_eq!(usage["data"][0]["requests"], 1);
+}
+
+#[tokio::test]
+async fn fallback_chain_is_followed_while_models_are_unavailable() {
+    let opus = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(|req| req.model_id() == Some(OPUS))
+        .then_http_response(|| bedrock_error(503, "ServiceUnavailableException"));
+    let sonnet = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(|req| req.model_id() == Some(SONNET))
+        .then_http_response(|| bedrock_error(429, "ThrottlingException"));
+    let opus_eu = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(|req| req.model_id() == Some(OPUS_EU))
+        .then_output(|| common::converse_output(30, 7));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::MatchAny,
+        [&opus, &sonnet, &opus_eu],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let app = build_app_with_client(client);
+
+    let response = app.oneshot(post("/chat/completions")).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-bedrock-model-id"], OPUS_EU);
+    assert_eq!(opus_eu.num_calls(), 1);
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use aws_sdk_bedrockruntime::{Client, config::retry::RetryConfig};
+use aws_smithy_mocks::{RuleMode, mock, mock_client};
+use aws_smithy_runtime_api::http::{Response as HttpResponse, StatusCode as HttpStatusCode};
+use aws_smithy_types::body::SdkBody;
+use axum::body::Body;
+use chat::inference_profile::InferenceProfileResolver;
+use http_body_util::BodyExt;
+use server::{get_app, settings::Settings};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn access_denied() -> HttpResponse {
+    let mut response = HttpResponse::new(
+        HttpStatusCode::try_from(403).unwrap(),
+        SdkBody::from(
+            r#"{"message":"You don't have access to the model with the specified model ID."}"#,
+        ),
+    );
+    response
+        .headers_mut()
+        .insert("x-amzn-errortype", "AccessDeniedException");
+    response
+}
+
+fn build_app_with_client(client: Client) -> axum::Router {
+    let prefixes = vec!["us.".to_string(), "global.".to_string()];
+    let state = Arc::new(common::app_state(
+        client,
+        Settings {
+            inference_profiles: Arc::new(InferenceProfileResolver::new(prefixes)),
+            ..Default::default()
+        },
+    ));
+    get_app(state)
+}
+
+fn post_v1_messages(model: &str) -> axum::http::Request<Body> {
+    let body = serde_json::json!({
+        "model": model,
+        "max_tokens": 16,
+        "messages": [{"role": "user", "content": "hi"}]
+    });
+    axum::http::Request::builder()
+        .method("POST")
+        .uri("/v1/messages")
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn bare_model_fails_over_to_next_prefix_and_remembers_it() {
+    let us_denied = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(|req| req.model_id() == Some("us.anthropic.claude-opus-4-8"))
+        .then_http_response(access_denied);
+    let global_ok = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(|req| req.model_id() == Some("global.anthropic.claude-opus-4-8"))
+        .then_output(|| common::converse_output(1, 1));
+    let global_ok_again = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(|req| req.model_id() == Some("global.anthropic.claude-opus-4-8"))
+        .then_output(|| common::converse_output(1, 1));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        [&us_denied, &global_ok, &global_ok_again],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let app = build_app_with_client(client);
+
+    for _ in 0..2 {
+        let response = app
+            .clone()
+            .oneshot(post_v1_messages("anthropic.claude-opus-4-8"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["model"], "anthropic.claude-opus-4-8");
+    }
+
+    assert_eq!(us_denied.num_calls(), 1);
+    assert_eq!(global_ok.num_calls(), 1);
+    assert_eq!(global_ok_again.num_calls(), 1);
+}
+
+#[tokio::test]
+async fn prefixed_model_is_not_failed_over() {
+    let us_denied = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(|req| req.model_id() == Some("us.anthropic.claude-opus-4-8"))
+        .then_http_response(access_denied);
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        [&us_denied],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+
+    let response = build_app_with_client(client)
+        .oneshot(post_v1_messages("us.anthropic.claude-opus-4-8"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+    assert_eq!(us_denied.num_calls(), 1);
+}
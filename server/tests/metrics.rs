@@ -0,0 +1,143 @@
+mod common;
+
+use aws_sdk_bedrockruntime::{
+    Client,
+    config::retry::RetryConfig,
+    operation::converse::ConverseOutput as ConverseSendOutput,
+    types::{ContentBlock, StopReason, TokenUsage},
+};
+use aws_smithy_mocks::{RuleMode, mock, mock_client};
+use aws_smithy_runtime_api::http::{Response as HttpResponse, StatusCode as HttpStatusCode};
+use aws_smithy_types::body::SdkBody;
+use axum::body::Body;
+use http_body_util::BodyExt;
+use server::{get_app, settings::Settings};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const MODEL: &str = "us.anthropic.claude-metrics-test";
+
+fn converse_output() -> ConverseSendOutput {
+    common::converse_reply(
+        vec![ContentBlock::Text("hi".to_string())],
+        StopReason::EndTurn,
+        TokenUsage::builder()
+            .input_tokens(30)
+            .output_tokens(7)
+            .total_tokens(37)
+            .cache_read_input_tokens(11)
+            .build()
+            .expect("usage"),
+    )
+}
+
+fn throttled() -> HttpResponse {
+    let mut response = HttpResponse::new(
+        HttpStatusCode::try_from(429).unwrap(),
+        SdkBody::from(r#"{"message":"Too many requests, please wait before trying again."}"#),
+    );
+    response
+        .headers_mut()
+        .insert("x-amzn-errortype", "ThrottlingException");
+    response
+}
+
+fn build_app_with_client(client: Client) -> axum::Router {
+    let state = Arc::new(common::app_state(client, Settings::default()));
+    get_app(state)
+}
+
+fn post_v1_messages() -> axum::http::Request<Body> {
+    let body = serde_json::json!({
+        "model": MODEL,
+        "max_tokens": 16,
+        "messages": [{"role": "user", "content": "hi"}]
+    });
+    axum::http::Request::builder()
+        .method("POST")
+        .uri("/v1/messages")
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap()
+}
+
+async fn scrape(app: axum::Router) -> String {
+    let response = app
+        .oneshot(
+            axum::http::Request::builder()
+                .uri("/metrics")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+/// The value of the first sample of `name` whose labels contain all of
+/// `labels`.
+fn sample(metrics: &str, name: &str, labels: &[&str]) -> Option<f64> {
+    metrics
+        .lines()
+        .filter(|line| line.starts_with(&format!("{name}{{")))
+        .find(|line| labels.iter().all(|label| line.contains(label)))
+        .and_then(|line| line.rsplit(' ').next()?.parse().ok())
+}
+
+#[tokio::test]
+async fn metrics_count_requests_tokens_and_bedrock_errors() {
+    let ok = mock!(aws_sdk_bedrockruntime::Client::converse).then_output(converse_output);
+    let throttled = mock!(aws_sdk_bedrockruntime::Client::converse).then_http_response(throttled);
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        [&ok, &throttled],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let app = build_app_with_client(client);
+
+    let response = app.clone().oneshot(post_v1_messages()).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let response = app.clone().oneshot(post_v1_messages()).await.unwrap();
+    assert_eq!(response.status(), 429);
+
+    let metrics = scrape(app).await;
+    let model = format!("model=\"{MODEL}\"");
+    let route = "route=\"/v1/messages\"";
+    assert_eq!(
+        sample(
+            &metrics,
+            "llm_proxy_http_requests_total",
+            &[route, &model, "status=\"200\""]
+        ),
+        Some(1.0)
+    );
+    assert_eq!(
+        sample(
+            &metrics,
+            "llm_proxy_http_requests_total",
+            &[route, &model, "status=\"429\""]
+        ),
+        Some(1.0)
+    );
+    // Usage is recorded once the response is built, before the scrape.
+    assert_eq!(
+        sample(
+            &metrics,
+            "llm_proxy_tokens_total",
+            &[&model, "type=\"cache_read\""]
+        ),
+        Some(11.0)
+    );
+    assert!(
+        sample(
+            &metrics,
+            "llm_proxy_bedrock_errors_total",
+            &["kind=\"rate_limit_error\""]
+        )
+        .is_some_and(|count| count >= 1.0)
+    );
+    assert!(metrics.contains("# TYPE llm_proxy_http_request_duration_seconds histogram"));
+}
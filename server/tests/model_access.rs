@@ -0,0 +1,327 @@
+mod common;
+
+use aws_sdk_bedrockruntime::{Client, config::retry::RetryConfig};
+use aws_smithy_mocks::{RuleMode, mock, mock_client};
+use aws_smithy_runtime_api::http::{Response as HttpResponse, StatusCode as HttpStatusCode};
+use aws_smithy_types::body::SdkBody;
+use axum::body::Body;
+use http_body_util::BodyExt;
+use server::{
+    aliases::{ModelAlias, ModelAliases},
+    auth::{ApiKeyConfig, ApiKeys},
+    fallbacks::{FallbackChain, FallbackChains},
+    get_app,
+    model_access::ModelRules,
+    models::{ModelCatalog, ModelConfig},
+    settings::Settings,
+    tenants::{TenantConfig, Tenants},
+    throttle::ThrottlingConfig,
+};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const HAIKU: &str = "us.anthropic.claude-haiku-access-test";
+const SONNET: &str = "us.anthropic.claude-sonnet-access-test";
+const PREVIEW: &str = "us.anthropic.claude-preview-access-test";
+
+fn throttled() -> HttpResponse {
+    let mut response = HttpResponse::new(
+        HttpStatusCode::try_from(429).unwrap(),
+        SdkBody::from(r#"{"message":"Too many requests, please wait before trying again."}"#),
+    );
+    response
+        .headers_mut()
+        .insert("x-amzn-errortype", "ThrottlingException");
+    response
+}
+
+fn access_denied() -> HttpResponse {
+    let mut response = HttpResponse::new(
+        HttpStatusCode::try_from(403).unwrap(),
+        SdkBody::from(
+            r#"{"message":"You don't have access to the model with the specified model ID."}"#,
+        ),
+    );
+    response
+        .headers_mut()
+        .insert("x-amzn-errortype", "AccessDeniedException");
+    response
+}
+
+/// `ci` may only use Haiku; `alice` anything but previews, and `bob` is in
+/// a tenant that denies Sonnet. Haiku falls back to the preview model.
+fn build_app_with_client(client: Client) -> axum::Router {
+    let model_aliases = ModelAliases::new(vec![ModelAlias {
+        pattern: "sonnet".to_string(),
+        target: SONNET.to_string(),
+    }])
+    .unwrap();
+    let models = [HAIKU, SONNET, PREVIEW].map(|id| ModelConfig {
+        id: id.to_string(),
+        display_name: None,
+        created_at: None,
+        owned_by: None,
+    });
+    let mut models = ModelCatalog::from_config(&models, &[]);
+    models.extend_aliases(&model_aliases, &[]);
+    let state = Arc::new(common::app_state(
+        client,
+        Settings {
+            api_keys: ApiKeys::new(vec![
+                ApiKeyConfig {
+                    models: ModelRules {
+                        allowed_models: vec!["*claude-haiku*".to_string()],
+                        ..Default::default()
+                    },
+                    ..common::api_key("ci")
+                },
+                ApiKeyConfig {
+                    models: ModelRules {
+                        denied_models: vec!["*preview*".to_string()],
+                        ..Default::default()
+                    },
+                    ..common::api_key("alice")
+                },
+                ApiKeyConfig {
+                    tenant: Some("search".to_string()),
+                    ..common::api_key("bob")
+                },
+                ApiKeyConfig {
+                    admin: true,
+                    ..common::api_key("ops")
+                },
+            ])
+            .unwrap(),
+            model_aliases,
+            models,
+            fallbacks: FallbackChains::new(vec![FallbackChain {
+                model: HAIKU.to_string(),
+                fallbacks: vec![PREVIEW.to_string()],
+            }])
+            .unwrap(),
+            // Pacing would only slow down the throttled requests below.
+            throttling: ThrottlingConfig {
+                enabled: false,
+                ..Default::default()
+            },
+            tenants: Tenants::new(vec![TenantConfig {
+                name: "search".to_string(),
+                denied_models: vec![SONNET.to_string()],
+                ..Default::default()
+            }])
+            .unwrap(),
+            ..Default::default()
+        },
+    ));
+    get_app(state)
+}
+
+fn post(uri: &str, key: &str, model: &str) -> axum::http::Request<Body> {
+    let body = serde_json::json!({
+        "model": model,
+        "max_tokens": 16,
+        "messages": [{"role": "user", "content": "hi"}]
+    });
+    axum::http::Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "application/json")
+        .header("x-api-key", key)
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap()
+}
+
+fn get(uri: &str, key: &str) -> axum::http::Request<Body> {
+    axum::http::Request::builder()
+        .uri(uri)
+        .header("x-api-key", key)
+        .body(Body::empty())
+        .unwrap()
+}
+
+async fn response_json(response: axum::response::Response) -> serde_json::Value {
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn disallowed_models_are_rejected_in_the_callers_protocol() {
+    let converse = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .then_output(|| common::converse_output(30, 7));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::MatchAny,
+        [&converse],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let app = build_app_with_client(client);
+
+    for (uri, key, model, status) in [
+        ("/v1/messages", "sk-ci", HAIKU, 200),
+        // Rules apply to the Bedrock model ID the alias resolves to.
+        ("/v1/messages", "sk-ci", "sonnet", 403),
+        ("/chat/completions", "sk-ci", SONNET, 403),
+        ("/v1/messages", "sk-alice", SONNET, 200),
+        ("/chat/completions", "sk-alice", PREVIEW, 403),
+        ("/v1/messages", "sk-bob", "sonnet", 403),
+        ("/v1/messages", "sk-bob", PREVIEW, 200),
+    ] {
+        let response = app.clone().oneshot(post(uri, key, model)).await.unwrap();
+        assert_eq!(response.status(), status, "{key} {model} on {uri}");
+        if status == 403 {
+            let json = response_json(response).await;
+            assert_eq!(json["error"]["type"], "permission_error");
+            if uri == "/v1/messages" {
+                assert_eq!(json["type"], "error");
+            } else {
+                assert!(json.get("type").is_none());
+            }
+        }
+    }
+    assert_eq!(converse.num_calls(), 3);
+
+    let usage = common::usage_after(&app, "?group_by=key,model", 7).await;
+    let row = |key: &str, model: &str| {
+        usage["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|row| row["key"] == key && row["model"] == model)
+            .unwrap()
+            .clone()
+    };
+    assert_eq!(row("ci", SONNET)["requests"], 2);
+    assert_eq!(row("ci", SONNET)["rejected_requests"], 2);
+    assert_eq!(row("ci", HAIKU)["rejected_requests"], 0);
+    assert_eq!(row("alice", PREVIEW)["rejected_requests"], 1);
+    assert_eq!(row("bob", SONNET)["rejected_requests"], 1);
+}
+
+#[tokio::test]
+async fn upstream_access_denied_is_not_counted_as_rejected() {
+    let denied = mock!(aws_sdk_bedrockruntime::Client::converse).then_http_response(access_denied);
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::MatchAny,
+        [&denied],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let app = build_app_with_client(client);
+
+    for (key, model) in [("sk-ci", HAIKU), ("sk-ci", SONNET)] {
+        let response = app
+            .clone()
+            .oneshot(post("/v1/messages", key, model))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403, "{key} {model}");
+        let json = response_json(response).await;
+        assert_eq!(json["error"]["type"], "permission_error");
+    }
+    assert_eq!(denied.num_calls(), 1);
+
+    let usage = common::usage_after(&app, "?group_by=key,model", 2).await;
+    let row = |model: &str| {
+        usage["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|row| row["key"] == "ci" && row["model"] == model)
+            .unwrap()
+            .clone()
+    };
+    assert_eq!(row(HAIKU)["failed_requests"], 1);
+    assert_eq!(row(HAIKU)["rejected_requests"], 0);
+    assert_eq!(row(SONNET)["rejected_requests"], 1);
+}
+
+#[tokio::test]
+async fn denied_fallbacks_are_skipped() {
+    let haiku = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(|req| req.model_id() == Some(HAIKU))
+        .then_http_response(throttled);
+    let preview = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(|req| req.model_id() == Some(PREVIEW))
+        .then_output(|| common::converse_output(30, 7));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::MatchAny,
+        [&haiku, &preview],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let app = build_app_with_client(client);
+
+    // `bob` may use the preview model, so his request falls back to it.
+    let response = app
+        .clone()
+        .oneshot(post("/v1/messages", "sk-bob", HAIKU))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-bedrock-model-id"], PREVIEW);
+    assert_eq!(preview.num_calls(), 1);
+
+    // `ci` may not, so it gets the throttle error instead.
+    for uri in ["/v1/messages", "/chat/completions"] {
+        let response = app
+            .clone()
+            .oneshot(post(uri, "sk-ci", HAIKU))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 429, "{uri}");
+        let json = response_json(response).await;
+        assert_eq!(json["error"]["type"], "rate_limit_error", "{uri}");
+    }
+    assert_eq!(preview.num_calls(), 1);
+}
+
+#[tokio::test]
+async fn model_listings_only_show_allowed_models() {
+    let app = build_app_with_client(mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        []
+    ));
+
+    for (key, expected) in [
+        ("sk-ci", vec![HAIKU]),
+        ("sk-alice", vec![HAIKU, SONNET, "sonnet"]),
+        ("sk-bob", vec![HAIKU, PREVIEW]),
+    ] {
+        let json = response_json(app.clone().oneshot(get("/v1/models", key)).await.unwrap()).await;
+        let ids: Vec<&str> = json["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|model| model["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, expected, "{key} on /v1/models");
+
+        let json = response_json(app.clone().oneshot(get("/models", key)).await.unwrap()).await;
+        let ids: Vec<&str> = json["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|model| model["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, expected, "{key} on /models");
+    }
+
+    let response = app
+        .clone()
+        .oneshot(get(&format!("/v1/models/{SONNET}"), "sk-ci"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+    let response = app
+        .clone()
+        .oneshot(get(&format!("/models/{SONNET}"), "sk-bob"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+    let response = app
+        .oneshot(get(&format!("/models/{HAIKU}"), "sk-ci"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+}
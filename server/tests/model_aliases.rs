@@ -0,0 +1,130 @@
+mod common;
+
+use aws_sdk_bedrockruntime::Client;
+use aws_smithy_mocks::{RuleMode, mock, mock_client};
+use axum::body::Body;
+use chat::inference_profile::InferenceProfileResolver;
+use http_body_util::BodyExt;
+use server::{
+    aliases::{ModelAlias, ModelAliases},
+    get_app,
+    settings::Settings,
+};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const SONNET_4_5: &str = "us.anthropic.claude-sonnet-4-5-20250929-v1:0";
+
+fn build_app_with_client(client: Client) -> axum::Router {
+    let aliases: Vec<ModelAlias> = serde_json::from_value(serde_json::json!([
+        {"pattern": "claude-*", "target": "us.anthropic.claude-*-v1:0"},
+        {"pattern": "gpt-4o", "target": SONNET_4_5}
+    ]))
+    .unwrap();
+
+    let prefixes = vec!["us.".to_string(), "global.".to_string()];
+    let state = Arc::new(common::app_state(
+        client,
+        Settings {
+            inference_profiles: Arc::new(InferenceProfileResolver::new(prefixes)),
+            model_aliases: ModelAliases::new(aliases).unwrap(),
+            ..Default::default()
+        },
+    ));
+    get_app(state)
+}
+
+async fn post_json(
+    app: axum::Router,
+    uri: &str,
+    body: serde_json::Value,
+) -> (axum::http::StatusCode, serde_json::Value) {
+    let request = axum::http::Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn v1_messages_rewrites_wildcard_alias_and_echoes_requested_model() {
+    let converse_rule = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(|req| req.model_id() == Some(SONNET_4_5))
+        .then_output(|| common::converse_output(1, 1));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        [&converse_rule]
+    );
+
+    let (status, json) = post_json(
+        build_app_with_client(client),
+        "/v1/messages",
+        serde_json::json!({
+            "model": "claude-sonnet-4-5-20250929",
+            "max_tokens": 16,
+            "messages": [{"role": "user", "content": "hi"}]
+        }),
+    )
+    .await;
+
+    assert_eq!(status, 200, "body: {json}");
+    assert_eq!(json["model"], "claude-sonnet-4-5-20250929");
+    assert_eq!(converse_rule.num_calls(), 1);
+}
+
+#[tokio::test]
+async fn chat_completions_rewrites_exact_alias_and_echoes_requested_model() {
+    let converse_rule = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(|req| req.model_id() == Some(SONNET_4_5))
+        .then_output(|| common::converse_output(1, 1));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        [&converse_rule]
+    );
+
+    let (status, json) = post_json(
+        build_app_with_client(client),
+        "/chat/completions",
+        serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "hi"}]
+        }),
+    )
+    .await;
+
+    assert_eq!(status, 200, "body: {json}");
+    assert_eq!(json["model"], "gpt-4o");
+    assert_eq!(converse_rule.num_calls(), 1);
+}
+
+#[tokio::test]
+async fn unaliased_model_is_forwarded_verbatim() {
+    let converse_rule = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(|req| req.model_id() == Some("global.anthropic.claude-opus-4-8"))
+        .then_output(|| common::converse_output(1, 1));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        [&converse_rule]
+    );
+
+    let (status, json) = post_json(
+        build_app_with_client(client),
+        "/chat/completions",
+        serde_json::json!({
+            "model": "global.anthropic.claude-opus-4-8",
+            "messages": [{"role": "user", "content": "hi"}]
+        }),
+    )
+    .await;
+
+    assert_eq!(status, 200, "body: {json}");
+    assert_eq!(json["model"], "global.anthropic.claude-opus-4-8");
+}
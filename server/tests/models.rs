@@ -0,0 +1,129 @@
+mod common;
+
+use aws_smithy_mocks::{RuleMode, mock_client};
+use axum::body::Body;
+use chat::inference_profile::InferenceProfileResolver;
+use http_body_util::BodyExt;
+use server::{
+    get_app,
+    models::{ModelCatalog, ModelConfig},
+    settings::Settings,
+};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn build_app() -> axum::Router {
+    let prefixes = vec!["us.".to_string(), "global.".to_string()];
+    let models: Vec<ModelConfig> = serde_json::from_value(serde_json::json!([
+        {
+            "id": "global.anthropic.claude-opus-4-8",
+            "display_name": "Claude Opus 4.8",
+            "created_at": "2026-08-01T00:00:00Z"
+        },
+        {"id": "us.anthropic.claude-sonnet-4-5-20250929-v1:0"},
+        {"id": "meta.llama3-70b-instruct-v1:0", "owned_by": "meta-llama"}
+    ]))
+    .unwrap();
+
+    let client = mock_client!(aws_sdk_bedrockruntime, RuleMode::Sequential, []);
+    let state = Arc::new(common::app_state(
+        client,
+        Settings {
+            models: ModelCatalog::from_config(&models, &prefixes),
+            inference_profiles: Arc::new(InferenceProfileResolver::new(prefixes)),
+            ..Default::default()
+        },
+    ));
+    get_app(state)
+}
+
+async fn get_json(uri: &str) -> (axum::http::StatusCode, serde_json::Value) {
+    let response = build_app()
+        .oneshot(
+            axum::http::Request::builder()
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn v1_models_lists_configured_models_in_anthropic_shape() {
+    let (status, json) = get_json("/v1/models").await;
+    assert_eq!(status, 200);
+    assert_eq!(json["has_more"], false);
+    assert_eq!(json["first_id"], "global.anthropic.claude-opus-4-8");
+    assert_eq!(json["last_id"], "meta.llama3-70b-instruct-v1:0");
+    assert_eq!(
+        json["data"][0],
+        serde_json::json!({
+            "type": "model",
+            "id": "global.anthropic.claude-opus-4-8",
+            "display_name": "Claude Opus 4.8",
+            "created_at": "2026-08-01T00:00:00Z"
+        })
+    );
+    assert_eq!(
+        json["data"][1]["display_name"],
+        "us.anthropic.claude-sonnet-4-5-20250929-v1:0"
+    );
+}
+
+#[tokio::test]
+async fn v1_models_paginates_with_cursors() {
+    let (_, first) = get_json("/v1/models?limit=1").await;
+    assert_eq!(first["data"].as_array().unwrap().len(), 1);
+    assert_eq!(first["has_more"], true);
+
+    let cursor = first["last_id"].as_str().unwrap();
+    let (_, second) = get_json(&format!("/v1/models?limit=1&after_id={cursor}")).await;
+    assert_eq!(
+        second["first_id"],
+        "us.anthropic.claude-sonnet-4-5-20250929-v1:0"
+    );
+    assert_eq!(second["has_more"], true);
+}
+
+#[tokio::test]
+async fn v1_models_rejects_out_of_range_limit() {
+    let (status, json) = get_json("/v1/models?limit=0").await;
+    assert_eq!(status, 400);
+    assert_eq!(json["error"]["type"], "invalid_request_error");
+}
+
+#[tokio::test]
+async fn v1_model_returns_single_model_or_not_found() {
+    let (status, json) = get_json("/v1/models/global.anthropic.claude-opus-4-8").await;
+    assert_eq!(status, 200);
+    assert_eq!(json["display_name"], "Claude Opus 4.8");
+
+    let (status, json) = get_json("/v1/models/unknown").await;
+    assert_eq!(status, 404);
+    assert_eq!(json["error"]["type"], "not_found_error");
+}
+
+#[tokio::test]
+async fn models_lists_configured_models_in_openai_shape() {
+    let (status, json) = get_json("/models").await;
+    assert_eq!(status, 200);
+    assert_eq!(json["object"], "list");
+    assert_eq!(
+        json["data"][0],
+        serde_json::json!({
+            "id": "global.anthropic.claude-opus-4-8",
+            "object": "model",
+            "created": 1785542400,
+            "owned_by": "anthropic"
+        })
+    );
+    assert_eq!(json["data"][2]["owned_by"], "meta-llama");
+
+    let (status, json) = get_json("/models/unknown").await;
+    assert_eq!(status, 404);
+    assert_eq!(json["error"]["code"], "model_not_found");
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use aws_sdk_bedrockruntime::Client;
+use aws_smithy_mocks::{RuleMode, mock, mock_client};
+use axum::body::Body;
+use http_body_util::BodyExt;
+use server::{
+    auth::{ApiKeyConfig, ApiKeys},
+    get_app,
+    rate_limit::RateLimits,
+    settings::Settings,
+};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn build_app_with_client(client: Client, limits: RateLimits) -> axum::Router {
+    let api_keys = ApiKeys::new(vec![ApiKeyConfig {
+        limits,
+        ..common::api_key("alice")
+    }])
+    .unwrap();
+
+    let state = Arc::new(common::app_state(
+        client,
+        Settings {
+            api_keys,
+            ..Default::default()
+        },
+    ));
+    get_app(state)
+}
+
+fn post(uri: &str) -> axum::http::Request<Body> {
+    let body = serde_json::json!({
+        "model": "us.anthropic.claude-opus-4-8",
+        "max_tokens": 16,
+        "messages": [{"role": "user", "content": "hi"}]
+    });
+    axum::http::Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "application/json")
+        .header("x-api-key", "sk-alice")
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap()
+}
+
+async fn response_json(response: axum::response::Response) -> serde_json::Value {
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn v1_messages_enforces_requests_per_minute() {
+    let converse_rule = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .then_output(|| common::converse_output(100, 50));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        [&converse_rule]
+    );
+    let app = build_app_with_client(
+        client,
+        RateLimits {
+            requests_per_minute: Some(1),
+            ..Default::default()
+        },
+    );
+
+    let response = app.clone().oneshot(post("/v1/messages")).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers()["anthropic-ratelimit-requests-limit"],
+        "1"
+    );
+    assert_eq!(
+        response.headers()["anthropic-ratelimit-requests-remaining"],
+        "0"
+    );
+    assert!(
+        response
+            .headers()
+            .contains_key("anthropic-ratelimit-requests-reset")
+    );
+
+    let response = app.oneshot(post("/v1/messages")).await.unwrap();
+    assert_eq!(response.status(), 429);
+    assert_eq!(response.headers()["retry-after"], "60");
+    let json = response_json(response).await;
+    assert_eq!(json["error"]["type"], "rate_limit_error");
+    assert_eq!(converse_rule.num_calls(), 1);
+}
+
+#[tokio::test]
+async fn chat_completions_debits_reported_usage_from_token_limits() {
+    let converse_rule = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .then_output(|| common::converse_output(100, 50));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        [&converse_rule]
+    );
+    let app = build_app_with_client(
+        client,
+        RateLimits {
+            input_tokens_per_minute: Some(60),
+            ..Default::default()
+        },
+    );
+
+    let response = app
+        .clone()
+        .oneshot(post("/chat/completions"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-ratelimit-limit-tokens"], "60");
+    assert_eq!(response.headers()["x-ratelimit-remaining-tokens"], "60");
+
+    // The first call reported 100 input tokens against a 60/min budget.
+    let response = app.oneshot(post("/chat/completions")).await.unwrap();
+    assert_eq!(response.status(), 429);
+    assert_eq!(response.headers()["x-ratelimit-remaining-tokens"], "0");
+    let json = response_json(response).await;
+    assert_eq!(json["error"]["type"], "rate_limit_error");
+    assert_eq!(json["error"]["code"], "rate_limit_exceeded");
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use aws_sdk_bedrockruntime::{Client, config::retry::RetryConfig};
+use aws_smithy_mocks::{RuleMode, mock, mock_client};
+use aws_smithy_runtime_api::http::{Response as HttpResponse, StatusCode as HttpStatusCode};
+use aws_smithy_types::body::SdkBody;
+use axum::body::Body;
+use http_body_util::BodyExt;
+use server::{AppState, get_app, ledger::UsageLedger, settings::Settings};
+use std::{path::PathBuf, sync::Arc};
+use tower::ServiceExt;
+
+const BEDROCK_REQUEST_ID: &str = "6f0bd8a5-1c2d-4e3f-9a8b-7c6d5e4f3a2b";
+
+fn throttled() -> HttpResponse {
+    let mut response = HttpResponse::new(
+        HttpStatusCode::try_from(429).unwrap(),
+        SdkBody::from(r#"{"message":"Too many requests, please wait before trying again."}"#),
+    );
+    response
+        .headers_mut()
+        .insert("x-amzn-errortype", "ThrottlingException");
+    response
+        .headers_mut()
+        .insert("x-amzn-requestid", BEDROCK_REQUEST_ID);
+    response
+}
+
+fn throttled_client() -> Client {
+    let converse_rule =
+        mock!(aws_sdk_bedrockruntime::Client::converse).then_http_response(throttled);
+    mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        [&converse_rule],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    )
+}
+
+fn build_app(client: Client, ledger: UsageLedger) -> axum::Router {
+    let state = Arc::new(AppState {
+        ledger,
+        ..common::app_state(client, Settings::default())
+    });
+    get_app(state)
+}
+
+fn post(uri: &str, body: serde_json::Value) -> axum::http::Request<Body> {
+    axum::http::Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap()
+}
+
+async fn response_json(response: axum::response::Response) -> serde_json::Value {
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn anthropic_error_carries_request_id_and_ledger_keeps_bedrock_id() {
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("llm-proxy-request-id-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let app = build_app(throttled_client(), UsageLedger::open(&path).unwrap());
+
+    let response = app
+        .oneshot(post(
+            "/v1/messages",
+            serde_json::json!({
+                "model": "anthropic.claude-opus-4-8",
+                "max_tokens": 16,
+                "messages": [{"role": "user", "content": "hi"}]
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 429);
+    let request_id = response.headers()["request-id"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(request_id.starts_with("req_"));
+    assert!(!response.headers().contains_key("x-request-id"));
+    let json = response_json(response).await;
+    assert_eq!(json["type"], "error");
+    assert_eq!(json["error"]["type"], "rate_limit_error");
+    assert_eq!(json["request_id"], request_id.as_str());
+
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    let row = common::poll_ledger(|| async {
+        conn.query_row(
+            "SELECT request_id, bedrock_request_id FROM usage",
+            [],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)),
+        )
+        .ok()
+    })
+    .await;
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(row, (request_id, Some(BEDROCK_REQUEST_ID.to_string())));
+}
+
+#[tokio::test]
+async fn openai_echoes_client_request_id() {
+    let app = build_app(throttled_client(), UsageLedger::open_in_memory().unwrap());
+    let mut request = post(
+        "/chat/completions",
+        serde_json::json!({
+            "model": "anthropic.claude-opus-4-8",
+            "messages": [{"role": "user", "content": "hi"}]
+        }),
+    );
+    request
+        .headers_mut()
+        .insert("x-request-id", "client-trace-7".parse().unwrap());
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), 429);
+    assert_eq!(response.headers()["x-request-id"], "client-trace-7");
+    assert!(!response.headers().contains_key("request-id"));
+    let json = response_json(response).await;
+    assert_eq!(json["error"]["code"], "rate_limit_exceeded");
+    assert_eq!(json["request_id"], "client-trace-7");
+}
+
+#[tokio::test]
+async fn successful_responses_carry_a_generated_request_id() {
+    let app = build_app(throttled_client(), UsageLedger::open_in_memory().unwrap());
+    let response = app
+        .oneshot(
+            axum::http::Request::builder()
+                .uri("/v1/models")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(
+        response.headers()["request-id"]
+            .to_str()
+            .unwrap()
+            .starts_with("req_")
+    );
+}
@@ -0,0 +1,404 @@
+mod common;
+
+use aws_sdk_bedrockruntime::{Client, config::retry::RetryConfig};
+use aws_smithy_eventstream::frame::write_message_to;
+use aws_smithy_mocks::{RuleMode, mock, mock_client};
+use aws_smithy_runtime_api::http::{Response as HttpResponse, StatusCode as HttpStatusCode};
+use aws_smithy_types::{
+    body::SdkBody,
+    event_stream::{Header, HeaderValue, Message},
+};
+use axum::body::Body;
+use http_body_util::BodyExt;
+use server::{
+    auth::{ApiKeyConfig, ApiKeys},
+    get_app,
+    response_cache::{
+        CACHE_STATUS, CacheScope, IDEMPOTENCY_KEY, IDEMPOTENT_REPLAYED, ResponseCacheConfig,
+    },
+    settings::Settings,
+    tenants::{TenantConfig, Tenants},
+};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const HAIKU: &str = "us.anthropic.claude-haiku-cache-test";
+
+/// A complete `converse_stream` response saying "hi".
+fn converse_stream_response() -> HttpResponse {
+    let events = [
+        ("messageStart", r#"{"role":"assistant"}"#),
+        (
+            "contentBlockDelta",
+            r#"{"contentBlockIndex":0,"delta":{"text":"hi"}}"#,
+        ),
+        ("contentBlockStop", r#"{"contentBlockIndex":0}"#),
+        ("messageStop", r#"{"stopReason":"end_turn"}"#),
+        (
+            "metadata",
+            r#"{"usage":{"inputTokens":30,"outputTokens":7,"totalTokens":37},"metrics":{"latencyMs":1}}"#,
+        ),
+    ];
+    let mut body = Vec::new();
+    for (event_type, payload) in events {
+        let message = Message::new(payload.as_bytes())
+            .add_header(Header::new(
+                ":message-type",
+                HeaderValue::String("event".into()),
+            ))
+            .add_header(Header::new(
+                ":event-type",
+                HeaderValue::String(event_type.into()),
+            ))
+            .add_header(Header::new(
+                ":content-type",
+                HeaderValue::String("application/json".into()),
+            ));
+        write_message_to(&message, &mut body).unwrap();
+    }
+    let mut response =
+        HttpResponse::new(HttpStatusCode::try_from(200).unwrap(), SdkBody::from(body));
+    response
+        .headers_mut()
+        .insert("content-type", "application/vnd.amazon.eventstream");
+    response
+}
+
+/// The id of the message an SSE response body starts.
+fn streamed_message_id(body: &str) -> String {
+    let data = body
+        .lines()
+        .find_map(|line| line.strip_prefix("data: "))
+        .expect("message_start event");
+    let event: serde_json::Value = serde_json::from_str(data).unwrap();
+    event["message"]["id"].as_str().unwrap().to_string()
+}
+
+/// `bob` and `eve` are in the `search` tenant; `ci` has none.
+fn build_app_with_client(client: Client, response_cache: ResponseCacheConfig) -> axum::Router {
+    let state = Arc::new(common::app_state(
+        client,
+        Settings {
+            api_keys: ApiKeys::new(vec![
+                common::api_key("ci"),
+                ApiKeyConfig {
+                    tenant: Some("search".to_string()),
+                    ..common::api_key("bob")
+                },
+                ApiKeyConfig {
+                    tenant: Some("search".to_string()),
+                    ..common::api_key("eve")
+                },
+            ])
+            .unwrap(),
+            tenants: Tenants::new(vec![TenantConfig {
+                name: "search".to_string(),
+                ..Default::default()
+            }])
+            .unwrap(),
+            response_cache,
+            ..Default::default()
+        },
+    ));
+    get_app(state)
+}
+
+fn post(
+    key: &str,
+    idempotency_key: Option<&str>,
+    text: &str,
+    stream: bool,
+) -> axum::http::Request<Body> {
+    let body = serde_json::json!({
+        "model": HAIKU,
+        "max_tokens": 16,
+        "stream": stream,
+        "messages": [{"role": "user", "content": text}]
+    });
+    let mut request = axum::http::Request::builder()
+        .method("POST")
+        .uri("/v1/messages")
+        .header("content-type", "application/json")
+        .header("x-api-key", key);
+    if let Some(idempotency_key) = idempotency_key {
+        request = request.header(IDEMPOTENCY_KEY, idempotency_key);
+    }
+    request
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap()
+}
+
+async fn response_text(response: axum::response::Response) -> String {
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+async fn response_json(response: axum::response::Response) -> serde_json::Value {
+    serde_json::from_str(&response_text(response).await).unwrap()
+}
+
+#[tokio::test]
+async fn identical_requests_are_served_from_the_cache() {
+    let converse = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .then_output(|| common::converse_output(30, 7));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::MatchAny,
+        [&converse],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let app = build_app_with_client(
+        client,
+        ResponseCacheConfig {
+            enabled: true,
+            ..Default::default()
+        },
+    );
+
+    let response = app
+        .clone()
+        .oneshot(post("sk-ci", None, "hi", false))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().get(CACHE_STATUS).is_none());
+    let first = response_json(response).await;
+
+    let response = app
+        .clone()
+        .oneshot(post("sk-ci", None, "hi", false))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()[CACHE_STATUS], "hit");
+    assert_eq!(response.headers()["x-bedrock-model-id"], HAIKU);
+    assert_eq!(response_json(response).await, first);
+
+    // Streaming requests replay the cached message as SSE events.
+    let response = app
+        .clone()
+        .oneshot(post("sk-ci", None, "hi", true))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()[CACHE_STATUS], "hit");
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let body = response_text(response).await;
+    let events: Vec<&str> = body
+        .lines()
+        .filter_map(|line| line.strip_prefix("event: "))
+        .collect();
+    assert_eq!(
+        events,
+        [
+            "message_start",
+            "content_block_start",
+            "content_block_delta",
+            "content_block_stop",
+            "message_delta",
+            "message_stop",
+        ]
+    );
+    assert!(body.contains(r#""text":"hi""#), "{body}");
+    assert_eq!(converse.num_calls(), 1);
+
+    // Other requests, or the same one from another key, miss.
+    for (key, text) in [("sk-ci", "hello"), ("sk-bob", "hi")] {
+        let response = app
+            .clone()
+            .oneshot(post(key, None, text, false))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(response.headers().get(CACHE_STATUS).is_none());
+    }
+    assert_eq!(converse.num_calls(), 3);
+}
+
+#[tokio::test]
+async fn keys_of_a_tenant_share_entries_in_tenant_scope() {
+    let converse = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .then_output(|| common::converse_output(30, 7));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::MatchAny,
+        [&converse],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let app = build_app_with_client(
+        client,
+        ResponseCacheConfig {
+            enabled: true,
+            scope: CacheScope::Tenant,
+            ..Default::default()
+        },
+    );
+
+    for (key, hit) in [("sk-bob", false), ("sk-eve", true), ("sk-ci", false)] {
+        let response = app
+            .clone()
+            .oneshot(post(key, None, "hi", false))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers().get(CACHE_STATUS).is_some(), hit, "{key}");
+    }
+    assert_eq!(converse.num_calls(), 2);
+}
+
+#[tokio::test]
+async fn retries_with_an_idempotency_key_are_not_sent_again() {
+    let converse = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .then_output(|| common::converse_output(30, 7));
+    let converse_stream = mock!(aws_sdk_bedrockruntime::Client::converse_stream)
+        .then_http_response(converse_stream_response);
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::MatchAny,
+        [&converse, &converse_stream],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let app = build_app_with_client(client, ResponseCacheConfig::default());
+
+    let response = app
+        .clone()
+        .oneshot(post("sk-ci", Some("retry-1"), "hi", false))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let first = response_json(response).await;
+
+    let response = app
+        .clone()
+        .oneshot(post("sk-ci", Some("retry-1"), "hi", false))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()[IDEMPOTENT_REPLAYED], "true");
+    assert_eq!(response_json(response).await["id"], first["id"]);
+
+    let response = app
+        .clone()
+        .oneshot(post("sk-ci", Some("retry-1"), "something else", false))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let json = response_json(response).await;
+    assert_eq!(json["error"]["type"], "invalid_request_error");
+
+    // Keys are per API key, and without one, or with the cache disabled,
+    // identical requests go to Bedrock.
+    for (key, idempotency_key) in [("sk-bob", Some("retry-1")), ("sk-ci", None)] {
+        let response = app
+            .clone()
+            .oneshot(post(key, idempotency_key, "hi", false))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(response.headers().get(IDEMPOTENT_REPLAYED).is_none());
+    }
+    assert_eq!(converse.num_calls(), 3);
+
+    // A stream that ended cleanly is stored too.
+    let response = app
+        .clone()
+        .oneshot(post("sk-ci", Some("retry-2"), "hi", true))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let first = response_text(response).await;
+    assert!(first.contains("message_stop"));
+
+    let response = app
+        .clone()
+        .oneshot(post("sk-ci", Some("retry-2"), "hi", true))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()[IDEMPOTENT_REPLAYED], "true");
+    assert_eq!(
+        streamed_message_id(&response_text(response).await),
+        streamed_message_id(&first)
+    );
+    assert_eq!(converse_stream.num_calls(), 1);
+}
+
+#[tokio::test]
+async fn tenants_pinned_to_different_regions_never_share_entries() {
+    let model = "anthropic.claude-haiku-cache-test";
+    let us = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(move |req| req.model_id() == Some(&format!("us.{model}")))
+        .then_output(|| common::converse_output(30, 7));
+    let eu = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(move |req| req.model_id() == Some(&format!("eu.{model}")))
+        .then_output(|| common::converse_output(30, 7));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::MatchAny,
+        [&us, &eu],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let tenant = |name: &str, prefix: &str| TenantConfig {
+        name: name.to_string(),
+        inference_profile_prefixes: Some(vec![prefix.to_string()]),
+        ..Default::default()
+    };
+    let state = Arc::new(common::app_state(
+        client,
+        Settings {
+            api_keys: ApiKeys::new(vec![
+                ApiKeyConfig {
+                    tenant: Some("us-team".to_string()),
+                    ..common::api_key("alice")
+                },
+                ApiKeyConfig {
+                    tenant: Some("eu-team".to_string()),
+                    ..common::api_key("bob")
+                },
+            ])
+            .unwrap(),
+            tenants: Tenants::new(vec![tenant("us-team", "us."), tenant("eu-team", "eu.")])
+                .unwrap(),
+            response_cache: ResponseCacheConfig {
+                enabled: true,
+                scope: CacheScope::Global,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    ));
+    let app = get_app(state);
+    let post = |key: &str| {
+        axum::http::Request::builder()
+            .method("POST")
+            .uri("/v1/messages")
+            .header("content-type", "application/json")
+            .header("x-api-key", key)
+            .body(Body::from(
+                serde_json::json!({
+                    "model": model,
+                    "max_tokens": 16,
+                    "messages": [{"role": "user", "content": "hi"}]
+                })
+                .to_string(),
+            ))
+            .unwrap()
+    };
+
+    for (key, served, hit) in [
+        ("sk-alice", "us.", false),
+        ("sk-bob", "eu.", false),
+        ("sk-bob", "eu.", true),
+    ] {
+        let response = app.clone().oneshot(post(key)).await.unwrap();
+        assert_eq!(response.status(), 200, "{key}");
+        assert_eq!(response.headers().get(CACHE_STATUS).is_some(), hit, "{key}");
+        assert_eq!(
+            response.headers()["x-bedrock-model-id"],
+            format!("{served}{model}").as_str(),
+            "{key}"
+        );
+    }
+    assert_eq!(us.num_calls(), 1);
+    assert_eq!(eu.num_calls(), 1);
+}
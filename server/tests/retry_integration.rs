@@ -1,3 +1,5 @@
+mod common;
+
 use aws_sdk_bedrockruntime::{
     Client,
     config::retry::RetryConfig,
@@ -9,8 +11,9 @@ use aws_sdk_bedrockruntime::{
 };
 use aws_smithy_mocks::{RuleMode, mock, mock_client};
 use axum::body::Body;
+use chat::inference_profile::InferenceProfileResolver;
 use http_body_util::BodyExt;
-use server::{AppState, get_app};
+use server::{get_app, settings::Settings};
 use std::sync::Arc;
 use std::time::Duration;
 use tower::ServiceExt;
@@ -47,11 +50,14 @@ fn successful_converse_output() -> ConverseSendOutput {
 }
 
 fn build_app_with_client(client: Client) -> axum::Router {
-    let state = Arc::new(AppState {
-        bedrockruntime_client: client,
-        inference_profile_prefixes: vec!["us.".to_string(), "global.".to_string()],
-        anthropic_beta_whitelist: vec![],
-    });
+    let prefixes = vec!["us.".to_string(), "global.".to_string()];
+    let state = Arc::new(common::app_state(
+        client,
+        Settings {
+            inference_profiles: Arc::new(InferenceProfileResolver::new(prefixes)),
+            ..Default::default()
+        },
+    ));
     get_app(state)
 }
 
@@ -174,4 +180,9 @@ async fn v1_messages_non_stream_does_not_retry_validation_errors() {
 
     assert_eq!(response.status(), 400);
     assert_eq!(converse_rule.num_calls(), 1);
+
+    let body_bytes = collect_body(response.into_body()).await;
+    let json: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+    assert_eq!(json["type"], "error");
+    assert_eq!(json["error"]["type"], "invalid_request_error");
 }
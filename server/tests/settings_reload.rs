@@ -0,0 +1,101 @@
+mod common;
+
+use aws_sdk_bedrock::config::{BehaviorVersion, Region};
+use server::settings::{ConfigSource, SettingsLoader, SettingsReloader};
+use std::{path::PathBuf, sync::Arc};
+
+fn bedrock_client() -> aws_sdk_bedrock::Client {
+    aws_sdk_bedrock::Client::from_conf(
+        aws_sdk_bedrock::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .build(),
+    )
+}
+
+fn bedrockruntime_client() -> aws_sdk_bedrockruntime::Client {
+    aws_sdk_bedrockruntime::Client::from_conf(
+        aws_sdk_bedrockruntime::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .build(),
+    )
+}
+
+fn config_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("llm-proxy-{}-{}.toml", name, std::process::id()))
+}
+
+#[tokio::test]
+async fn reload_activates_valid_config_and_keeps_current_on_error() {
+    let path = config_path("reload");
+    std::fs::write(
+        &path,
+        r#"
+anthropic_beta_whitelist = ["effort-2025-11-24"]
+inference_profile_prefixes = ["us."]
+"#,
+    )
+    .unwrap();
+
+    let source = ConfigSource {
+        path: path.clone(),
+        ..Default::default()
+    };
+    let loader = SettingsLoader::new(source, bedrock_client());
+    let loaded = loader.load().await.unwrap();
+    let state = Arc::new(common::app_state(bedrockruntime_client(), loaded.settings));
+    let original_profiles = state.settings.load().inference_profiles.clone();
+    let mut reloader = SettingsReloader::new(loader, state.clone(), loaded.config);
+
+    std::fs::write(
+        &path,
+        r#"
+anthropic_beta_whitelist = ["effort-2025-11-24", "context-1m-2025-08-07"]
+inference_profile_prefixes = ["us."]
+
+[[model_aliases]]
+pattern = "opus"
+target = "us.anthropic.claude-opus-4-8"
+"#,
+    )
+    .unwrap();
+    assert!(reloader.reload().await);
+    let settings = state.settings.load();
+    assert_eq!(
+        settings.anthropic_beta_whitelist,
+        vec!["effort-2025-11-24", "context-1m-2025-08-07"]
+    );
+    assert_eq!(
+        settings.model_aliases.resolve("opus").as_deref(),
+        Some("us.anthropic.claude-opus-4-8")
+    );
+    assert!(settings.models.get("opus").is_some());
+    // Unchanged prefixes keep the resolver and what it learned.
+    assert!(Arc::ptr_eq(
+        &settings.inference_profiles,
+        &original_profiles
+    ));
+
+    std::fs::write(
+        &path,
+        r#"
+anthropic_beta_whitelist = []
+
+[[api_keys]]
+name = "alice"
+key_hash = "not-a-hash"
+"#,
+    )
+    .unwrap();
+    assert!(!reloader.reload().await);
+    let settings = state.settings.load();
+    assert_eq!(settings.anthropic_beta_whitelist.len(), 2);
+    assert!(!settings.api_keys.is_enabled());
+
+    std::fs::write(&path, "anthropic_beta_whitelist = \"not-a-list\"\n").unwrap();
+    assert!(!reloader.reload().await);
+    assert_eq!(state.settings.load().anthropic_beta_whitelist.len(), 2);
+
+    let _ = std::fs::remove_file(&path);
+}
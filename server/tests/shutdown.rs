@@ -0,0 +1,158 @@
+mod common;
+
+use aws_sdk_bedrockruntime::{Client, config::retry::RetryConfig};
+use aws_smithy_eventstream::frame::write_message_to;
+use aws_smithy_mocks::{RuleMode, mock, mock_client};
+use aws_smithy_runtime_api::http::{Response as HttpResponse, StatusCode as HttpStatusCode};
+use aws_smithy_types::{
+    body::SdkBody,
+    event_stream::{Header, HeaderValue, Message},
+};
+use axum::body::{Body, Bytes};
+use futures::StreamExt;
+use http_body::Frame;
+use http_body_util::{BodyExt, StreamBody};
+use server::{AppState, get_app, settings::Settings};
+use std::{convert::Infallible, sync::Arc, time::Duration};
+use tower::ServiceExt;
+
+/// A `converse_stream` response that starts the message, then stays open
+/// without sending anything more, like a model still thinking.
+fn open_stream() -> HttpResponse {
+    let message_start = Message::new(&br#"{"role":"assistant"}"#[..])
+        .add_header(Header::new(
+            ":message-type",
+            HeaderValue::String("event".into()),
+        ))
+        .add_header(Header::new(
+            ":event-type",
+            HeaderValue::String("messageStart".into()),
+        ))
+        .add_header(Header::new(
+            ":content-type",
+            HeaderValue::String("application/json".into()),
+        ));
+    let mut frame = Vec::new();
+    write_message_to(&message_start, &mut frame).unwrap();
+    let body = StreamBody::new(
+        futures::stream::iter([Ok::<_, Infallible>(Frame::data(Bytes::from(frame)))])
+            .chain(futures::stream::pending()),
+    );
+    let mut response = HttpResponse::new(
+        HttpStatusCode::try_from(200).unwrap(),
+        SdkBody::from_body_1_x(body),
+    );
+    response
+        .headers_mut()
+        .insert("content-type", "application/vnd.amazon.eventstream");
+    response
+}
+
+fn build_state() -> Arc<AppState> {
+    let converse_stream_rule =
+        mock!(aws_sdk_bedrockruntime::Client::converse_stream).then_http_response(open_stream);
+    let client: Client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::MatchAny,
+        [&converse_stream_rule],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    Arc::new(common::app_state(client, Settings::default()))
+}
+
+fn post(uri: &str, body: serde_json::Value) -> axum::http::Request<Body> {
+    axum::http::Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap()
+}
+
+/// Reads the rest of an SSE body, failing if it does not end in time.
+async fn read_to_end(body: Body) -> String {
+    let bytes = tokio::time::timeout(Duration::from_secs(5), body.collect())
+        .await
+        .expect("stream did not end")
+        .unwrap()
+        .to_bytes();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+fn sse_data(body: &str) -> Vec<serde_json::Value> {
+    body.lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .map(|data| serde_json::from_str(data).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn anthropic_stream_open_at_deadline_ends_with_overloaded_error() {
+    let state = build_state();
+    let response = get_app(state.clone())
+        .oneshot(post(
+            "/v1/messages",
+            serde_json::json!({
+                "model": "anthropic.claude-opus-4-8",
+                "max_tokens": 16,
+                "stream": true,
+                "messages": [{"role": "user", "content": "hi"}]
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let request_id = response.headers()["request-id"]
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    // Draining alone leaves the stream running.
+    state.shutdown.drain();
+    let mut body = response.into_body();
+    let first = body.frame().await.unwrap().unwrap().into_data().unwrap();
+    assert!(String::from_utf8_lossy(&first).contains("message_start"));
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), body.frame())
+            .await
+            .is_err()
+    );
+
+    state.shutdown.expire();
+    let rest = read_to_end(body).await;
+    assert!(rest.starts_with("event: error"));
+    let events = sse_data(&rest);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["type"], "error");
+    assert_eq!(events[0]["error"]["type"], "overloaded_error");
+    assert_eq!(events[0]["request_id"], request_id.as_str());
+}
+
+#[tokio::test]
+async fn openai_stream_open_at_deadline_ends_with_server_error() {
+    let state = build_state();
+    let response = get_app(state.clone())
+        .oneshot(post(
+            "/chat/completions",
+            serde_json::json!({
+                "model": "anthropic.claude-opus-4-8",
+                "stream": true,
+                "messages": [{"role": "user", "content": "hi"}]
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let mut body = response.into_body();
+    let first = body.frame().await.unwrap().unwrap().into_data().unwrap();
+    assert!(String::from_utf8_lossy(&first).contains("chat.completion.chunk"));
+
+    state.shutdown.expire();
+    let rest = read_to_end(body).await;
+    assert!(rest.ends_with("data: [DONE]\n\n"));
+    let events = sse_data(rest.trim_end().trim_end_matches("data: [DONE]"));
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["error"]["type"], "server_error");
+    assert_eq!(events[0]["error"]["code"], "overloaded");
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use aws_smithy_mocks::{RuleMode, mock, mock_client};
+use axum::body::Body;
+use opentelemetry::{
+    Value,
+    trace::{SpanId, TraceId, TracerProvider},
+};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use server::{get_app, settings::Settings};
+use std::sync::Arc;
+use tower::ServiceExt;
+use tracing_subscriber::layer::SubscriberExt;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_SPAN_ID: &str = "00f067aa0ba902b7";
+
+fn attribute<'a>(span: &'a SpanData, key: &str) -> Option<&'a Value> {
+    span.attributes
+        .iter()
+        .find(|kv| kv.key.as_str() == key)
+        .map(|kv| &kv.value)
+}
+
+#[tokio::test]
+async fn request_span_continues_incoming_trace_with_gen_ai_attributes() {
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let converse_rule = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .then_output(|| common::converse_output(12, 3));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        [&converse_rule]
+    );
+    let state = Arc::new(common::app_state(client, Settings::default()));
+    let body = serde_json::json!({
+        "model": "us.anthropic.claude-opus-4-8",
+        "max_tokens": 16,
+        "messages": [{"role": "user", "content": "hi"}]
+    });
+    let request = axum::http::Request::builder()
+        .method("POST")
+        .uri("/v1/messages")
+        .header("content-type", "application/json")
+        .header("traceparent", format!("00-{TRACE_ID}-{PARENT_SPAN_ID}-01"))
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap();
+
+    let response = get_app(state).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), 200);
+    drop(response);
+    provider.force_flush().unwrap();
+
+    let spans = exporter.get_finished_spans().unwrap();
+    let request_span = spans
+        .iter()
+        .find(|span| span.name == "POST /v1/messages")
+        .expect("request span");
+    assert_eq!(
+        request_span.span_context.trace_id(),
+        TraceId::from_hex(TRACE_ID).unwrap()
+    );
+    assert_eq!(
+        request_span.parent_span_id,
+        SpanId::from_hex(PARENT_SPAN_ID).unwrap()
+    );
+    assert_eq!(
+        attribute(request_span, "gen_ai.request.model"),
+        Some(&Value::from("us.anthropic.claude-opus-4-8"))
+    );
+    assert_eq!(
+        attribute(request_span, "gen_ai.usage.input_tokens"),
+        Some(&Value::I64(12))
+    );
+    assert_eq!(
+        attribute(request_span, "gen_ai.usage.output_tokens"),
+        Some(&Value::I64(3))
+    );
+
+    let converse_span = spans
+        .iter()
+        .find(|span| span.name == "bedrock.converse")
+        .expect("bedrock.converse span");
+    assert_eq!(
+        converse_span.parent_span_id,
+        request_span.span_context.span_id()
+    );
+}
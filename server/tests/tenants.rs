@@ -0,0 +1,180 @@
+mod common;
+
+use aws_sdk_bedrockruntime::{Client, config::retry::RetryConfig};
+use aws_smithy_mocks::{RuleMode, mock, mock_client};
+use axum::body::Body;
+use chat::inference_profile::InferenceProfileResolver;
+use http_body_util::BodyExt;
+use server::{
+    auth::{ApiKeyConfig, ApiKeys},
+    get_app,
+    settings::Settings,
+    tenants::{DefaultParams, TenantConfig, Tenants},
+};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const SONNET: &str = "anthropic.claude-sonnet-tenant-test";
+const OPUS: &str = "anthropic.claude-opus-tenant-test";
+
+/// `alice` and `carol` belong to the `search` tenant; `bob` to none.
+fn build_app_with_client(client: Client, search: TenantConfig) -> axum::Router {
+    let state = Arc::new(common::app_state(
+        client,
+        Settings {
+            inference_profiles: Arc::new(InferenceProfileResolver::new(vec!["us.".to_string()])),
+            api_keys: ApiKeys::new(vec![
+                ApiKeyConfig {
+                    tenant: Some("search".to_string()),
+                    ..common::api_key("alice")
+                },
+                common::api_key("bob"),
+                ApiKeyConfig {
+                    tenant: Some("search".to_string()),
+                    ..common::api_key("carol")
+                },
+            ])
+            .unwrap(),
+            tenants: Tenants::new(vec![search]).unwrap(),
+            ..Default::default()
+        },
+    ));
+    get_app(state)
+}
+
+fn post(uri: &str, key: &str, model: &str) -> axum::http::Request<Body> {
+    let body = serde_json::json!({
+        "model": model,
+        "max_tokens": 16,
+        "messages": [{"role": "user", "content": "hi"}]
+    });
+    axum::http::Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "application/json")
+        .header("x-api-key", key)
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap()
+}
+
+async fn response_json(response: axum::response::Response) -> serde_json::Value {
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn tenant_settings_apply_to_its_keys_only() {
+    let tenant_sonnet = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(|req| {
+            req.model_id() == Some(&format!("eu.{SONNET}"))
+                && req.inference_config().and_then(|c| c.temperature()) == Some(0.2)
+        })
+        .then_output(|| common::converse_output(30, 7));
+    let global_opus = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(|req| {
+            req.model_id() == Some(&format!("us.{OPUS}"))
+                && req
+                    .inference_config()
+                    .and_then(|c| c.temperature())
+                    .is_none()
+        })
+        .then_output(|| common::converse_output(30, 7));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::MatchAny,
+        [&tenant_sonnet, &global_opus],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let app = build_app_with_client(
+        client,
+        TenantConfig {
+            name: "search".to_string(),
+            allowed_models: vec!["*claude-sonnet*".to_string()],
+            inference_profile_prefixes: Some(vec!["eu.".to_string()]),
+            defaults: DefaultParams {
+                temperature: Some(0.2),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    for uri in ["/v1/messages", "/chat/completions"] {
+        let response = app
+            .clone()
+            .oneshot(post(uri, "sk-alice", SONNET))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200, "{uri}");
+
+        let response = app
+            .clone()
+            .oneshot(post(uri, "sk-alice", OPUS))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403, "{uri}");
+        let json = response_json(response).await;
+        assert_eq!(json["error"]["type"], "permission_error", "{uri}");
+
+        let response = app
+            .clone()
+            .oneshot(post(uri, "sk-bob", OPUS))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200, "{uri}");
+    }
+    assert_eq!(tenant_sonnet.num_calls(), 2);
+    assert_eq!(global_opus.num_calls(), 2);
+
+    let response = app
+        .oneshot(post("/v1/messages/count_tokens", "sk-alice", OPUS))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+}
+
+#[tokio::test]
+async fn tenant_limits_are_shared_by_its_keys() {
+    let sonnet = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .match_requests(|req| req.model_id() == Some(&format!("us.{SONNET}")))
+        .then_output(|| common::converse_output(30, 7));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::MatchAny,
+        [&sonnet],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let app = build_app_with_client(
+        client,
+        TenantConfig {
+            name: "search".to_string(),
+            requests_per_minute: Some(1),
+            ..Default::default()
+        },
+    );
+
+    let response = app
+        .clone()
+        .oneshot(post("/v1/messages", "sk-alice", SONNET))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers()["anthropic-ratelimit-requests-limit"],
+        "1"
+    );
+
+    let response = app
+        .clone()
+        .oneshot(post("/v1/messages", "sk-carol", SONNET))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 429);
+
+    let response = app
+        .oneshot(post("/v1/messages", "sk-bob", SONNET))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(sonnet.num_calls(), 2);
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use aws_sdk_bedrockruntime::{Client, config::retry::RetryConfig};
+use aws_smithy_mocks::{RuleMode, mock, mock_client};
+use aws_smithy_runtime_api::http::{Response as HttpResponse, StatusCode as HttpStatusCode};
+use aws_smithy_types::body::SdkBody;
+use axum::body::Body;
+use http_body_util::BodyExt;
+use server::{get_app, settings::Settings, throttle::ThrottlingConfig};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const MODEL: &str = "us.anthropic.claude-throttle-test";
+
+fn throttled() -> HttpResponse {
+    let mut response = HttpResponse::new(
+        HttpStatusCode::try_from(429).unwrap(),
+        SdkBody::from(r#"{"message":"Too many requests, please wait before trying again."}"#),
+    );
+    response
+        .headers_mut()
+        .insert("x-amzn-errortype", "ThrottlingException");
+    response
+}
+
+fn build_app_with_client(client: Client, throttling: ThrottlingConfig) -> axum::Router {
+    let state = Arc::new(common::app_state(
+        client,
+        Settings {
+            throttling,
+            ..Default::default()
+        },
+    ));
+    get_app(state)
+}
+
+fn post(uri: &str) -> axum::http::Request<Body> {
+    let body = serde_json::json!({
+        "model": MODEL,
+        "max_tokens": 16,
+        "messages": [{"role": "user", "content": "hi"}]
+    });
+    axum::http::Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap()
+}
+
+async fn response_json(response: axum::response::Response) -> serde_json::Value {
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn bedrock_throttling_paces_later_requests_and_sets_retry_after() {
+    let converse_rule = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .sequence()
+        .http_response(throttled)
+        .output(|| common::converse_output(30, 7))
+        .build();
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        [&converse_rule],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let app = build_app_with_client(
+        client,
+        ThrottlingConfig {
+            max_queue_wait_secs: 0,
+            ..Default::default()
+        },
+    );
+
+    // Throttled with no known request rate, the model is paced at half of
+    // one request per second.
+    let response = app.clone().oneshot(post("/v1/messages")).await.unwrap();
+    assert_eq!(response.status(), 429);
+    assert_eq!(response.headers()["retry-after"], "2");
+    let json = response_json(response).await;
+    assert_eq!(json["error"]["type"], "rate_limit_error");
+
+    // The next slot is further away than the queue may wait, so the request
+    // is refused without reaching Bedrock.
+    let response = app
+        .clone()
+        .oneshot(post("/chat/completions"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 429);
+    assert_eq!(response.headers()["retry-after"], "2");
+    let json = response_json(response).await;
+    assert_eq!(json["error"]["code"], "rate_limit_exceeded");
+    assert_eq!(converse_rule.num_calls(), 1);
+}
+
+#[tokio::test]
+async fn disabled_throttling_forwards_every_request() {
+    let converse_rule = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .sequence()
+        .http_response(throttled)
+        .output(|| common::converse_output(30, 7))
+        .build();
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        [&converse_rule],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let app = build_app_with_client(
+        client,
+        ThrottlingConfig {
+            enabled: false,
+            max_queue_wait_secs: 0,
+            ..Default::default()
+        },
+    );
+
+    let response = app.clone().oneshot(post("/v1/messages")).await.unwrap();
+    assert_eq!(response.status(), 429);
+    assert!(!response.headers().contains_key("retry-after"));
+    let response = app.oneshot(post("/v1/messages")).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(converse_rule.num_calls(), 2);
+}
@@ -0,0 +1,257 @@
+mod common;
+
+use arc_swap::ArcSwap;
+use aws_sdk_bedrockruntime::config::{BehaviorVersion, Region};
+use axum::serve::Listener;
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, CertifiedIssuer, DnType, IsCa, KeyPair,
+};
+use rustls::{
+    ClientConfig, RootCertStore,
+    crypto::aws_lc_rs,
+    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName},
+};
+use server::{
+    auth::{ApiKeyConfig, ApiKeys},
+    get_app,
+    settings::Settings,
+    tls::{TlsConfig, TlsConnectInfo, TlsListener, watch_certificates},
+};
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_rustls::TlsConnector;
+
+/// The subject of [`Pki::client_config`]'s certificate as the proxy reads it.
+const CLIENT_SUBJECT: &str = "CN=ci, O=Acme";
+
+/// A throwaway CA plus the files a `[tls]` table points at.
+struct Pki {
+    dir: PathBuf,
+    ca: CertifiedIssuer<'static, KeyPair>,
+}
+
+impl Pki {
+    fn new(name: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("llm-proxy-tls-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "Test CA");
+        let ca = CertifiedIssuer::self_signed(params, KeyPair::generate().unwrap()).unwrap();
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+        let pki = Self { dir, ca };
+        pki.write_server_cert();
+        pki
+    }
+
+    fn issue(&self, params: CertificateParams) -> (Certificate, KeyPair) {
+        let key = KeyPair::generate().unwrap();
+        let cert = params.signed_by(&key, &self.ca).unwrap();
+        (cert, key)
+    }
+
+    /// Writes a new `localhost` certificate and key, returning the former.
+    fn write_server_cert(&self) -> CertificateDer<'static> {
+        let (cert, key) =
+            self.issue(CertificateParams::new(vec!["localhost".to_string()]).unwrap());
+        std::fs::write(self.dir.join("cert.pem"), cert.pem()).unwrap();
+        std::fs::write(self.dir.join("key.pem"), key.serialize_pem()).unwrap();
+        cert.der().clone()
+    }
+
+    fn tls_config(&self, require_client_cert: bool) -> TlsConfig {
+        TlsConfig {
+            cert_path: self.dir.join("cert.pem"),
+            key_path: self.dir.join("key.pem"),
+            client_ca_path: Some(self.dir.join("ca.pem")),
+            require_client_cert,
+        }
+    }
+
+    /// A client trusting the CA, presenting a certificate for
+    /// [`CLIENT_SUBJECT`] if `with_cert`.
+    fn client_config(&self, with_cert: bool) -> Arc<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.ca.der().clone()).unwrap();
+        let builder = ClientConfig::builder_with_provider(Arc::new(aws_lc_rs::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        if !with_cert {
+            return Arc::new(builder.with_no_client_auth());
+        }
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.distinguished_name.push(DnType::CommonName, "ci");
+        params
+            .distinguished_name
+            .push(DnType::OrganizationName, "Acme");
+        let (cert, key) = self.issue(params);
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der()));
+        Arc::new(
+            builder
+                .with_client_auth_cert(vec![cert.der().clone()], key)
+                .unwrap(),
+        )
+    }
+}
+
+impl Drop for Pki {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn bedrockruntime_client() -> aws_sdk_bedrockruntime::Client {
+    aws_sdk_bedrockruntime::Client::from_conf(
+        aws_sdk_bedrockruntime::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .build(),
+    )
+}
+
+/// Serves the app over TLS on an ephemeral port, as `main` does.
+async fn serve(tls: TlsConfig) -> SocketAddr {
+    let state = Arc::new(common::app_state(
+        bedrockruntime_client(),
+        Settings {
+            api_keys: ApiKeys::new(vec![
+                ApiKeyConfig {
+                    key_hash: None,
+                    client_cert_subject: Some(CLIENT_SUBJECT.to_string()),
+                    admin: true,
+                    ..common::api_key("ci")
+                },
+                common::api_key("alice"),
+            ])
+            .unwrap(),
+            ..Default::default()
+        },
+    ));
+    let server_config = Arc::new(ArcSwap::from_pointee(tls.server_config().unwrap()));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let listener = TlsListener::new(listener, server_config.clone()).unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(watch_certificates(tls, server_config));
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            get_app(state).into_make_service_with_connect_info::<TlsConnectInfo>(),
+        )
+        .await
+    });
+    addr
+}
+
+/// Sends `GET path` over a new connection, returning the response status
+/// and the server's certificate.
+async fn get(
+    addr: SocketAddr,
+    client: Arc<ClientConfig>,
+    path: &str,
+    api_key: Option<&str>,
+) -> std::io::Result<(u16, CertificateDer<'static>)> {
+    let tcp = tokio::net::TcpStream::connect(addr).await?;
+    let server_name = ServerName::try_from("localhost").unwrap();
+    let mut stream = TlsConnector::from(client).connect(server_name, tcp).await?;
+    let api_key = api_key
+        .map(|key| format!("x-api-key: {key}\r\n"))
+        .unwrap_or_default();
+    stream
+        .write_all(
+            format!("GET {path} HTTP/1.1\r\nhost: localhost\r\n{api_key}connection: close\r\n\r\n")
+                .as_bytes(),
+        )
+        .await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    let status = response
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| std::io::Error::other(format!("not an HTTP response: {response:?}")))?;
+    let server_cert = stream.get_ref().1.peer_certificates().unwrap()[0].clone();
+    Ok((status, server_cert))
+}
+
+#[tokio::test]
+async fn client_certificate_subject_maps_to_principal() {
+    let pki = Pki::new("mtls");
+    let addr = serve(pki.tls_config(false)).await;
+
+    // The certificate alone identifies `ci`, an admin.
+    let (status, _) = get(addr, pki.client_config(true), "/admin/usage", None)
+        .await
+        .unwrap();
+    assert_eq!(status, 200);
+
+    // Without one, an API key is still required and accepted.
+    let (status, _) = get(addr, pki.client_config(false), "/v1/models", None)
+        .await
+        .unwrap();
+    assert_eq!(status, 401);
+    let (status, _) = get(
+        addr,
+        pki.client_config(false),
+        "/v1/models",
+        Some("sk-alice"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(status, 200);
+    let (status, _) = get(
+        addr,
+        pki.client_config(false),
+        "/admin/usage",
+        Some("sk-alice"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(status, 403);
+}
+
+#[tokio::test]
+async fn required_client_certificate_rejects_clients_without_one() {
+    let pki = Pki::new("required");
+    let addr = serve(pki.tls_config(true)).await;
+
+    assert!(
+        get(
+            addr,
+            pki.client_config(false),
+            "/v1/models",
+            Some("sk-alice")
+        )
+        .await
+        .is_err()
+    );
+    let (status, _) = get(addr, pki.client_config(true), "/v1/models", None)
+        .await
+        .unwrap();
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn certificate_is_reloaded_when_files_change() {
+    let pki = Pki::new("reload");
+    let addr = serve(pki.tls_config(false)).await;
+    let (_, original) = get(addr, pki.client_config(true), "/v1/models", None)
+        .await
+        .unwrap();
+
+    let renewed = pki.write_server_cert();
+    assert_ne!(renewed, original);
+    for _ in 0..50 {
+        let (_, served) = get(addr, pki.client_config(true), "/v1/models", None)
+            .await
+            .unwrap();
+        if served == renewed {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    panic!("the renewed certificate was not served");
+}
@@ -0,0 +1,219 @@
+mod common;
+
+use aws_sdk_bedrockruntime::{Client, config::retry::RetryConfig};
+use aws_smithy_mocks::{RuleMode, mock, mock_client};
+use aws_smithy_runtime_api::http::{Response as HttpResponse, StatusCode as HttpStatusCode};
+use aws_smithy_types::body::SdkBody;
+use axum::{body::Body, http::HeaderValue};
+use http_body_util::BodyExt;
+use server::{
+    auth::{ApiKeyConfig, ApiKeys},
+    get_app,
+    ledger::{ModelPrice, PriceTable},
+    rate_limit::RateLimits,
+    settings::Settings,
+};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const MODEL: &str = "us.anthropic.claude-opus-4-8";
+
+fn throttled() -> HttpResponse {
+    let mut response = HttpResponse::new(
+        HttpStatusCode::try_from(429).unwrap(),
+        SdkBody::from(r#"{"message":"Too many requests, please wait before trying again."}"#),
+    );
+    response
+        .headers_mut()
+        .insert("x-amzn-errortype", "ThrottlingException");
+    response
+}
+
+fn build_app_with_client(client: Client) -> axum::Router {
+    let ci = ApiKeyConfig {
+        limits: RateLimits {
+            requests_per_minute: Some(1),
+            ..Default::default()
+        },
+        ..common::api_key("ci")
+    };
+    let ops = ApiKeyConfig {
+        admin: true,
+        ..common::api_key("ops")
+    };
+    let state = Arc::new(common::app_state(
+        client,
+        Settings {
+            api_keys: ApiKeys::new(vec![common::api_key("alice"), ci, ops]).unwrap(),
+            prices: PriceTable::new(vec![ModelPrice {
+                model: "*claude-opus*".to_string(),
+                input: 5.0,
+                output: 25.0,
+                ..Default::default()
+            }])
+            .unwrap(),
+            ..Default::default()
+        },
+    ));
+    get_app(state)
+}
+
+fn post(uri: &str) -> axum::http::Request<Body> {
+    let body = serde_json::json!({
+        "model": MODEL,
+        "max_tokens": 16,
+        "messages": [{"role": "user", "content": "hi"}]
+    });
+    axum::http::Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "application/json")
+        .header("x-api-key", "sk-alice")
+        .body(Body::from(serde_json::to_vec(&body).unwrap()))
+        .unwrap()
+}
+
+fn get_usage(query: &str, key: &str) -> axum::http::Request<Body> {
+    axum::http::Request::builder()
+        .uri(format!("/admin/usage{query}"))
+        .header("x-api-key", key)
+        .body(Body::empty())
+        .unwrap()
+}
+
+async fn response_json(response: axum::response::Response) -> serde_json::Value {
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn completed_and_failed_requests_are_recorded_and_priced() {
+    let ok = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .then_output(|| common::converse_output(1000, 200));
+    let ok_again = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .then_output(|| common::converse_output(1000, 200));
+    let throttled = mock!(aws_sdk_bedrockruntime::Client::converse).then_http_response(throttled);
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        [&ok, &ok_again, &throttled],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let app = build_app_with_client(client);
+
+    let response = app.clone().oneshot(post("/v1/messages")).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let response = app
+        .clone()
+        .oneshot(post("/chat/completions"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let response = app.clone().oneshot(post("/v1/messages")).await.unwrap();
+    assert_eq!(response.status(), 429);
+
+    let json = common::usage_after(&app, "?group_by=key,model", 3).await;
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "data": [{
+                "key": "alice",
+                "model": MODEL,
+                "requests": 3,
+                "failed_requests": 1,
+                "rejected_requests": 0,
+                "input_tokens": 2000,
+                "output_tokens": 400,
+                "cache_read_input_tokens": 0,
+                "cache_write_input_tokens": 0,
+                "cost_usd": 2.0 * (1000.0 * 5.0 + 200.0 * 25.0) / 1e6,
+            }]
+        })
+    );
+
+    let json = common::usage_after(&app, "?bucket=day", 3).await;
+    let row = &json["data"][0];
+    assert!(row.get("key").is_none());
+    assert!(
+        row["bucket_start"]
+            .as_str()
+            .unwrap()
+            .ends_with("T00:00:00Z")
+    );
+}
+
+#[tokio::test]
+async fn rate_limited_requests_are_recorded_as_rejected() {
+    let ok = mock!(aws_sdk_bedrockruntime::Client::converse)
+        .then_output(|| common::converse_output(1000, 200));
+    let client = mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        [&ok],
+        |builder| builder.retry_config(RetryConfig::disabled())
+    );
+    let app = build_app_with_client(client);
+    let post_as_ci = || {
+        let mut request = post("/v1/messages");
+        request
+            .headers_mut()
+            .insert("x-api-key", HeaderValue::from_static("sk-ci"));
+        request
+    };
+
+    let response = app.clone().oneshot(post_as_ci()).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let response = app.clone().oneshot(post_as_ci()).await.unwrap();
+    assert_eq!(response.status(), 429);
+    assert_eq!(ok.num_calls(), 1);
+    // A refused request is refused whatever its body, and without a model
+    // to record it against when the body is malformed.
+    let mut malformed = post_as_ci();
+    *malformed.body_mut() = Body::from("{");
+    let response = app.clone().oneshot(malformed).await.unwrap();
+    assert_eq!(response.status(), 429);
+
+    let json = common::usage_after(&app, "?key=ci&group_by=model", 3).await;
+    let row = |model: &str| {
+        json["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|row| row["model"] == model)
+            .unwrap()
+            .clone()
+    };
+    assert_eq!(row(MODEL)["requests"], 2);
+    assert_eq!(row(MODEL)["failed_requests"], 1);
+    assert_eq!(row(MODEL)["rejected_requests"], 1);
+    assert_eq!(row("")["requests"], 1);
+    assert_eq!(row("")["failed_requests"], 1);
+    assert_eq!(row("")["rejected_requests"], 1);
+    assert_eq!(row("")["cost_usd"], 0.0);
+}
+
+#[tokio::test]
+async fn usage_requires_admin_key() {
+    let app = build_app_with_client(mock_client!(
+        aws_sdk_bedrockruntime,
+        RuleMode::Sequential,
+        []
+    ));
+
+    let response = app
+        .clone()
+        .oneshot(get_usage("", "sk-alice"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+    let json = response_json(response).await;
+    assert_eq!(json["error"]["type"], "permission_error");
+
+    let response = app
+        .oneshot(get_usage("?group_by=region", "sk-ops"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let json = response_json(response).await;
+    assert_eq!(json["error"]["type"], "invalid_request_error");
+}